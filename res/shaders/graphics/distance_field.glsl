@@ -0,0 +1,53 @@
+// Distance field text, see TextEffects
+// Included by the sprite shaders, u_Texture, uv and color have to be declared before it
+uniform int u_distance_field;
+uniform float u_spread;
+uniform float u_outline_width;
+uniform vec4 u_outline_color;
+uniform float u_glow_width;
+uniform vec4 u_glow_color;
+uniform vec2 u_shadow_offset;
+uniform float u_shadow_softness;
+uniform vec4 u_shadow_color;
+
+float median(float r, float g, float b) {
+    return max(min(r, g), min(max(r, g), b));
+}
+
+// Distance to the outline in texels, positive inside
+float distance_at(vec2 position) {
+    vec4 texel = texture(u_Texture, position);
+    float field = u_distance_field == 2 ? median(texel.r, texel.g, texel.b) : texel.a;
+    return (field - 0.5) * 2.0 * u_spread;
+}
+
+vec4 over(vec4 top, vec4 bottom) {
+    float a = top.a + bottom.a * (1.0 - top.a);
+    if (a == 0.0) {
+        return vec4(0.0);
+    }
+    return vec4((top.rgb * top.a + bottom.rgb * bottom.a * (1.0 - top.a)) / a, a);
+}
+
+vec4 distance_field_color() {
+    float distance = distance_at(uv);
+    // How many texels one screen pixel covers, keeps edges one pixel soft at any scale
+    float pixel = max(fwidth(distance), 0.0001);
+
+    vec4 result = vec4(color.rgb, color.a * clamp(distance / pixel + 0.5, 0.0, 1.0));
+    if (u_outline_width > 0.0) {
+        float outline = clamp((distance + u_outline_width) / pixel + 0.5, 0.0, 1.0);
+        result = over(result, vec4(u_outline_color.rgb, u_outline_color.a * outline));
+    }
+    if (u_glow_width > 0.0) {
+        float glow = clamp(1.0 + (distance + u_outline_width) / u_glow_width, 0.0, 1.0);
+        result = over(result, vec4(u_glow_color.rgb, u_glow_color.a * glow * glow));
+    }
+    if (u_shadow_color.a > 0.0) {
+        vec2 offset = u_shadow_offset / vec2(textureSize(u_Texture, 0));
+        float softness = max(u_shadow_softness, pixel);
+        float shadow = clamp((distance_at(uv - offset) + u_outline_width) / softness + 0.5, 0.0, 1.0);
+        result = over(result, vec4(u_shadow_color.rgb, u_shadow_color.a * shadow));
+    }
+    return result;
+}
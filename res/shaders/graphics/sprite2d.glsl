@@ -86,7 +86,13 @@ layout(location = 0) out vec4 out_color;
 
 uniform sampler2D u_Texture;
 
+#include "distance_field.glsl"
+
 void main() {
+    if (u_distance_field != 0) {
+        out_color = distance_field_color();
+        return;
+    }
     vec4 texColor = texture(u_Texture, uv);
 	out_color = texColor * color;
 }
\ No newline at end of file
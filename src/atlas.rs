@@ -0,0 +1,589 @@
+/*
+ *   Copyright (c) 2021 Ludwig Bogsveen
+ *   All rights reserved.
+
+ *   Permission is hereby granted, free of charge, to any person obtaining a copy
+ *   of this software and associated documentation files (the "Software"), to deal
+ *   in the Software without restriction, including without limitation the rights
+ *   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *   copies of the Software, and to permit persons to whom the Software is
+ *   furnished to do so, subject to the following conditions:
+
+ *   The above copyright notice and this permission notice shall be included in all
+ *   copies or substantial portions of the Software.
+
+ *   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *   SOFTWARE.
+ */
+
+use std::path::Path;
+
+use json::JsonValue;
+
+use crate::texture::{ImageError, ImagePack};
+
+#[derive(Debug)]
+pub enum AtlasError {
+    Io(std::io::Error),
+    Image(ImageError),
+    Json(json::Error),
+    UnknownFormat,
+    InvalidDescriptor(String),
+}
+
+impl From<std::io::Error> for AtlasError {
+    fn from(error: std::io::Error) -> Self { Self::Io(error) }
+}
+
+impl From<ImageError> for AtlasError {
+    fn from(error: ImageError) -> Self { Self::Image(error) }
+}
+
+impl From<json::Error> for AtlasError {
+    fn from(error: json::Error) -> Self { Self::Json(error) }
+}
+
+impl std::fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "io error: {}", error),
+            Self::Image(error) => write!(f, "image error: {}", error),
+            Self::Json(error) => write!(f, "json error: {}", error),
+            Self::UnknownFormat => write!(f, "unknown atlas format"),
+            Self::InvalidDescriptor(reason) => write!(f, "invalid atlas descriptor: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for AtlasError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::Image(error) => Some(error),
+            Self::Json(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AtlasPage {
+    pub file: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+// Coordinates use the same lower left origin as Image and TextureRegion, no matter which tool made the atlas.
+// width and height is the size stored in the page, rotation is how many degrees clockwise it is stored rotated.
+// offset is how much transparent border was trimmed away from the left and bottom of the source image.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AtlasRegion {
+    pub name: String,
+    pub page: usize,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub rotation: u32,
+    pub offset_x: u32,
+    pub offset_y: u32,
+    pub source_width: u32,
+    pub source_height: u32,
+    pub pivot: (f32, f32),
+}
+
+impl AtlasRegion {
+    pub fn new(name: &str, page: usize, x: u32, y: u32, width: u32, height: u32) -> AtlasRegion {
+        AtlasRegion {
+            name: name.to_string(),
+            page,
+            x,
+            y,
+            width,
+            height,
+            rotation: 0,
+            offset_x: 0,
+            offset_y: 0,
+            source_width: width,
+            source_height: height,
+            pivot: (0.5, 0.5),
+        }
+    }
+}
+
+// frames holds region names and how long each one is shown in milliseconds
+#[derive(Clone, Debug, PartialEq)]
+pub struct AtlasAnimation {
+    pub name: String,
+    pub frames: std::vec::Vec::<(String, u32)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AtlasDescriptor {
+    pub pages: std::vec::Vec::<AtlasPage>,
+    pub regions: std::vec::Vec::<AtlasRegion>,
+    pub animations: std::vec::Vec::<AtlasAnimation>,
+}
+
+impl Default for AtlasDescriptor {
+    fn default() -> Self { AtlasDescriptor::new() }
+}
+
+// Detects if the text is our own format, TexturePacker JSON, an Aseprite export or a libGDX atlas
+impl std::str::FromStr for AtlasDescriptor {
+    type Err = AtlasError;
+
+    fn from_str(text: &str) -> Result<AtlasDescriptor, AtlasError> {
+        let trimmed = text.trim_start();
+        if !trimmed.starts_with('{') {
+            return AtlasDescriptor::from_libgdx_atlas(text);
+        }
+
+        let root = json::parse(text)?;
+        if root.has_key("regions") {
+            AtlasDescriptor::from_json_value(&root)
+        } else if root.has_key("frames") {
+            let app = root["meta"]["app"].as_str().unwrap_or("");
+            if app.contains("aseprite") {
+                AtlasDescriptor::from_aseprite_value(&root)
+            } else {
+                AtlasDescriptor::from_texture_packer_value(&root)
+            }
+        } else {
+            Err(AtlasError::UnknownFormat)
+        }
+    }
+}
+
+impl AtlasDescriptor {
+    pub fn new() -> AtlasDescriptor {
+        AtlasDescriptor {
+            pages: std::vec::Vec::new(),
+            regions: std::vec::Vec::new(),
+            animations: std::vec::Vec::new(),
+        }
+    }
+
+    // Page i is expected to be saved as "{page_file_stem}_{i}.png"
+    pub fn from_image_pack(image_pack: &ImagePack, page_file_stem: &str) -> AtlasDescriptor {
+        let mut descriptor = AtlasDescriptor::new();
+
+        for i in 0..image_pack.page_count() {
+            let page = image_pack.get_page(i);
+            descriptor.pages.push(AtlasPage {
+                file: format!("{}_{}.png", page_file_stem, i),
+                width: page.width(),
+                height: page.height(),
+            });
+        }
+
+        let mut names: std::vec::Vec::<&String> = image_pack.images().keys().collect();
+        names.sort();
+
+        for name in names {
+            let packed = image_pack.get(name).unwrap();
+            descriptor.regions.push(AtlasRegion {
+                name: name.to_string(),
+                page: packed.page,
+                x: packed.x,
+                y: packed.y,
+                width: packed.width,
+                height: packed.height,
+                rotation: if packed.rotated { 90 } else { 0 },
+                offset_x: packed.offset_x,
+                offset_y: packed.offset_y,
+                source_width: packed.source_width,
+                source_height: packed.source_height,
+                pivot: (0.5, 0.5),
+            });
+        }
+
+        descriptor
+    }
+
+    pub fn region(&self, name: &str) -> Option<&AtlasRegion> {
+        self.regions.iter().find(|region| region.name == name)
+    }
+
+    pub fn from_file(path: &str) -> Result<AtlasDescriptor, AtlasError> {
+        let text = std::fs::read_to_string(path)?;
+        text.parse()
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), AtlasError> {
+        std::fs::write(path, self.to_json())?;
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut pages = JsonValue::new_array();
+        for page in &self.pages {
+            pages.push(json::object!{
+                "file" => page.file.as_str(),
+                "width" => page.width,
+                "height" => page.height,
+            }).unwrap();
+        }
+
+        let mut regions = JsonValue::new_array();
+        for region in &self.regions {
+            regions.push(json::object!{
+                "name" => region.name.as_str(),
+                "page" => region.page,
+                "x" => region.x,
+                "y" => region.y,
+                "width" => region.width,
+                "height" => region.height,
+                "rotation" => region.rotation,
+                "offset_x" => region.offset_x,
+                "offset_y" => region.offset_y,
+                "source_width" => region.source_width,
+                "source_height" => region.source_height,
+                "pivot_x" => region.pivot.0,
+                "pivot_y" => region.pivot.1,
+            }).unwrap();
+        }
+
+        let mut animations = JsonValue::new_array();
+        for animation in &self.animations {
+            let mut frames = JsonValue::new_array();
+            for (region, duration) in &animation.frames {
+                frames.push(json::object!{
+                    "region" => region.as_str(),
+                    "duration" => *duration,
+                }).unwrap();
+            }
+            animations.push(json::object!{
+                "name" => animation.name.as_str(),
+                "frames" => frames,
+            }).unwrap();
+        }
+
+        json::object!{
+            "pages" => pages,
+            "regions" => regions,
+            "animations" => animations,
+        }.pretty(4)
+    }
+
+    pub fn from_json(text: &str) -> Result<AtlasDescriptor, AtlasError> {
+        AtlasDescriptor::from_json_value(&json::parse(text)?)
+    }
+
+    fn from_json_value(root: &JsonValue) -> Result<AtlasDescriptor, AtlasError> {
+        let mut descriptor = AtlasDescriptor::new();
+
+        for page in root["pages"].members() {
+            descriptor.pages.push(AtlasPage {
+                file: field_str(page, "file")?.to_string(),
+                width: field_u32(page, "width")?,
+                height: field_u32(page, "height")?,
+            });
+        }
+
+        for region in root["regions"].members() {
+            let width = field_u32(region, "width")?;
+            let height = field_u32(region, "height")?;
+            descriptor.regions.push(AtlasRegion {
+                name: field_str(region, "name")?.to_string(),
+                page: region["page"].as_usize().unwrap_or(0),
+                x: field_u32(region, "x")?,
+                y: field_u32(region, "y")?,
+                width,
+                height,
+                rotation: region["rotation"].as_u32().unwrap_or(0),
+                offset_x: region["offset_x"].as_u32().unwrap_or(0),
+                offset_y: region["offset_y"].as_u32().unwrap_or(0),
+                source_width: region["source_width"].as_u32().unwrap_or(width),
+                source_height: region["source_height"].as_u32().unwrap_or(height),
+                pivot: (region["pivot_x"].as_f32().unwrap_or(0.5), region["pivot_y"].as_f32().unwrap_or(0.5)),
+            });
+        }
+
+        for animation in root["animations"].members() {
+            let mut frames = std::vec::Vec::new();
+            for frame in animation["frames"].members() {
+                frames.push((field_str(frame, "region")?.to_string(), frame["duration"].as_u32().unwrap_or(0)));
+            }
+            descriptor.animations.push(AtlasAnimation {
+                name: field_str(animation, "name")?.to_string(),
+                frames,
+            });
+        }
+
+        Ok(descriptor)
+    }
+
+    // Both the "hash" and "array" flavours of TexturePacker's JSON export
+    pub fn from_texture_packer_json(text: &str) -> Result<AtlasDescriptor, AtlasError> {
+        AtlasDescriptor::from_texture_packer_value(&json::parse(text)?)
+    }
+
+    fn from_texture_packer_value(root: &JsonValue) -> Result<AtlasDescriptor, AtlasError> {
+        let (descriptor, _) = parse_frames(root)?;
+        Ok(descriptor)
+    }
+
+    // Aseprite's sprite sheet export, frame tags become animations
+    pub fn from_aseprite_json(text: &str) -> Result<AtlasDescriptor, AtlasError> {
+        AtlasDescriptor::from_aseprite_value(&json::parse(text)?)
+    }
+
+    fn from_aseprite_value(root: &JsonValue) -> Result<AtlasDescriptor, AtlasError> {
+        let (mut descriptor, durations) = parse_frames(root)?;
+
+        for tag in root["meta"]["frameTags"].members() {
+            let from = field_u32(tag, "from")? as usize;
+            let to = field_u32(tag, "to")? as usize;
+            if from > to || to >= descriptor.regions.len() {
+                return Err(AtlasError::InvalidDescriptor(format!("frame tag range {}..{} is out of bounds", from, to)));
+            }
+
+            let forward: std::vec::Vec::<usize> = (from..=to).collect();
+            let order: std::vec::Vec::<usize> = match tag["direction"].as_str().unwrap_or("forward") {
+                "reverse" => forward.iter().rev().cloned().collect(),
+                "pingpong" => forward.iter().cloned().chain(forward.iter().rev().skip(1).take(forward.len().saturating_sub(2)).cloned()).collect(),
+                _ => forward,
+            };
+
+            descriptor.animations.push(AtlasAnimation {
+                name: field_str(tag, "name")?.to_string(),
+                frames: order.iter().map(|&i| (descriptor.regions[i].name.clone(), durations[i])).collect(),
+            });
+        }
+
+        Ok(descriptor)
+    }
+
+    // Both the legacy (xy/size/orig/offset) and the newer (bounds/offsets) libGDX .atlas layouts
+    pub fn from_libgdx_atlas(text: &str) -> Result<AtlasDescriptor, AtlasError> {
+        let mut descriptor = AtlasDescriptor::new();
+        let mut region: Option<LibGdxRegion> = None;
+        let mut expect_page = true;
+
+        for line in text.lines() {
+            let line = line.trim_end();
+            if line.trim().is_empty() {
+                finish_libgdx_region(&mut descriptor, region.take())?;
+                expect_page = true;
+                continue;
+            }
+
+            if expect_page {
+                descriptor.pages.push(AtlasPage { file: line.trim().to_string(), width: 0, height: 0 });
+                expect_page = false;
+                continue;
+            }
+
+            match line.find(':') {
+                Some(colon) => {
+                    let key = line[..colon].trim();
+                    let values: std::vec::Vec::<&str> = line[colon + 1..].split(',').map(|value| value.trim()).collect();
+
+                    match region.as_mut() {
+                        Some(region) => region.set(key, &values)?,
+                        None => {
+                            if key == "size" {
+                                let page = descriptor.pages.last_mut().unwrap();
+                                page.width = parse_u32(&values, 0)?;
+                                page.height = parse_u32(&values, 1)?;
+                            }
+                        },
+                    }
+                },
+                None => {
+                    finish_libgdx_region(&mut descriptor, region.take())?;
+                    region = Some(LibGdxRegion::new(line.trim(), descriptor.pages.len() - 1));
+                },
+            }
+        }
+        finish_libgdx_region(&mut descriptor, region.take())?;
+
+        if descriptor.pages.is_empty() {
+            return Err(AtlasError::UnknownFormat);
+        }
+        Ok(descriptor)
+    }
+}
+
+fn field_u32(value: &JsonValue, key: &str) -> Result<u32, AtlasError> {
+    value[key].as_u32().ok_or_else(|| AtlasError::InvalidDescriptor(format!("missing number \"{}\"", key)))
+}
+
+fn field_str<'a>(value: &'a JsonValue, key: &str) -> Result<&'a str, AtlasError> {
+    value[key].as_str().ok_or_else(|| AtlasError::InvalidDescriptor(format!("missing string \"{}\"", key)))
+}
+
+// TexturePacker and Aseprite share the frame layout, returns the regions and every frame's duration
+fn parse_frames(root: &JsonValue) -> Result<(AtlasDescriptor, std::vec::Vec::<u32>), AtlasError> {
+    let meta = &root["meta"];
+    let page_width = field_u32(&meta["size"], "w")?;
+    let page_height = field_u32(&meta["size"], "h")?;
+
+    let mut descriptor = AtlasDescriptor::new();
+    descriptor.pages.push(AtlasPage {
+        file: meta["image"].as_str().unwrap_or("").to_string(),
+        width: page_width,
+        height: page_height,
+    });
+
+    let frames: std::vec::Vec::<(String, &JsonValue)> = if root["frames"].is_object() {
+        root["frames"].entries().map(|(name, frame)| (name.to_string(), frame)).collect()
+    } else {
+        let mut frames = std::vec::Vec::new();
+        for frame in root["frames"].members() {
+            frames.push((field_str(frame, "filename")?.to_string(), frame));
+        }
+        frames
+    };
+
+    let mut durations = std::vec::Vec::new();
+
+    for (name, frame) in frames {
+        let rect = &frame["frame"];
+        let (x, y, w, h) = (field_u32(rect, "x")?, field_u32(rect, "y")?, field_u32(rect, "w")?, field_u32(rect, "h")?);
+        let rotated = frame["rotated"].as_bool().unwrap_or(false);
+
+        // frame holds the unrotated size, rotated frames take up h by w pixels in the page
+        let (width, height) = if rotated { (h, w) } else { (w, h) };
+        if x.checked_add(width).is_none_or(|right| right > page_width) || y.checked_add(height).is_none_or(|top| top > page_height) {
+            return Err(AtlasError::InvalidDescriptor(format!("frame \"{}\" is outside of the page", name)));
+        }
+
+        let source_width = frame["sourceSize"]["w"].as_u32().unwrap_or(w);
+        let source_height = frame["sourceSize"]["h"].as_u32().unwrap_or(h);
+        let trim = &frame["spriteSourceSize"];
+        let trim_y = trim["y"].as_u32().unwrap_or(0);
+        let trim_h = trim["h"].as_u32().unwrap_or(h);
+
+        let pivot = &frame["pivot"];
+
+        descriptor.regions.push(AtlasRegion {
+            name,
+            page: 0,
+            x,
+            y: page_height - y - height,
+            width,
+            height,
+            rotation: if rotated { 90 } else { 0 },
+            offset_x: trim["x"].as_u32().unwrap_or(0),
+            offset_y: source_height.saturating_sub(trim_y.saturating_add(trim_h)),
+            source_width,
+            source_height,
+            pivot: (pivot["x"].as_f32().unwrap_or(0.5), 1.0 - pivot["y"].as_f32().unwrap_or(0.5)),
+        });
+        durations.push(frame["duration"].as_u32().unwrap_or(0));
+    }
+
+    Ok((descriptor, durations))
+}
+
+fn parse_u32(values: &[&str], index: usize) -> Result<u32, AtlasError> {
+    values.get(index).and_then(|value| value.parse().ok()).ok_or_else(|| AtlasError::InvalidDescriptor(format!("expected a number in {:?}", values)))
+}
+
+fn parse_i32(values: &[&str], index: usize) -> Result<i32, AtlasError> {
+    values.get(index).and_then(|value| value.parse().ok()).ok_or_else(|| AtlasError::InvalidDescriptor(format!("expected a number in {:?}", values)))
+}
+
+struct LibGdxRegion {
+    name: String,
+    page: usize,
+    bounds: (u32, u32, u32, u32),
+    original: Option<(u32, u32)>,
+    offset: (u32, u32),
+    degrees: u32,
+    index: i32,
+}
+
+impl LibGdxRegion {
+    fn new(name: &str, page: usize) -> LibGdxRegion {
+        LibGdxRegion {
+            name: name.to_string(),
+            page,
+            bounds: (0, 0, 0, 0),
+            original: None,
+            offset: (0, 0),
+            degrees: 0,
+            index: -1,
+        }
+    }
+
+    fn set(&mut self, key: &str, values: &[&str]) -> Result<(), AtlasError> {
+        match key {
+            "xy" => {
+                self.bounds.0 = parse_u32(values, 0)?;
+                self.bounds.1 = parse_u32(values, 1)?;
+            },
+            "size" => {
+                self.bounds.2 = parse_u32(values, 0)?;
+                self.bounds.3 = parse_u32(values, 1)?;
+            },
+            "bounds" => self.bounds = (parse_u32(values, 0)?, parse_u32(values, 1)?, parse_u32(values, 2)?, parse_u32(values, 3)?),
+            "orig" => self.original = Some((parse_u32(values, 0)?, parse_u32(values, 1)?)),
+            "offset" => self.offset = (parse_u32(values, 0)?, parse_u32(values, 1)?),
+            "offsets" => {
+                self.offset = (parse_u32(values, 0)?, parse_u32(values, 1)?);
+                self.original = Some((parse_u32(values, 2)?, parse_u32(values, 3)?));
+            },
+            "rotate" => self.degrees = match values.first() {
+                Some(&"true") => 90,
+                Some(&"false") => 0,
+                _ => parse_u32(values, 0)?,
+            },
+            "index" => self.index = parse_i32(values, 0)?,
+            _ => {},
+        }
+        Ok(())
+    }
+}
+
+fn finish_libgdx_region(descriptor: &mut AtlasDescriptor, region: Option<LibGdxRegion>) -> Result<(), AtlasError> {
+    let region = match region {
+        Some(region) => region,
+        None => return Ok(()),
+    };
+
+    let page = &descriptor.pages[region.page];
+    if page.height == 0 {
+        return Err(AtlasError::InvalidDescriptor(format!("page \"{}\" has no size", page.file)));
+    }
+
+    // libGDX stores the unrotated size and rotates counter clockwise
+    let (x, y, w, h) = region.bounds;
+    let (width, height) = if region.degrees % 180 == 90 { (h, w) } else { (w, h) };
+    if x.checked_add(width).is_none_or(|right| right > page.width) || y.checked_add(height).is_none_or(|top| top > page.height) {
+        return Err(AtlasError::InvalidDescriptor(format!("region \"{}\" is outside of the page", region.name)));
+    }
+
+    let (source_width, source_height) = region.original.unwrap_or((w, h));
+    let name = if region.index >= 0 { format!("{}_{}", region.name, region.index) } else { region.name };
+
+    descriptor.regions.push(AtlasRegion {
+        name,
+        page: region.page,
+        x,
+        y: page.height - y - height,
+        width,
+        height,
+        rotation: (360 - region.degrees % 360) % 360,
+        offset_x: region.offset.0,
+        offset_y: region.offset.1,
+        source_width,
+        source_height,
+        pivot: (0.5, 0.5),
+    });
+    Ok(())
+}
+
+pub(crate) fn page_path(descriptor_path: &str, page_file: &str) -> std::path::PathBuf {
+    match Path::new(descriptor_path).parent() {
+        Some(directory) => directory.join(page_file),
+        None => Path::new(page_file).to_path_buf(),
+    }
+}
@@ -0,0 +1,311 @@
+/*
+ *   Copyright (c) 2021 Ludwig Bogsveen
+ *   All rights reserved.
+
+ *   Permission is hereby granted, free of charge, to any person obtaining a copy
+ *   of this software and associated documentation files (the "Software"), to deal
+ *   in the Software without restriction, including without limitation the rights
+ *   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *   copies of the Software, and to permit persons to whom the Software is
+ *   furnished to do so, subject to the following conditions:
+
+ *   The above copyright notice and this permission notice shall be included in all
+ *   copies or substantial portions of the Software.
+
+ *   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *   SOFTWARE.
+ */
+
+use std::path::{Path, PathBuf};
+
+use engine_renderer::texture::{Image, ImagePack, ImagePackSettings};
+
+const USAGE: &str = "\
+usage: engine_renderer-pack <input directory> <output descriptor> [options]
+
+Packs every image in the input directory (recursively) into atlas pages written next to the
+descriptor as <descriptor name>_<page>.png. Regions are named after their path relative to the
+input directory without the extension, e.g. \"player/walk_0\".
+
+options:
+    --padding <pixels>      empty pixels between images (default 2)
+    --extrude <pixels>      repeat edge pixels outwards to stop bleeding (default 0)
+    --max-size <w>x<h>      maximum page size (default 4096x4096)
+    --max-pages <count>     fail if more pages than this are needed
+    --rotate                allow rotating images by 90 degrees
+    --trim                  trim transparent borders
+    --incremental           skip packing if neither inputs nor options changed since the last run
+";
+
+const IMAGE_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "bmp", "tga", "qoi"];
+
+struct Options {
+    input: PathBuf,
+    output: PathBuf,
+    settings: ImagePackSettings,
+    max_pages: Option<usize>,
+    incremental: bool,
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} expects a value", flag))?;
+    value.parse().map_err(|_| format!("{} expects a number, got \"{}\"", flag, value))
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut settings = ImagePackSettings::new();
+    settings.padding = 2;
+
+    let mut positional = Vec::new();
+    let mut max_pages = None;
+    let mut incremental = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--padding" => settings.padding = parse_number(&arg, args.next())?,
+            "--extrude" => settings.extrude = parse_number(&arg, args.next())?,
+            "--max-pages" => max_pages = Some(parse_number(&arg, args.next())?),
+            "--max-size" => {
+                let value = args.next().ok_or("--max-size expects a value")?;
+                let mut parts = value.split('x');
+                settings.max_width = parse_number("--max-size", parts.next().map(str::to_string))?;
+                settings.max_height = parse_number("--max-size", parts.next().map(str::to_string))?;
+            },
+            "--rotate" => settings.allow_rotation = true,
+            "--trim" => settings.trim = true,
+            "--incremental" => incremental = true,
+            "-h" | "--help" => return Err(String::new()),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+
+    if positional.len() != 2 {
+        return Err("expected an input directory and an output descriptor".to_string());
+    }
+    let output = positional.pop().unwrap();
+    let input = positional.pop().unwrap();
+
+    Ok(Options { input, output, settings, max_pages, incremental })
+}
+
+fn collect_images(directory: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_images(&path, files)?;
+        } else {
+            let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("").to_lowercase();
+            if IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+                files.push(path);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn region_name(input: &Path, file: &Path) -> String {
+    let relative = file.strip_prefix(input).unwrap_or(file).with_extension("");
+    relative.components().map(|component| component.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+// 64 bit FNV-1a, unlike DefaultHasher the result is the same on every platform and Rust version
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Fnv1a { Fnv1a(0xcbf2_9ce4_8422_2325) }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    // Length prefixed so that ("ab", "c") and ("a", "bc") hash differently
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u64(bytes.len() as u64);
+        self.write(bytes);
+    }
+
+    fn write_u64(&mut self, value: u64) { self.write(&value.to_le_bytes()); }
+
+    fn finish(&self) -> u64 { self.0 }
+}
+
+// Fingerprint of every input file and every option that changes the output
+fn fingerprint(options: &Options, files: &[PathBuf]) -> std::io::Result<u64> {
+    let mut hasher = Fnv1a::new();
+    let settings = &options.settings;
+    for value in &[settings.max_width, settings.max_height, settings.padding, settings.extrude] {
+        hasher.write_u64(*value as u64);
+    }
+    hasher.write(&[settings.allow_rotation as u8, settings.trim as u8]);
+    hasher.write_u64(options.max_pages.map_or(0, |max_pages| max_pages as u64 + 1));
+
+    for file in files {
+        hasher.write_bytes(region_name(&options.input, file).as_bytes());
+        hasher.write_bytes(&std::fs::read(file)?);
+    }
+    Ok(hasher.finish())
+}
+
+fn page_files(output: &Path, page_count: usize) -> Vec<PathBuf> {
+    let stem = output.file_stem().and_then(|stem| stem.to_str()).unwrap_or("atlas");
+    (0..page_count).map(|page| output.with_file_name(format!("{}_{}.png", stem, page))).collect()
+}
+
+// Removes the pages an earlier pack with more pages left behind
+fn remove_stale_pages(output: &Path, page_count: usize) -> std::io::Result<()> {
+    let mut page = page_count;
+    loop {
+        let file = page_files(output, page + 1).pop().unwrap();
+        if !file.exists() {
+            return Ok(());
+        }
+        std::fs::remove_file(&file)?;
+        page += 1;
+    }
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let mut files = Vec::new();
+    collect_images(&options.input, &mut files).map_err(|e| format!("failed to read {}: {}", options.input.display(), e))?;
+    files.sort();
+
+    if files.is_empty() {
+        return Err(format!("no images found in {}", options.input.display()));
+    }
+
+    let cache_path = options.output.with_extension("pack-cache");
+    let fingerprint = fingerprint(&options, &files).map_err(|e| format!("failed to read input: {}", e))?;
+
+    if options.incremental {
+        let cached = std::fs::read_to_string(&cache_path).ok().and_then(|text| {
+            let mut lines = text.lines();
+            let hash = u64::from_str_radix(lines.next()?, 16).ok()?;
+            let pages = lines.next()?.parse::<usize>().ok()?;
+            Some((hash, pages))
+        });
+
+        if let Some((hash, pages)) = cached {
+            let outputs_exist = options.output.exists() && page_files(&options.output, pages).iter().all(|page| page.exists());
+            if hash == fingerprint && outputs_exist {
+                println!("{} is up to date", options.output.display());
+                return Ok(());
+            }
+        }
+    }
+
+    let mut images = Vec::with_capacity(files.len());
+    for file in &files {
+        let mut reader = std::fs::File::open(file).map_err(|e| format!("failed to open {}: {}", file.display(), e))?;
+        let image = Image::from_reader(&mut reader).map_err(|e| format!("failed to decode {}: {}", file.display(), e))?;
+        images.push((region_name(&options.input, file), image));
+    }
+
+    let mut pack = ImagePack::with_settings(options.settings);
+    pack.add_images(images);
+
+    if let Some(max_pages) = options.max_pages {
+        if pack.page_count() > max_pages {
+            return Err(format!("the images need {} pages but at most {} are allowed", pack.page_count(), max_pages));
+        }
+    }
+
+    if let Some(directory) = options.output.parent().filter(|directory| !directory.as_os_str().is_empty()) {
+        std::fs::create_dir_all(directory).map_err(|e| format!("failed to create {}: {}", directory.display(), e))?;
+    }
+
+    let output = options.output.to_str().ok_or("the output path is not valid unicode")?;
+    pack.save(output).map_err(|e| format!("failed to write {}: {}", output, e))?;
+    remove_stale_pages(&options.output, pack.page_count()).map_err(|e| format!("failed to remove old pages: {}", e))?;
+    std::fs::write(&cache_path, format!("{:016x}\n{}\n", fingerprint, pack.page_count())).map_err(|e| format!("failed to write {}: {}", cache_path.display(), e))?;
+
+    println!("packed {} images into {} page(s), {:.1}% of the page area is used", files.len(), pack.page_count(), pack.efficiency() * 100.0);
+    for page in 0..pack.page_count() {
+        let bitmap = pack.get_page(page);
+        println!("    page {}: {}x{}, {:.1}% used", page, bitmap.width(), bitmap.height(), pack.page_efficiency(page) * 100.0);
+    }
+    Ok(())
+}
+
+fn main() {
+    let options = match parse_options(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("error: {}\n", message);
+            }
+            eprint!("{}", USAGE);
+            std::process::exit(if message.is_empty() { 0 } else { 2 });
+        }
+    };
+
+    if let Err(message) = run(options) {
+        eprintln!("error: {}", message);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>().into_iter()
+    }
+
+    fn test_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(directory.join("images")).unwrap();
+        directory
+    }
+
+    #[test]
+    fn fnv1a_is_stable() {
+        let mut hasher = Fnv1a::new();
+        assert_eq!(hasher.finish(), 0xcbf2_9ce4_8422_2325);
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn parse_pack_options() {
+        let options = parse_options(args(&["in", "out/atlas.json", "--max-size", "256x128", "--rotate", "--padding", "0", "--incremental"])).unwrap();
+        assert_eq!((options.input, options.output), (PathBuf::from("in"), PathBuf::from("out/atlas.json")));
+        assert_eq!((options.settings.max_width, options.settings.max_height, options.settings.padding), (256, 128, 0));
+        assert!(options.settings.allow_rotation && !options.settings.trim && options.incremental);
+
+        assert!(parse_options(args(&["in"])).is_err());
+        assert!(parse_options(args(&["in", "out.json", "--padding", "two"])).is_err());
+        assert!(parse_options(args(&["in", "out.json", "--unknown"])).is_err());
+    }
+
+    #[test]
+    fn pack_removes_stale_pages() {
+        let directory = test_directory("engine_renderer_pack_stale_test");
+        for name in &["a", "b", "c"] {
+            Image::from_color(8, 8, 0xFF_FF_FF_FF).to_file(directory.join("images").join(format!("{}.png", name)).to_str().unwrap()).unwrap();
+        }
+        let input = directory.join("images");
+        let output = directory.join("atlas.json");
+        let pack_args = || args(&[input.to_str().unwrap(), output.to_str().unwrap(), "--max-size", "8x8", "--padding", "0"]);
+
+        run(parse_options(pack_args()).unwrap()).unwrap();
+        assert!(page_files(&output, 3).iter().all(|page| page.exists()));
+        let fingerprint_before = fingerprint(&parse_options(pack_args()).unwrap(), &[input.join("a.png")]).unwrap();
+
+        std::fs::remove_file(input.join("c.png")).unwrap();
+        run(parse_options(pack_args()).unwrap()).unwrap();
+        assert!(page_files(&output, 2).iter().all(|page| page.exists()));
+        assert!(!directory.join("atlas_2.png").exists());
+        assert_eq!(fingerprint(&parse_options(pack_args()).unwrap(), &[input.join("a.png")]).unwrap(), fingerprint_before);
+    }
+}
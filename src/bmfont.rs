@@ -0,0 +1,437 @@
+/*
+ *   Copyright (c) 2021 Ludwig Bogsveen
+ *   All rights reserved.
+
+ *   Permission is hereby granted, free of charge, to any person obtaining a copy
+ *   of this software and associated documentation files (the "Software"), to deal
+ *   in the Software without restriction, including without limitation the rights
+ *   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *   copies of the Software, and to permit persons to whom the Software is
+ *   furnished to do so, subject to the following conditions:
+
+ *   The above copyright notice and this permission notice shall be included in all
+ *   copies or substantial portions of the Software.
+
+ *   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *   SOFTWARE.
+ */
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use crate::atlas::page_path;
+use crate::texture::{Image, ImageError};
+
+const BINARY_MAGIC: [u8; 3] = *b"BMF";
+
+#[derive(Debug)]
+pub enum BitmapFontError {
+    Io(std::io::Error),
+    Image(ImageError),
+    UnsupportedVersion(u8),
+    Truncated,
+    InvalidDescriptor(String),
+    MissingPage(u32),
+}
+
+impl From<std::io::Error> for BitmapFontError {
+    fn from(error: std::io::Error) -> Self { Self::Io(error) }
+}
+
+impl From<ImageError> for BitmapFontError {
+    fn from(error: ImageError) -> Self { Self::Image(error) }
+}
+
+// What a channel of the page images holds, fonts exported with channel packing put a different glyph in each channel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChannelContent {
+    Glyph,
+    Outline,
+    GlyphAndOutline,
+    Zero,
+    One,
+}
+
+impl ChannelContent {
+    fn from_value(value: u32) -> ChannelContent {
+        match value {
+            1 => Self::Outline,
+            2 => Self::GlyphAndOutline,
+            3 => Self::Zero,
+            4 => Self::One,
+            _ => Self::Glyph,
+        }
+    }
+}
+
+// x and y use the same lower left origin as Image, the offsets and advance are kept as BMFont stores them with
+// y_offset going down from the top of the line. channel is the BMFont bit mask, 1 blue, 2 green, 4 red, 8 alpha.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BitmapChar {
+    pub id: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub x_offset: i32,
+    pub y_offset: i32,
+    pub x_advance: i32,
+    pub page: u32,
+    pub channel: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BitmapFontDescriptor {
+    pub face: String,
+    pub size: i32,
+    pub line_height: u32,
+    pub base: u32,
+    pub page_width: u32,
+    pub page_height: u32,
+    pub packed: bool,
+    pub channels: [ChannelContent; 4], // red, green, blue and alpha
+    pub pages: Vec<String>,
+    pub chars: Vec<BitmapChar>,
+    pub kernings: Vec<(u32, u32, i32)>,
+}
+
+// A parsed .fnt file together with its page images
+pub struct BitmapFont {
+    pub(crate) descriptor: BitmapFontDescriptor,
+    pub(crate) pages: Vec<Image>,
+    pub(crate) chars: HashMap<u32, BitmapChar>,
+    pub(crate) kernings: HashMap<(u32, u32), i32>,
+}
+
+impl Default for BitmapFontDescriptor {
+    fn default() -> Self { BitmapFontDescriptor::new() }
+}
+
+impl BitmapFontDescriptor {
+    pub fn new() -> BitmapFontDescriptor {
+        BitmapFontDescriptor {
+            face: String::new(),
+            size: 0,
+            line_height: 0,
+            base: 0,
+            page_width: 0,
+            page_height: 0,
+            packed: false,
+            channels: [ChannelContent::Glyph; 4],
+            pages: Vec::new(),
+            chars: Vec::new(),
+            kernings: Vec::new(),
+        }
+    }
+
+    pub fn from_file(path: &str) -> Result<BitmapFontDescriptor, BitmapFontError> {
+        BitmapFontDescriptor::parse(&std::fs::read(path)?)
+    }
+
+    // Detects if the bytes are the binary or the text format
+    pub fn parse(bytes: &[u8]) -> Result<BitmapFontDescriptor, BitmapFontError> {
+        if bytes.starts_with(&BINARY_MAGIC) {
+            BitmapFontDescriptor::from_binary(bytes)
+        } else {
+            let text = std::str::from_utf8(bytes).map_err(|_| BitmapFontError::InvalidDescriptor("text is not UTF-8".to_string()))?;
+            BitmapFontDescriptor::from_text(text)
+        }
+    }
+
+    pub fn from_text(text: &str) -> Result<BitmapFontDescriptor, BitmapFontError> {
+        let mut descriptor = BitmapFontDescriptor::new();
+        // The binary format stores the page count in 16 bits, text files without a pages= count get the same limit
+        let mut page_count = u16::MAX as usize + 1;
+
+        for line in text.lines() {
+            let mut tokens = tokenize(line).into_iter();
+            let tag = match tokens.next() {
+                Some((tag, _)) => tag,
+                None => continue,
+            };
+            let attributes: HashMap<String, String> = tokens.collect();
+            let number = |key: &str| -> Result<i32, BitmapFontError> {
+                let value = attributes.get(key).ok_or_else(|| BitmapFontError::InvalidDescriptor(format!("{} is missing \"{}\"", tag, key)))?;
+                value.parse().map_err(|_| BitmapFontError::InvalidDescriptor(format!("\"{}\" in {} is not a number", key, tag)))
+            };
+            let unsigned = |key: &str| -> Result<u32, BitmapFontError> {
+                let value = number(key)?;
+                u32::try_from(value).map_err(|_| BitmapFontError::InvalidDescriptor(format!("\"{}\" in {} is negative", key, tag)))
+            };
+            let optional = |key: &str| attributes.get(key).and_then(|value| value.parse::<i32>().ok()).unwrap_or(0);
+
+            match tag.as_str() {
+                "info" => {
+                    descriptor.face = attributes.get("face").cloned().unwrap_or_default();
+                    descriptor.size = optional("size");
+                },
+                "common" => {
+                    descriptor.line_height = unsigned("lineHeight")?;
+                    descriptor.base = unsigned("base")?;
+                    descriptor.page_width = unsigned("scaleW")?;
+                    descriptor.page_height = unsigned("scaleH")?;
+                    if attributes.contains_key("pages") {
+                        page_count = unsigned("pages")? as usize;
+                    }
+                    descriptor.packed = optional("packed") != 0;
+                    descriptor.channels = [
+                        ChannelContent::from_value(optional("redChnl") as u32),
+                        ChannelContent::from_value(optional("greenChnl") as u32),
+                        ChannelContent::from_value(optional("blueChnl") as u32),
+                        ChannelContent::from_value(optional("alphaChnl") as u32),
+                    ];
+                },
+                "page" => {
+                    let id = unsigned("id")? as usize;
+                    if id >= page_count {
+                        return Err(BitmapFontError::InvalidDescriptor(format!("page {} is past the page count of {}", id, page_count)));
+                    }
+                    let file = attributes.get("file").cloned().ok_or_else(|| BitmapFontError::InvalidDescriptor("page is missing \"file\"".to_string()))?;
+                    if descriptor.pages.len() <= id {
+                        descriptor.pages.resize(id + 1, String::new());
+                    }
+                    descriptor.pages[id] = file;
+                },
+                "char" => {
+                    let character = BitmapChar {
+                        id: unsigned("id")?,
+                        x: unsigned("x")?,
+                        y: unsigned("y")?,
+                        width: unsigned("width")?,
+                        height: unsigned("height")?,
+                        x_offset: number("xoffset")?,
+                        y_offset: number("yoffset")?,
+                        x_advance: number("xadvance")?,
+                        page: if attributes.contains_key("page") { unsigned("page")? } else { 0 },
+                        channel: attributes.get("chnl").and_then(|value| value.parse().ok()).unwrap_or(15),
+                    };
+                    descriptor.push_char(character)?;
+                },
+                "kerning" => descriptor.kernings.push((unsigned("first")?, unsigned("second")?, number("amount")?)),
+                _ => {},
+            }
+        }
+
+        descriptor.validate()
+    }
+
+    // Version 3 of the binary format, made up of the same blocks as the text format
+    pub fn from_binary(bytes: &[u8]) -> Result<BitmapFontDescriptor, BitmapFontError> {
+        if bytes.len() < 4 || !bytes.starts_with(&BINARY_MAGIC) {
+            return Err(BitmapFontError::InvalidDescriptor("missing BMF signature".to_string()));
+        }
+        if bytes[3] != 3 {
+            return Err(BitmapFontError::UnsupportedVersion(bytes[3]));
+        }
+
+        let mut descriptor = BitmapFontDescriptor::new();
+        let mut offset = 4;
+        while offset < bytes.len() {
+            let block_type = bytes[offset];
+            let size = read_u32(bytes, offset + 1)? as usize;
+            let block = bytes.get(offset + 5..offset + 5 + size).ok_or(BitmapFontError::Truncated)?;
+            offset += 5 + size;
+
+            match block_type {
+                1 => {
+                    descriptor.size = read_u16(block, 0)? as i16 as i32;
+                    let name = block.get(14..).ok_or(BitmapFontError::Truncated)?;
+                    descriptor.face = String::from_utf8_lossy(name.split(|&b| b == 0).next().unwrap_or(&[])).into_owned();
+                },
+                2 => {
+                    descriptor.line_height = read_u16(block, 0)? as u32;
+                    descriptor.base = read_u16(block, 2)? as u32;
+                    descriptor.page_width = read_u16(block, 4)? as u32;
+                    descriptor.page_height = read_u16(block, 6)? as u32;
+                    let flags = *block.get(10).ok_or(BitmapFontError::Truncated)?;
+                    descriptor.packed = flags & 0x80 != 0;
+                    let channels = block.get(11..15).ok_or(BitmapFontError::Truncated)?;
+                    // Stored as alpha, red, green and blue
+                    descriptor.channels = [
+                        ChannelContent::from_value(channels[1] as u32),
+                        ChannelContent::from_value(channels[2] as u32),
+                        ChannelContent::from_value(channels[3] as u32),
+                        ChannelContent::from_value(channels[0] as u32),
+                    ];
+                },
+                3 => {
+                    descriptor.pages = block.split(|&b| b == 0)
+                        .filter(|name| !name.is_empty())
+                        .map(|name| String::from_utf8_lossy(name).into_owned())
+                        .collect();
+                },
+                4 => {
+                    for record in block.chunks_exact(20) {
+                        descriptor.push_char(BitmapChar {
+                            id: read_u32(record, 0)?,
+                            x: read_u16(record, 4)? as u32,
+                            y: read_u16(record, 6)? as u32,
+                            width: read_u16(record, 8)? as u32,
+                            height: read_u16(record, 10)? as u32,
+                            x_offset: read_u16(record, 12)? as i16 as i32,
+                            y_offset: read_u16(record, 14)? as i16 as i32,
+                            x_advance: read_u16(record, 16)? as i16 as i32,
+                            page: record[18] as u32,
+                            channel: record[19] as u32,
+                        })?;
+                    }
+                },
+                5 => {
+                    for record in block.chunks_exact(10) {
+                        descriptor.kernings.push((read_u32(record, 0)?, read_u32(record, 4)?, read_u16(record, 8)? as i16 as i32));
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        descriptor.validate()
+    }
+
+    // Flips the char to the lower left origin, common always comes before the chars
+    fn push_char(&mut self, mut character: BitmapChar) -> Result<(), BitmapFontError> {
+        if character.y.checked_add(character.height).is_none_or(|top| top > self.page_height) {
+            return Err(BitmapFontError::InvalidDescriptor(format!("char {} is outside of the page", character.id)));
+        }
+        character.y = self.page_height - character.y - character.height;
+        self.chars.push(character);
+        Ok(())
+    }
+
+    fn validate(self) -> Result<BitmapFontDescriptor, BitmapFontError> {
+        if self.line_height == 0 {
+            return Err(BitmapFontError::InvalidDescriptor("missing common block".to_string()));
+        }
+        if let Some(character) = self.chars.iter().find(|character| character.page as usize >= self.pages.len()) {
+            return Err(BitmapFontError::MissingPage(character.page));
+        }
+        Ok(self)
+    }
+}
+
+impl BitmapFont {
+    // Loads the .fnt file and the page images next to it
+    pub fn from_file(path: &str) -> Result<BitmapFont, BitmapFontError> {
+        let descriptor = BitmapFontDescriptor::from_file(path)?;
+        let mut pages = Vec::new();
+        for page in &descriptor.pages {
+            pages.push(Image::decode(&std::fs::read(page_path(path, page))?)?);
+        }
+        BitmapFont::new(descriptor, pages)
+    }
+
+    pub fn new(descriptor: BitmapFontDescriptor, pages: Vec<Image>) -> Result<BitmapFont, BitmapFontError> {
+        for character in &descriptor.chars {
+            let page = pages.get(character.page as usize).ok_or(BitmapFontError::MissingPage(character.page))?;
+            let right = character.x.checked_add(character.width);
+            let top = character.y.checked_add(character.height);
+            if right.is_none_or(|right| right > page.width()) || top.is_none_or(|top| top > page.height()) {
+                return Err(BitmapFontError::InvalidDescriptor(format!("char {} is outside of page {}", character.id, character.page)));
+            }
+        }
+
+        Ok(BitmapFont {
+            chars: descriptor.chars.iter().map(|character| (character.id, *character)).collect(),
+            kernings: descriptor.kernings.iter().map(|&(first, second, amount)| ((first, second), amount)).collect(),
+            descriptor,
+            pages,
+        })
+    }
+
+    pub fn descriptor(&self) -> &BitmapFontDescriptor {
+        &self.descriptor
+    }
+
+    pub fn char(&self, id: u32) -> Option<&BitmapChar> {
+        self.chars.get(&id)
+    }
+
+    pub fn kerning(&self, first: u32, second: u32) -> i32 {
+        self.kernings.get(&(first, second)).cloned().unwrap_or(0)
+    }
+
+    // The glyph as coverage in every channel like FreeType glyphs, chars that use all channels are copied as is
+    pub fn glyph_image(&self, id: u32) -> Option<Image> {
+        let character = self.chars.get(&id)?;
+        let mut image = self.pages[character.page as usize].crop(character.x, character.y, character.width, character.height);
+
+        let shift = match character.channel {
+            4 => 24,
+            2 => 16,
+            1 => 8,
+            8 => 0,
+            _ => return Some(image),
+        };
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                let coverage = (image.get_rgba8(x, y) >> shift) & 0xFF;
+                image.set_rgba8(x, y, coverage << 24 | coverage << 16 | coverage << 8 | coverage);
+            }
+        }
+        Some(image)
+    }
+}
+
+// Splits a line of the text format into its tag and key=value pairs, values can be quoted
+fn tokenize(line: &str) -> Vec<(String, String)> {
+    let mut tokens = Vec::new();
+    let mut chars = line.trim().chars().peekable();
+
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut key = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() || c == '=' {
+                break;
+            }
+            key.push(c);
+            chars.next();
+        }
+
+        let mut value = String::new();
+        if chars.peek() == Some(&'=') {
+            chars.next();
+            if chars.peek() == Some(&'"') {
+                chars.next();
+                for c in &mut chars {
+                    if c == '"' {
+                        break;
+                    }
+                    value.push(c);
+                }
+            } else {
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() {
+                        break;
+                    }
+                    value.push(c);
+                    chars.next();
+                }
+            }
+        }
+        tokens.push((key, value));
+    }
+
+    tokens
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, BitmapFontError> {
+    let b = bytes.get(offset..offset + 2).ok_or(BitmapFontError::Truncated)?;
+    Ok(u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, BitmapFontError> {
+    let b = bytes.get(offset..offset + 4).ok_or(BitmapFontError::Truncated)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
@@ -0,0 +1,201 @@
+/*
+ *   Copyright (c) 2021 Ludwig Bogsveen
+ *   All rights reserved.
+
+ *   Permission is hereby granted, free of charge, to any person obtaining a copy
+ *   of this software and associated documentation files (the "Software"), to deal
+ *   in the Software without restriction, including without limitation the rights
+ *   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *   copies of the Software, and to permit persons to whom the Software is
+ *   furnished to do so, subject to the following conditions:
+
+ *   The above copyright notice and this permission notice shall be included in all
+ *   copies or substantial portions of the Software.
+
+ *   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *   SOFTWARE.
+ */
+
+use crate::matrix::Mat3x3f;
+use crate::vector::Vec3f;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CoordinateMode {
+    // -1 to 1 across the viewport, what Graphics has always used
+    Ndc,
+    PixelsTopLeft,
+    PixelsBottomLeft,
+    // A fixed number of units with the origin in the top left, the viewport keeps the aspect ratio and the rest of
+    // the frame is left as bars
+    Virtual { width: f32, height: f32 },
+}
+
+// Position is the world point at the origin of the coordinate mode, rotation (radians) and zoom are around the
+// center of the viewport. The viewport is a part of the frame in 0 to 1 units with the origin in the bottom left.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera2D {
+    position: (f32, f32),
+    zoom: f32,
+    rotation: f32,
+    viewport: (f32, f32, f32, f32),
+    mode: CoordinateMode,
+    frame_width: u32,
+    frame_height: u32,
+}
+
+impl Camera2D {
+    pub fn new(mode: CoordinateMode) -> Camera2D {
+        Camera2D {
+            position: (0.0, 0.0),
+            zoom: 1.0,
+            rotation: 0.0,
+            viewport: (0.0, 0.0, 1.0, 1.0),
+            mode,
+            frame_width: 1,
+            frame_height: 1,
+        }
+    }
+
+    pub fn set_position(&mut self, x: f32, y: f32)     { self.position = (x, y); }
+    pub fn set_zoom(&mut self, zoom: f32)               { self.zoom = zoom; }
+    pub fn set_rotation(&mut self, rotation: f32)       { self.rotation = rotation; }
+    pub fn set_mode(&mut self, mode: CoordinateMode)    { self.mode = mode; }
+
+    pub fn set_viewport(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        self.viewport = (x, y, width, height);
+    }
+
+    pub fn translate(&mut self, x: f32, y: f32) {
+        self.position = (self.position.0 + x, self.position.1 + y);
+    }
+
+    pub fn position (&self) -> (f32, f32)               { self.position }
+    pub fn zoom     (&self) -> f32                      { self.zoom     }
+    pub fn rotation (&self) -> f32                      { self.rotation }
+    pub fn viewport (&self) -> (f32, f32, f32, f32)     { self.viewport }
+    pub fn mode     (&self) -> CoordinateMode           { self.mode     }
+
+    // Graphics calls this with the size of the window or the framebuffer it draws into
+    pub fn resize(&mut self, frame_width: u32, frame_height: u32) {
+        self.frame_width = frame_width.max(1);
+        self.frame_height = frame_height.max(1);
+    }
+
+    pub fn frame_size(&self) -> (u32, u32) {
+        (self.frame_width, self.frame_height)
+    }
+
+    // The area drawn to in frame pixels as x, y, width and height with the origin in the bottom left, the same as
+    // glViewport. Virtual resolutions are letterboxed inside the viewport.
+    pub fn pixel_viewport(&self) -> (i32, i32, i32, i32) {
+        let (fw, fh) = (self.frame_width as f32, self.frame_height as f32);
+        let x = (self.viewport.0 * fw).round();
+        let y = (self.viewport.1 * fh).round();
+        let width = (self.viewport.2 * fw).round().max(1.0);
+        let height = (self.viewport.3 * fh).round().max(1.0);
+
+        match self.mode {
+            CoordinateMode::Virtual { width: vw, height: vh } if vw > 0.0 && vh > 0.0 => {
+                let scale = (width / vw).min(height / vh);
+                let fitted_width = (vw * scale).round().max(1.0);
+                let fitted_height = (vh * scale).round().max(1.0);
+                let fitted_x = x + ((width - fitted_width) / 2.0).floor();
+                let fitted_y = y + ((height - fitted_height) / 2.0).floor();
+                (fitted_x as i32, fitted_y as i32, fitted_width as i32, fitted_height as i32)
+            },
+            _ => (x as i32, y as i32, width as i32, height as i32),
+        }
+    }
+
+    // Size of the area the coordinate mode spans, in its own units
+    fn extent(&self) -> (f32, f32) {
+        let (_, _, width, height) = self.pixel_viewport();
+        match self.mode {
+            CoordinateMode::Ndc => (2.0, 2.0),
+            CoordinateMode::PixelsTopLeft | CoordinateMode::PixelsBottomLeft => (width as f32, height as f32),
+            CoordinateMode::Virtual { width, height } => (width, height),
+        }
+    }
+
+    // From the units of the coordinate mode to normalized device coordinates
+    fn base(&self) -> Mat3x3f {
+        let (width, height) = self.extent();
+        match self.mode {
+            CoordinateMode::Ndc => Mat3x3f::identity(),
+            CoordinateMode::PixelsBottomLeft => Mat3x3f::mult(&Mat3x3f::translation(-1.0, -1.0), &Mat3x3f::scale(2.0 / width, 2.0 / height)),
+            CoordinateMode::PixelsTopLeft | CoordinateMode::Virtual { .. } => Mat3x3f::mult(&Mat3x3f::translation(-1.0, 1.0), &Mat3x3f::scale(2.0 / width, -2.0 / height)),
+        }
+    }
+
+    // The point rotation and zoom happen around, in the units of the coordinate mode
+    fn center(&self) -> (f32, f32) {
+        let (width, height) = self.extent();
+        match self.mode {
+            CoordinateMode::Ndc => (0.0, 0.0),
+            _ => (width / 2.0, height / 2.0),
+        }
+    }
+
+    // Moves world coordinates into the units of the coordinate mode
+    pub fn view_matrix(&self) -> Mat3x3f {
+        let (cx, cy) = self.center();
+        let view = Mat3x3f::translation(-self.position.0 - cx, -self.position.1 - cy);
+        let view = Mat3x3f::mult(&Mat3x3f::rotation(-self.rotation), &view);
+        let view = Mat3x3f::mult(&Mat3x3f::scale(self.zoom, self.zoom), &view);
+        Mat3x3f::mult(&Mat3x3f::translation(cx, cy), &view)
+    }
+
+    // From world coordinates to normalized device coordinates inside the viewport
+    pub fn matrix(&self) -> Mat3x3f {
+        Mat3x3f::mult(&self.base(), &self.view_matrix())
+    }
+
+    // World units covered by one pixel of the viewport at a zoom of 1, virtual resolutions count their own units
+    // as pixels. y is negative when the y axis points down.
+    pub fn pixel_scale(&self) -> (f32, f32) {
+        let (_, _, width, height) = self.pixel_viewport();
+        match self.mode {
+            CoordinateMode::Ndc => (2.0 / width as f32, 2.0 / height as f32),
+            CoordinateMode::PixelsBottomLeft => (1.0, 1.0),
+            CoordinateMode::PixelsTopLeft | CoordinateMode::Virtual { .. } => (1.0, -1.0),
+        }
+    }
+
+    pub fn y_down(&self) -> bool {
+        self.pixel_scale().1 < 0.0
+    }
+
+    // Screen coordinates are frame pixels with the origin in the top left, the same as cursor positions
+    pub fn world_to_screen(&self, x: f32, y: f32) -> (f32, f32) {
+        let ndc = Mat3x3f::mult_vec(&self.matrix(), Vec3f::new(x, y, 1.0));
+        let (vx, vy, width, height) = self.pixel_viewport();
+        let screen_x = vx as f32 + (ndc.x + 1.0) / 2.0 * width as f32;
+        let screen_y = self.frame_height as f32 - (vy as f32 + (ndc.y + 1.0) / 2.0 * height as f32);
+        (screen_x, screen_y)
+    }
+
+    pub fn screen_to_world(&self, x: f32, y: f32) -> (f32, f32) {
+        let (vx, vy, width, height) = self.pixel_viewport();
+        let ndc_x = (x - vx as f32) / width as f32 * 2.0 - 1.0;
+        let ndc_y = (self.frame_height as f32 - y - vy as f32) / height as f32 * 2.0 - 1.0;
+        match self.matrix().inverse() {
+            Some(inverse) => {
+                let world = Mat3x3f::mult_vec(&inverse, Vec3f::new(ndc_x, ndc_y, 1.0));
+                (world.x, world.y)
+            },
+            None => self.position,
+        }
+    }
+
+    // Whether a screen position is inside the viewport, cursors over letterbox bars are not
+    pub fn contains_screen_point(&self, x: f32, y: f32) -> bool {
+        let (vx, vy, width, height) = self.pixel_viewport();
+        let y = self.frame_height as f32 - y;
+        x >= vx as f32 && x < (vx + width) as f32 && y >= vy as f32 && y < (vy + height) as f32
+    }
+}
@@ -21,7 +21,7 @@
  *   SOFTWARE.
  */
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Color {
     rgba: u32,
 }
@@ -109,6 +109,104 @@ impl From<Color> for (u8, u8, u8, u8) {
 }
 
 
+#[derive(Clone, Copy)]
+pub struct ColorMatrix {
+    pub m: [[f32; 5]; 4],
+}
+
+impl ColorMatrix {
+    pub fn identity() -> ColorMatrix {
+        ColorMatrix {
+            m: [
+                [1.0, 0.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0, 0.0],
+            ],
+        }
+    }
+
+    pub fn saturation(saturation: f32) -> ColorMatrix {
+        let s = saturation;
+        let r = 0.2126 * (1.0 - s);
+        let g = 0.7152 * (1.0 - s);
+        let b = 0.0722 * (1.0 - s);
+        ColorMatrix {
+            m: [
+                [r + s, g,     b,     0.0, 0.0],
+                [r,     g + s, b,     0.0, 0.0],
+                [r,     g,     b + s, 0.0, 0.0],
+                [0.0,   0.0,   0.0,   1.0, 0.0],
+            ],
+        }
+    }
+
+    pub fn grayscale() -> ColorMatrix { ColorMatrix::saturation(0.0) }
+
+    pub fn brightness(offset: f32) -> ColorMatrix {
+        let mut matrix = ColorMatrix::identity();
+        for i in 0..3 {
+            matrix.m[i][4] = offset;
+        }
+        matrix
+    }
+
+    pub fn contrast(contrast: f32) -> ColorMatrix {
+        let mut matrix = ColorMatrix::identity();
+        for i in 0..3 {
+            matrix.m[i][i] = contrast;
+            matrix.m[i][4] = 0.5 * (1.0 - contrast);
+        }
+        matrix
+    }
+
+    pub fn invert() -> ColorMatrix {
+        let mut matrix = ColorMatrix::identity();
+        for i in 0..3 {
+            matrix.m[i][i] = -1.0;
+            matrix.m[i][4] = 1.0;
+        }
+        matrix
+    }
+
+    pub fn tint(color: Color) -> ColorMatrix {
+        let (r, g, b, a) = <(f32, f32, f32, f32)>::from(color);
+        let mut matrix = ColorMatrix::identity();
+        matrix.m[0][0] = r;
+        matrix.m[1][1] = g;
+        matrix.m[2][2] = b;
+        matrix.m[3][3] = a;
+        matrix
+    }
+
+    // Applies rhs first and lhs second, the same order as Mat3x3f::mult.
+    pub fn mult(lhs: &ColorMatrix, rhs: &ColorMatrix) -> ColorMatrix {
+        let mut new_matrix = ColorMatrix { m: [[0.0; 5]; 4] };
+        for r in 0..4 {
+            for c in 0..5 {
+                let mut value = 0.0;
+                for i in 0..4 {
+                    value += lhs.m[r][i] * rhs.m[i][c];
+                }
+                if c == 4 {
+                    value += lhs.m[r][4];
+                }
+                new_matrix.m[r][c] = value;
+            }
+        }
+        new_matrix
+    }
+
+    pub fn transform(&self, rgba: (f32, f32, f32, f32)) -> (f32, f32, f32, f32) {
+        let v = [rgba.0, rgba.1, rgba.2, rgba.3];
+        let mut res = [0.0; 4];
+        for (value, row) in res.iter_mut().zip(self.m.iter()) {
+            *value = row[0] * v[0] + row[1] * v[1] + row[2] * v[2] + row[3] * v[3] + row[4];
+        }
+        (res[0], res[1], res[2], res[3])
+    }
+}
+
 pub const RED           : Color = Color { rgba: 0xFF0000FF };
 pub const LIGHT_RED     : Color = Color { rgba: 0xFFCCCBFF };
 pub const DARK_RED      : Color = Color { rgba: 0x8B0000FF };
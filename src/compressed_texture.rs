@@ -0,0 +1,1012 @@
+/*
+ *   Copyright (c) 2021 Ludwig Bogsveen
+ *   All rights reserved.
+
+ *   Permission is hereby granted, free of charge, to any person obtaining a copy
+ *   of this software and associated documentation files (the "Software"), to deal
+ *   in the Software without restriction, including without limitation the rights
+ *   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *   copies of the Software, and to permit persons to whom the Software is
+ *   furnished to do so, subject to the following conditions:
+
+ *   The above copyright notice and this permission notice shall be included in all
+ *   copies or substantial portions of the Software.
+
+ *   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *   SOFTWARE.
+ */
+
+use std::io::prelude::*;
+use std::fs::File;
+use std::convert::TryInto;
+
+use crate::texture::Image;
+
+// The S3TC, BPTC and ETC2 enums are not all part of the core profile the gl crate is generated for.
+const GL_COMPRESSED_RGB_S3TC_DXT1_EXT:              u32 = 0x83F0;
+const GL_COMPRESSED_RGBA_S3TC_DXT1_EXT:             u32 = 0x83F1;
+const GL_COMPRESSED_RGBA_S3TC_DXT5_EXT:             u32 = 0x83F3;
+const GL_COMPRESSED_SRGB_S3TC_DXT1_EXT:             u32 = 0x8C4C;
+const GL_COMPRESSED_SRGB_ALPHA_S3TC_DXT1_EXT:       u32 = 0x8C4D;
+const GL_COMPRESSED_SRGB_ALPHA_S3TC_DXT5_EXT:       u32 = 0x8C4F;
+const GL_COMPRESSED_RGBA_BPTC_UNORM:                u32 = 0x8E8C;
+const GL_COMPRESSED_SRGB_ALPHA_BPTC_UNORM:          u32 = 0x8E8D;
+const GL_COMPRESSED_RGB8_ETC2:                      u32 = 0x9274;
+const GL_COMPRESSED_SRGB8_ETC2:                     u32 = 0x9275;
+const GL_COMPRESSED_RGB8_PUNCHTHROUGH_ALPHA1_ETC2:  u32 = 0x9276;
+const GL_COMPRESSED_SRGB8_PUNCHTHROUGH_ALPHA1_ETC2: u32 = 0x9277;
+const GL_COMPRESSED_RGBA8_ETC2_EAC:                 u32 = 0x9278;
+const GL_COMPRESSED_SRGB8_ALPHA8_ETC2_EAC:          u32 = 0x9279;
+
+const KTX2_IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+const DDS_MAGIC: [u8; 4] = *b"DDS ";
+
+#[derive(Debug)]
+pub enum CompressedTextureError {
+    Io(std::io::Error),
+    UnknownContainer,
+    InvalidHeader,
+    Truncated,
+    UnsupportedFormat(u32),
+    UnsupportedSupercompression(u32),
+}
+
+impl From<std::io::Error> for CompressedTextureError {
+    fn from(error: std::io::Error) -> Self { Self::Io(error) }
+}
+
+impl std::fmt::Display for CompressedTextureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "io error: {}", error),
+            Self::UnknownContainer => write!(f, "not a KTX2 or DDS file"),
+            Self::InvalidHeader => write!(f, "invalid container header"),
+            Self::Truncated => write!(f, "the file is truncated or an offset is out of range"),
+            Self::UnsupportedFormat(format) => write!(f, "unsupported format {}", format),
+            Self::UnsupportedSupercompression(scheme) => write!(f, "unsupported supercompression scheme {}", scheme),
+        }
+    }
+}
+
+impl std::error::Error for CompressedTextureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CompressedFormat {
+    Bc1,
+    Bc1Alpha,
+    Bc3,
+    Bc7,
+    Etc2Rgb,
+    Etc2RgbA1,
+    Etc2Rgba,
+}
+
+impl CompressedFormat {
+    pub fn block_size(&self) -> usize {
+        match self {
+            Self::Bc1 | Self::Bc1Alpha | Self::Etc2Rgb | Self::Etc2RgbA1 => 8,
+            Self::Bc3 | Self::Bc7 | Self::Etc2Rgba => 16,
+        }
+    }
+
+    // Saturates instead of overflowing for sizes read from broken headers
+    pub fn level_size(&self, width: u32, height: u32) -> usize {
+        let blocks_x = width.div_ceil(4).max(1) as usize;
+        let blocks_y = height.div_ceil(4).max(1) as usize;
+        blocks_x.saturating_mul(blocks_y).saturating_mul(self.block_size())
+    }
+
+    // Whether blocks can be mirrored vertically without decoding them, see CompressedImage::level_data_bottom_up
+    pub fn can_flip(&self) -> bool {
+        matches!(self, Self::Bc1 | Self::Bc1Alpha | Self::Bc3)
+    }
+
+    pub fn gl_internal_format(&self, srgb: bool) -> u32 {
+        match (self, srgb) {
+            (Self::Bc1,       false) => GL_COMPRESSED_RGB_S3TC_DXT1_EXT,
+            (Self::Bc1,       true)  => GL_COMPRESSED_SRGB_S3TC_DXT1_EXT,
+            (Self::Bc1Alpha,  false) => GL_COMPRESSED_RGBA_S3TC_DXT1_EXT,
+            (Self::Bc1Alpha,  true)  => GL_COMPRESSED_SRGB_ALPHA_S3TC_DXT1_EXT,
+            (Self::Bc3,       false) => GL_COMPRESSED_RGBA_S3TC_DXT5_EXT,
+            (Self::Bc3,       true)  => GL_COMPRESSED_SRGB_ALPHA_S3TC_DXT5_EXT,
+            (Self::Bc7,       false) => GL_COMPRESSED_RGBA_BPTC_UNORM,
+            (Self::Bc7,       true)  => GL_COMPRESSED_SRGB_ALPHA_BPTC_UNORM,
+            (Self::Etc2Rgb,   false) => GL_COMPRESSED_RGB8_ETC2,
+            (Self::Etc2Rgb,   true)  => GL_COMPRESSED_SRGB8_ETC2,
+            (Self::Etc2RgbA1, false) => GL_COMPRESSED_RGB8_PUNCHTHROUGH_ALPHA1_ETC2,
+            (Self::Etc2RgbA1, true)  => GL_COMPRESSED_SRGB8_PUNCHTHROUGH_ALPHA1_ETC2,
+            (Self::Etc2Rgba,  false) => GL_COMPRESSED_RGBA8_ETC2_EAC,
+            (Self::Etc2Rgba,  true)  => GL_COMPRESSED_SRGB8_ALPHA8_ETC2_EAC,
+        }
+    }
+
+    // Asks the current context whether it can sample this format natively.
+    // A GL context has to be current when this is called.
+    pub fn is_supported(&self) -> bool {
+        let version = crate::renderer::gl_version_number();
+        match self {
+            Self::Bc1 | Self::Bc1Alpha | Self::Bc3 => {
+                crate::renderer::gl_has_extension("GL_EXT_texture_compression_s3tc")
+            },
+            Self::Bc7 => {
+                version >= (4, 2) || crate::renderer::gl_has_extension("GL_ARB_texture_compression_bptc")
+            },
+            Self::Etc2Rgb | Self::Etc2RgbA1 | Self::Etc2Rgba => {
+                version >= (4, 3) || crate::renderer::gl_has_extension("GL_ARB_ES3_compatibility")
+            },
+        }
+    }
+
+    fn from_vk_format(vk_format: u32) -> Option<(CompressedFormat, bool)> {
+        match vk_format {
+            131 => Some((Self::Bc1,       false)),
+            132 => Some((Self::Bc1,       true)),
+            133 => Some((Self::Bc1Alpha,  false)),
+            134 => Some((Self::Bc1Alpha,  true)),
+            137 => Some((Self::Bc3,       false)),
+            138 => Some((Self::Bc3,       true)),
+            145 => Some((Self::Bc7,       false)),
+            146 => Some((Self::Bc7,       true)),
+            147 => Some((Self::Etc2Rgb,   false)),
+            148 => Some((Self::Etc2Rgb,   true)),
+            149 => Some((Self::Etc2RgbA1, false)),
+            150 => Some((Self::Etc2RgbA1, true)),
+            151 => Some((Self::Etc2Rgba,  false)),
+            152 => Some((Self::Etc2Rgba,  true)),
+            _ => None,
+        }
+    }
+
+    fn from_dxgi_format(dxgi_format: u32) -> Option<(CompressedFormat, bool)> {
+        match dxgi_format {
+            71 => Some((Self::Bc1Alpha, false)),
+            72 => Some((Self::Bc1Alpha, true)),
+            77 => Some((Self::Bc3,      false)),
+            78 => Some((Self::Bc3,      true)),
+            98 => Some((Self::Bc7,      false)),
+            99 => Some((Self::Bc7,      true)),
+            _ => None,
+        }
+    }
+}
+
+pub struct CompressedLevel {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+// A block compressed image together with all of its mip levels, level 0 being the largest.
+// The blocks are kept in the row order of the container. That is top-down for DDS files and KTX2 files without an
+// orientation, while Image and textures store the bottom row first. decode and Texture::from_compressed flip the
+// rows so compressed textures come out the same way up as Image::from_file ones.
+pub struct CompressedImage {
+    format: CompressedFormat,
+    srgb: bool,
+    bottom_up: bool,
+    levels: Vec<CompressedLevel>,
+}
+
+impl CompressedImage {
+    // Every level needs at least the blocks its size covers, decode and level_data_bottom_up rely on it
+    pub fn new(format: CompressedFormat, srgb: bool, levels: Vec<CompressedLevel>) -> Result<CompressedImage, CompressedTextureError> {
+        if levels.is_empty() {
+            return Err(CompressedTextureError::InvalidHeader);
+        }
+        if levels.iter().any(|level| level.data.len() < format.level_size(level.width, level.height)) {
+            return Err(CompressedTextureError::Truncated);
+        }
+
+        Ok(CompressedImage {
+            format,
+            srgb,
+            bottom_up: false,
+            levels,
+        })
+    }
+
+    // Set when the first row of blocks is the bottom of the image, like KTX2 files with the "ru" orientation
+    pub fn set_bottom_up(&mut self, bottom_up: bool) {
+        self.bottom_up = bottom_up;
+    }
+
+    pub fn from_file(file_path: &str) -> Result<CompressedImage, CompressedTextureError> {
+        let mut file = File::open(file_path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        CompressedImage::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<CompressedImage, CompressedTextureError> {
+        if bytes.starts_with(&KTX2_IDENTIFIER) {
+            CompressedImage::from_ktx2(bytes)
+        } else if bytes.starts_with(&DDS_MAGIC) {
+            CompressedImage::from_dds(bytes)
+        } else {
+            Err(CompressedTextureError::UnknownContainer)
+        }
+    }
+
+    pub fn is_container(bytes: &[u8]) -> bool {
+        bytes.starts_with(&KTX2_IDENTIFIER) || bytes.starts_with(&DDS_MAGIC)
+    }
+
+    pub fn from_ktx2(bytes: &[u8]) -> Result<CompressedImage, CompressedTextureError> {
+        if !bytes.starts_with(&KTX2_IDENTIFIER) {
+            return Err(CompressedTextureError::InvalidHeader);
+        }
+
+        let vk_format   = read_u32(bytes, 12)?;
+        let width       = read_u32(bytes, 20)?;
+        let height      = read_u32(bytes, 24)?;
+        let depth       = read_u32(bytes, 28)?;
+        let layer_count = read_u32(bytes, 32)?;
+        let face_count  = read_u32(bytes, 36)?;
+        let level_count = read_u32(bytes, 40)?.max(1);
+        let supercompression = read_u32(bytes, 44)?;
+        let kvd_offset  = read_u32(bytes, 56)? as usize;
+        let kvd_length  = read_u32(bytes, 60)? as usize;
+
+        if width == 0 || depth > 1 || layer_count > 1 || face_count != 1 || level_count > max_level_count(width, height) {
+            return Err(CompressedTextureError::InvalidHeader);
+        }
+        if supercompression != 0 {
+            return Err(CompressedTextureError::UnsupportedSupercompression(supercompression));
+        }
+
+        let (format, srgb) = match CompressedFormat::from_vk_format(vk_format) {
+            Some(format) => format,
+            None => return Err(CompressedTextureError::UnsupportedFormat(vk_format)),
+        };
+
+        let mut levels = Vec::with_capacity(level_count as usize);
+        for level in 0..level_count {
+            let index = 80 + level as usize * 24;
+            let offset = to_usize(read_u64(bytes, index)?)?;
+            let length = to_usize(read_u64(bytes, index + 8)?)?;
+
+            let (level_width, level_height) = level_dimensions(width, height, level);
+            if length < format.level_size(level_width, level_height) {
+                return Err(CompressedTextureError::Truncated);
+            }
+
+            let data = read_bytes(bytes, offset, format.level_size(level_width, level_height))?;
+            levels.push(CompressedLevel { width: level_width, height: level_height, data });
+        }
+
+        let mut image = CompressedImage::new(format, srgb, levels)?;
+        image.set_bottom_up(ktx2_orientation(bytes, kvd_offset, kvd_length)?.get(1) == Some(&b'u'));
+        Ok(image)
+    }
+
+    pub fn from_dds(bytes: &[u8]) -> Result<CompressedImage, CompressedTextureError> {
+        if !bytes.starts_with(&DDS_MAGIC) || read_u32(bytes, 4)? != 124 {
+            return Err(CompressedTextureError::InvalidHeader);
+        }
+
+        let height      = read_u32(bytes, 12)?;
+        let width       = read_u32(bytes, 16)?;
+        let level_count = read_u32(bytes, 28)?.max(1);
+        let four_cc     = read_u32(bytes, 84)?;
+
+        if width == 0 || height == 0 || level_count > max_level_count(width, height) {
+            return Err(CompressedTextureError::InvalidHeader);
+        }
+
+        let mut offset: usize = 128;
+        let (format, srgb) = match &four_cc.to_le_bytes() {
+            b"DXT1" => (CompressedFormat::Bc1Alpha, false),
+            b"DXT5" => (CompressedFormat::Bc3, false),
+            b"DX10" => {
+                let dxgi_format = read_u32(bytes, 128)?;
+                offset += 20;
+                match CompressedFormat::from_dxgi_format(dxgi_format) {
+                    Some(format) => format,
+                    None => return Err(CompressedTextureError::UnsupportedFormat(dxgi_format)),
+                }
+            },
+            _ => return Err(CompressedTextureError::UnsupportedFormat(four_cc)),
+        };
+
+        let mut levels = Vec::with_capacity(level_count as usize);
+        for level in 0..level_count {
+            let (level_width, level_height) = level_dimensions(width, height, level);
+            let size = format.level_size(level_width, level_height);
+
+            let data = read_bytes(bytes, offset, size)?;
+            offset = offset.checked_add(size).ok_or(CompressedTextureError::Truncated)?;
+            levels.push(CompressedLevel { width: level_width, height: level_height, data });
+        }
+
+        CompressedImage::new(format, srgb, levels)
+    }
+
+    // Decodes one mip level on the CPU, used when the driver can't sample the format itself.
+    // The rows are bottom-up like every other Image. None when the image has no such level.
+    pub fn decode(&self, level: usize) -> Option<Image> {
+        let level = self.levels.get(level)?;
+        let blocks_x = level.width.div_ceil(4).max(1) as usize;
+        let blocks_y = level.height.div_ceil(4).max(1) as usize;
+        let block_size = self.format.block_size();
+
+        let mut pixels = vec![0u8; level.width as usize * level.height as usize * 4];
+        let mut block_pixels = [[0u8; 4]; 16];
+
+        for by in 0..blocks_y {
+            for bx in 0..blocks_x {
+                let offset = (bx + by * blocks_x) * block_size;
+                let block = &level.data[offset..offset + block_size];
+
+                match self.format {
+                    CompressedFormat::Bc1       => decode_bc1(block, true, false, &mut block_pixels),
+                    CompressedFormat::Bc1Alpha  => decode_bc1(block, true, true, &mut block_pixels),
+                    CompressedFormat::Bc3       => decode_bc3(block, &mut block_pixels),
+                    CompressedFormat::Bc7       => decode_bc7(block, &mut block_pixels),
+                    CompressedFormat::Etc2Rgb   => decode_etc2_rgb(block, false, &mut block_pixels),
+                    CompressedFormat::Etc2RgbA1 => decode_etc2_rgb(block, true, &mut block_pixels),
+                    CompressedFormat::Etc2Rgba  => decode_etc2_rgba(block, &mut block_pixels),
+                }
+
+                for py in 0..4 {
+                    for px in 0..4 {
+                        let x = bx * 4 + px;
+                        let y = by * 4 + py;
+                        if x >= level.width as usize || y >= level.height as usize {
+                            continue;
+                        }
+                        let row = if self.bottom_up { y } else { level.height as usize - 1 - y };
+                        let i = (x + row * level.width as usize) * 4;
+                        pixels[i..i + 4].copy_from_slice(&block_pixels[px + py * 4]);
+                    }
+                }
+            }
+        }
+
+        Some(Image::new(level.width, level.height, pixels))
+    }
+
+    // The blocks of a level with the bottom row first, ready for glCompressedTexImage2D. Only formats where
+    // CompressedFormat::can_flip is true can be mirrored without decoding, the others give None when the image
+    // is top-down. So do levels taller than one block whose height isn't a multiple of 4, their rows would have to
+    // move between blocks. Levels shorter than a block only flip the rows that are in the image.
+    pub fn level_data_bottom_up(&self, level: usize) -> Option<Vec<u8>> {
+        let level = self.levels.get(level)?;
+        if self.bottom_up {
+            return Some(level.data.clone());
+        }
+        if !self.format.can_flip() || (level.height > 4 && level.height % 4 != 0) {
+            return None;
+        }
+
+        let rows = level.height.clamp(1, 4) as usize;
+        let block_size = self.format.block_size();
+        let row_size = level.width.div_ceil(4).max(1) as usize * block_size;
+        let size = self.format.level_size(level.width, level.height);
+        let mut data = Vec::with_capacity(size);
+        for row in level.data[..size].chunks(row_size).rev() {
+            for block in row.chunks(block_size) {
+                let mut block = block.to_vec();
+                match self.format {
+                    CompressedFormat::Bc3 => {
+                        flip_bc4_alpha(&mut block[0..8], rows);
+                        flip_bc1(&mut block[8..16], rows);
+                    },
+                    _ => flip_bc1(&mut block, rows),
+                }
+                data.extend_from_slice(&block);
+            }
+        }
+        Some(data)
+    }
+
+    pub fn format(&self)      -> CompressedFormat       { self.format            }
+    pub fn is_srgb(&self)     -> bool                   { self.srgb              }
+    pub fn is_bottom_up(&self) -> bool                  { self.bottom_up         }
+    pub fn levels(&self)      -> &Vec<CompressedLevel>  { &self.levels           }
+    pub fn width(&self)       -> u32                    { self.levels[0].width   }
+    pub fn height(&self)      -> u32                    { self.levels[0].height  }
+}
+
+// A level count that goes past a 1x1 level is broken
+fn max_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+fn level_dimensions(width: u32, height: u32, level: u32) -> (u32, u32) {
+    (width.checked_shr(level).unwrap_or(0).max(1), height.checked_shr(level).unwrap_or(0).max(1))
+}
+
+fn to_usize(value: u64) -> Result<usize, CompressedTextureError> {
+    value.try_into().map_err(|_| CompressedTextureError::Truncated)
+}
+
+// The value of the KTXorientation key, empty when the file doesn't have one
+fn ktx2_orientation(bytes: &[u8], offset: usize, length: usize) -> Result<Vec<u8>, CompressedTextureError> {
+    let data = read_bytes(bytes, offset, length)?;
+    let mut position = 0;
+    while position + 4 <= data.len() {
+        let entry_length = read_u32(&data, position)? as usize;
+        let entry = read_bytes(&data, position + 4, entry_length)?;
+        if let Some(value) = entry.strip_prefix(b"KTXorientation\0") {
+            return Ok(value.iter().take_while(|c| **c != 0).copied().collect());
+        }
+        // Entries are padded to 4 bytes
+        position = position + 4 + entry_length.div_ceil(4) * 4;
+    }
+    Ok(Vec::new())
+}
+
+fn read_bytes(bytes: &[u8], offset: usize, length: usize) -> Result<Vec<u8>, CompressedTextureError> {
+    let end = offset.checked_add(length).ok_or(CompressedTextureError::Truncated)?;
+    match bytes.get(offset..end) {
+        Some(data) => Ok(data.to_vec()),
+        None => Err(CompressedTextureError::Truncated),
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, CompressedTextureError> {
+    match bytes.get(offset..offset + 4) {
+        Some(b) => Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        None => Err(CompressedTextureError::Truncated),
+    }
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, CompressedTextureError> {
+    Ok(read_u32(bytes, offset)? as u64 | (read_u32(bytes, offset + 4)? as u64) << 32)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// BC1 / BC3
+
+fn rgb565(color: u16) -> [u8; 4] {
+    let r = ((color >> 11) & 31) as u8;
+    let g = ((color >> 5) & 63) as u8;
+    let b = (color & 31) as u8;
+    [(r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2), 255]
+}
+
+// BC3 always uses the four colour palette, plain BC1 blocks may switch to three colours and black or transparent.
+fn decode_bc1(block: &[u8], three_color_mode: bool, transparent_black: bool, pixels: &mut [[u8; 4]; 16]) {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+
+    let e0 = rgb565(c0);
+    let e1 = rgb565(c1);
+    let mut palette = [e0, e1, [0; 4], [0; 4]];
+
+    if c0 > c1 || !three_color_mode {
+        for i in 0..3 {
+            palette[2][i] = ((2 * e0[i] as u32 + e1[i] as u32) / 3) as u8;
+            palette[3][i] = ((e0[i] as u32 + 2 * e1[i] as u32) / 3) as u8;
+        }
+        palette[2][3] = 255;
+        palette[3][3] = 255;
+    } else {
+        for i in 0..3 {
+            palette[2][i] = ((e0[i] as u32 + e1[i] as u32) / 2) as u8;
+        }
+        palette[2][3] = 255;
+        palette[3][3] = if transparent_black { 0 } else { 255 };
+    }
+
+    for i in 0..16 {
+        pixels[i] = palette[((indices >> (i * 2)) & 3) as usize];
+    }
+}
+
+fn decode_bc4_alpha(block: &[u8], pixels: &mut [[u8; 4]; 16]) {
+    let a0 = block[0] as u32;
+    let a1 = block[1] as u32;
+    let mut indices = 0u64;
+    for i in 0..6 {
+        indices |= (block[2 + i] as u64) << (i * 8);
+    }
+
+    let mut palette = [0u8; 8];
+    palette[0] = a0 as u8;
+    palette[1] = a1 as u8;
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i + 1] = (((7 - i as u32) * a0 + i as u32 * a1) / 7) as u8;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = (((5 - i as u32) * a0 + i as u32 * a1) / 5) as u8;
+        }
+        palette[6] = 0;
+        palette[7] = 255;
+    }
+
+    for i in 0..16 {
+        pixels[i][3] = palette[((indices >> (i * 3)) & 7) as usize];
+    }
+}
+
+fn decode_bc3(block: &[u8], pixels: &mut [[u8; 4]; 16]) {
+    decode_bc1(&block[8..16], false, false, pixels);
+    decode_bc4_alpha(&block[0..8], pixels);
+}
+
+// Every row of pixels has a byte of 2 bit indices, only the first rows rows are flipped
+fn flip_bc1(block: &mut [u8], rows: usize) {
+    block[4..4 + rows].reverse();
+}
+
+// Every row of pixels has 12 bits of 3 bit indices, only the first rows rows are flipped
+fn flip_bc4_alpha(block: &mut [u8], rows: usize) {
+    let mut indices = 0u64;
+    for i in 0..6 {
+        indices |= (block[2 + i] as u64) << (i * 8);
+    }
+    let mut flipped = indices & !((1u64 << (rows * 12)) - 1);
+    for row in 0..rows {
+        flipped |= ((indices >> (row * 12)) & 0xFFF) << ((rows - 1 - row) * 12);
+    }
+    for i in 0..6 {
+        block[2 + i] = (flipped >> (i * 8)) as u8;
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// BC7
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn read(&mut self, count: usize) -> u32 {
+        let mut value = 0;
+        for i in 0..count {
+            let bit = (self.bytes[self.position >> 3] >> (self.position & 7)) & 1;
+            value |= (bit as u32) << i;
+            self.position += 1;
+        }
+        value
+    }
+}
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: usize,
+    rotation_bits: usize,
+    index_selection_bits: usize,
+    color_bits: usize,
+    alpha_bits: usize,
+    endpoint_pbits: bool,
+    shared_pbits: bool,
+    index_bits: usize,
+    index_bits_2: usize,
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode { subsets: 3, partition_bits: 4, rotation_bits: 0, index_selection_bits: 0, color_bits: 4, alpha_bits: 0, endpoint_pbits: true,  shared_pbits: false, index_bits: 3, index_bits_2: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 6, alpha_bits: 0, endpoint_pbits: false, shared_pbits: true,  index_bits: 3, index_bits_2: 0 },
+    Bc7Mode { subsets: 3, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 0, endpoint_pbits: false, shared_pbits: false, index_bits: 2, index_bits_2: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 0, endpoint_pbits: true,  shared_pbits: false, index_bits: 2, index_bits_2: 0 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 1, color_bits: 5, alpha_bits: 6, endpoint_pbits: false, shared_pbits: false, index_bits: 2, index_bits_2: 3 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 0, color_bits: 7, alpha_bits: 8, endpoint_pbits: false, shared_pbits: false, index_bits: 2, index_bits_2: 2 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 7, endpoint_pbits: true,  shared_pbits: false, index_bits: 4, index_bits_2: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 5, endpoint_pbits: true,  shared_pbits: false, index_bits: 2, index_bits_2: 0 },
+];
+
+// One bit per pixel, pixel 0 in the lowest bit.
+const BC7_PARTITIONS_2: [u16; 64] = [
+    0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80, 0xC800, 0xFFEC, 0xFE80, 0xE800, 0xFFE8, 0xFF00, 0xFFF0, 0xF000,
+    0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE, 0x088C, 0x3110, 0x6666, 0x366C, 0x17E8, 0x0FF0, 0x718E, 0x399C,
+    0xAAAA, 0xF0F0, 0x5A5A, 0x33CC, 0x3C3C, 0x55AA, 0x9696, 0xA55A, 0x73CE, 0x13C8, 0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660,
+    0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C, 0x9336, 0x9CC6, 0x817E, 0xE718, 0xCCF0, 0x0FCC, 0x7744, 0xEE22,
+];
+
+// Two bits per pixel, pixel 0 in the lowest bits.
+const BC7_PARTITIONS_3: [u32; 64] = [
+    0xAA685050, 0x6A5A5040, 0x5A5A4200, 0x5450A0A8, 0xA5A50000, 0xA0A05050, 0x5555A0A0, 0x5A5A5050,
+    0xAA550000, 0xAA555500, 0xAAAA5500, 0x90909090, 0x94949494, 0xA4A4A4A4, 0xA9A59450, 0x2A0A4250,
+    0xA5945040, 0x0A425054, 0xA5A5A500, 0x55A0A0A0, 0xA8A85454, 0x6A6A4040, 0xA4A45000, 0x1A1A0500,
+    0x0050A4A4, 0xAAA59090, 0x14696914, 0x69691400, 0xA08585A0, 0xAA821414, 0x50A4A450, 0x6A5A0200,
+    0xA9A58000, 0x5090A0A8, 0xA8A09050, 0x24242424, 0x00AA5500, 0x24924924, 0x24499224, 0x50A50A50,
+    0x500AA550, 0xAAAA4444, 0x66660000, 0xA5A0A5A0, 0x50A050A0, 0x69286928, 0x44AAAA44, 0x66666600,
+    0xAA444444, 0x54A854A8, 0x95809580, 0x96969600, 0xA85454A8, 0x80959580, 0xAA141414, 0x96960000,
+    0xAAAA1414, 0xA05050A0, 0xA0A5A5A0, 0x96000000, 0x40804080, 0xA9A8A9A8, 0xAAAAAA44, 0x2A4A5254,
+];
+
+const BC7_ANCHORS_2: [usize; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
+    15,  2,  8,  2,  2,  8,  8, 15,  2,  8,  2,  2,  8,  8,  2,  2,
+    15, 15,  6,  8,  2,  8, 15, 15,  2,  8,  2,  2,  2, 15, 15,  6,
+     6,  2,  6,  8, 15, 15,  2,  2, 15, 15, 15, 15, 15,  2,  2, 15,
+];
+
+const BC7_ANCHORS_3_SECOND: [usize; 64] = [
+     3,  3, 15, 15,  8,  3, 15, 15,  8,  8,  6,  6,  6,  5,  3,  3,
+     3,  3,  8, 15,  3,  3,  6, 10,  5,  8,  8,  6,  8,  5, 15, 15,
+     8, 15,  3,  5,  6, 10,  8, 15, 15,  3, 15,  5, 15, 15, 15, 15,
+     3, 15,  5,  5,  5,  8,  5, 10,  5, 10,  8, 13, 15, 12,  3,  3,
+];
+
+const BC7_ANCHORS_3_THIRD: [usize; 64] = [
+    15,  8,  8,  3, 15, 15,  3,  8, 15, 15, 15, 15, 15, 15, 15,  8,
+    15,  8, 15,  3, 15,  8, 15,  8,  3, 15,  6, 10, 15, 15, 10,  8,
+    15,  3, 15, 10, 10,  8,  9, 10,  6, 15,  8, 15,  3,  6,  6,  8,
+    15,  3, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,  3, 15, 15,  8,
+];
+
+const BC7_WEIGHTS_2: [u32; 4]  = [0, 21, 43, 64];
+const BC7_WEIGHTS_3: [u32; 8]  = [0, 9, 18, 27, 37, 46, 55, 64];
+const BC7_WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn bc7_weight(bits: usize, index: u32) -> u32 {
+    match bits {
+        2 => BC7_WEIGHTS_2[index as usize],
+        3 => BC7_WEIGHTS_3[index as usize],
+        _ => BC7_WEIGHTS_4[index as usize],
+    }
+}
+
+fn bc7_subset(mode: &Bc7Mode, partition: usize, pixel: usize) -> usize {
+    match mode.subsets {
+        2 => ((BC7_PARTITIONS_2[partition] >> pixel) & 1) as usize,
+        3 => ((BC7_PARTITIONS_3[partition] >> (pixel * 2)) & 3) as usize,
+        _ => 0,
+    }
+}
+
+fn bc7_is_anchor(mode: &Bc7Mode, partition: usize, pixel: usize) -> bool {
+    match mode.subsets {
+        2 => pixel == 0 || pixel == BC7_ANCHORS_2[partition],
+        3 => pixel == 0 || pixel == BC7_ANCHORS_3_SECOND[partition] || pixel == BC7_ANCHORS_3_THIRD[partition],
+        _ => pixel == 0,
+    }
+}
+
+fn decode_bc7(block: &[u8], pixels: &mut [[u8; 4]; 16]) {
+    let mode_index = match (0..8).find(|bit| (block[0] >> bit) & 1 == 1) {
+        Some(mode_index) => mode_index,
+        None => {
+            // Reserved mode, the specification decodes it as transparent black.
+            *pixels = [[0; 4]; 16];
+            return;
+        }
+    };
+
+    let mode = &BC7_MODES[mode_index];
+    let mut reader = BitReader { bytes: block, position: mode_index + 1 };
+
+    let partition = reader.read(mode.partition_bits) as usize;
+    let rotation = reader.read(mode.rotation_bits);
+    let index_selection = reader.read(mode.index_selection_bits);
+
+    let endpoint_count = mode.subsets * 2;
+    let mut endpoints = [[0u32; 4]; 6];
+
+    for channel in 0..3 {
+        for endpoint in endpoints.iter_mut().take(endpoint_count) {
+            endpoint[channel] = reader.read(mode.color_bits);
+        }
+    }
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        endpoint[3] = if mode.alpha_bits > 0 { reader.read(mode.alpha_bits) } else { 255 };
+    }
+
+    let mut color_bits = mode.color_bits;
+    let mut alpha_bits = mode.alpha_bits;
+    let channels = if mode.alpha_bits > 0 { 4 } else { 3 };
+
+    if mode.endpoint_pbits {
+        for endpoint in endpoints.iter_mut().take(endpoint_count) {
+            let pbit = reader.read(1);
+            for channel in endpoint.iter_mut().take(channels) {
+                *channel = (*channel << 1) | pbit;
+            }
+        }
+        color_bits += 1;
+        if alpha_bits > 0 { alpha_bits += 1; }
+    } else if mode.shared_pbits {
+        for subset in 0..mode.subsets {
+            let pbit = reader.read(1);
+            for endpoint in &mut endpoints[subset * 2..subset * 2 + 2] {
+                for channel in endpoint.iter_mut().take(channels) {
+                    *channel = (*channel << 1) | pbit;
+                }
+            }
+        }
+        color_bits += 1;
+    }
+
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        for channel in endpoint.iter_mut().take(3) {
+            *channel <<= 8 - color_bits;
+            *channel |= *channel >> color_bits;
+        }
+        if alpha_bits > 0 {
+            endpoint[3] <<= 8 - alpha_bits;
+            endpoint[3] |= endpoint[3] >> alpha_bits;
+        }
+    }
+
+    let mut color_indices = [0u32; 16];
+    let mut alpha_indices = [0u32; 16];
+
+    for (pixel, index) in color_indices.iter_mut().enumerate() {
+        let anchor = bc7_is_anchor(mode, partition, pixel);
+        *index = reader.read(if anchor { mode.index_bits - 1 } else { mode.index_bits });
+    }
+    if mode.index_bits_2 > 0 {
+        for (pixel, index) in alpha_indices.iter_mut().enumerate() {
+            *index = reader.read(if pixel == 0 { mode.index_bits_2 - 1 } else { mode.index_bits_2 });
+        }
+    } else {
+        alpha_indices = color_indices;
+    }
+
+    let (mut color_index_bits, mut alpha_index_bits) = (mode.index_bits, if mode.index_bits_2 > 0 { mode.index_bits_2 } else { mode.index_bits });
+    if index_selection == 1 {
+        std::mem::swap(&mut color_indices, &mut alpha_indices);
+        std::mem::swap(&mut color_index_bits, &mut alpha_index_bits);
+    }
+
+    for pixel in 0..16 {
+        let subset = bc7_subset(mode, partition, pixel);
+        let e0 = endpoints[subset * 2];
+        let e1 = endpoints[subset * 2 + 1];
+
+        let color_weight = bc7_weight(color_index_bits, color_indices[pixel]);
+        let alpha_weight = bc7_weight(alpha_index_bits, alpha_indices[pixel]);
+
+        let mut color = [0u8; 4];
+        for channel in 0..3 {
+            color[channel] = (((64 - color_weight) * e0[channel] + color_weight * e1[channel] + 32) >> 6) as u8;
+        }
+        color[3] = (((64 - alpha_weight) * e0[3] + alpha_weight * e1[3] + 32) >> 6) as u8;
+
+        match rotation {
+            1 => color.swap(0, 3),
+            2 => color.swap(1, 3),
+            3 => color.swap(2, 3),
+            _ => {}
+        }
+
+        pixels[pixel] = color;
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// ETC2 / EAC
+
+const ETC_MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8], [5, 17], [9, 29], [13, 42], [18, 60], [24, 80], [33, 106], [47, 183],
+];
+
+const ETC_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+fn bits(block: u64, high: u32, low: u32) -> i32 {
+    ((block >> low) & ((1 << (high - low + 1)) - 1)) as i32
+}
+
+fn clamp8(value: i32) -> u8 { value.clamp(0, 255) as u8 }
+
+fn extend4(value: i32) -> i32 { (value << 4) | value }
+fn extend5(value: i32) -> i32 { (value << 3) | (value >> 2) }
+fn extend6(value: i32) -> i32 { (value << 2) | (value >> 4) }
+fn extend7(value: i32) -> i32 { (value << 1) | (value >> 6) }
+
+fn etc_pixel_index(block: u64, x: usize, y: usize) -> usize {
+    let i = x * 4 + y;
+    let lsb = (block >> i) & 1;
+    let msb = (block >> (i + 16)) & 1;
+    ((msb << 1) | lsb) as usize
+}
+
+fn decode_etc2_rgb(block: &[u8], punchthrough: bool, pixels: &mut [[u8; 4]; 16]) {
+    let block = u64::from_be_bytes([block[0], block[1], block[2], block[3], block[4], block[5], block[6], block[7]]);
+
+    // In the punchthrough format the diff bit is the opaque bit and every block is differential.
+    let diff_bit = bits(block, 33, 33) == 1;
+    let differential = punchthrough || diff_bit;
+    let opaque = !punchthrough || diff_bit;
+
+    if differential {
+        let r = bits(block, 63, 59);
+        let g = bits(block, 55, 51);
+        let b = bits(block, 47, 43);
+        let dr = (bits(block, 58, 56) << 29) >> 29;
+        let dg = (bits(block, 50, 48) << 29) >> 29;
+        let db = (bits(block, 42, 40) << 29) >> 29;
+
+        if r + dr < 0 || r + dr > 31 {
+            decode_etc2_t(block, opaque, pixels);
+            return;
+        }
+        if g + dg < 0 || g + dg > 31 {
+            decode_etc2_h(block, opaque, pixels);
+            return;
+        }
+        if b + db < 0 || b + db > 31 {
+            decode_etc2_planar(block, pixels);
+            return;
+        }
+
+        let base = [
+            [extend5(r), extend5(g), extend5(b)],
+            [extend5(r + dr), extend5(g + dg), extend5(b + db)],
+        ];
+        decode_etc_subblocks(block, base, opaque, pixels);
+    } else {
+        let base = [
+            [extend4(bits(block, 63, 60)), extend4(bits(block, 55, 52)), extend4(bits(block, 47, 44))],
+            [extend4(bits(block, 59, 56)), extend4(bits(block, 51, 48)), extend4(bits(block, 43, 40))],
+        ];
+        decode_etc_subblocks(block, base, true, pixels);
+    }
+}
+
+fn decode_etc_subblocks(block: u64, base: [[i32; 3]; 2], opaque: bool, pixels: &mut [[u8; 4]; 16]) {
+    let codewords = [bits(block, 39, 37) as usize, bits(block, 36, 34) as usize];
+    let flip = bits(block, 32, 32) == 1;
+
+    for y in 0..4 {
+        for x in 0..4 {
+            let subblock = if flip { (y >= 2) as usize } else { (x >= 2) as usize };
+            let modifiers = ETC_MODIFIERS[codewords[subblock]];
+            let index = etc_pixel_index(block, x, y);
+
+            if !opaque && index == 2 {
+                pixels[x + y * 4] = [0, 0, 0, 0];
+                continue;
+            }
+
+            let modifier = match index {
+                0 => if opaque { modifiers[0] } else { 0 },
+                1 => modifiers[1],
+                2 => -modifiers[0],
+                _ => -modifiers[1],
+            };
+
+            let color = base[subblock];
+            pixels[x + y * 4] = [clamp8(color[0] + modifier), clamp8(color[1] + modifier), clamp8(color[2] + modifier), 255];
+        }
+    }
+}
+
+fn decode_etc_paint(block: u64, paint: [[i32; 3]; 4], opaque: bool, pixels: &mut [[u8; 4]; 16]) {
+    for y in 0..4 {
+        for x in 0..4 {
+            let index = etc_pixel_index(block, x, y);
+            if !opaque && index == 2 {
+                pixels[x + y * 4] = [0, 0, 0, 0];
+                continue;
+            }
+            let color = paint[index];
+            pixels[x + y * 4] = [clamp8(color[0]), clamp8(color[1]), clamp8(color[2]), 255];
+        }
+    }
+}
+
+fn decode_etc2_t(block: u64, opaque: bool, pixels: &mut [[u8; 4]; 16]) {
+    let c1 = [
+        extend4((bits(block, 60, 59) << 2) | bits(block, 57, 56)),
+        extend4(bits(block, 55, 52)),
+        extend4(bits(block, 51, 48)),
+    ];
+    let c2 = [
+        extend4(bits(block, 47, 44)),
+        extend4(bits(block, 43, 40)),
+        extend4(bits(block, 39, 36)),
+    ];
+    let d = ETC_DISTANCES[((bits(block, 35, 34) << 1) | bits(block, 32, 32)) as usize];
+
+    let paint = [
+        c1,
+        [c2[0] + d, c2[1] + d, c2[2] + d],
+        c2,
+        [c2[0] - d, c2[1] - d, c2[2] - d],
+    ];
+    decode_etc_paint(block, paint, opaque, pixels);
+}
+
+fn decode_etc2_h(block: u64, opaque: bool, pixels: &mut [[u8; 4]; 16]) {
+    let r1 = bits(block, 62, 59);
+    let g1 = (bits(block, 58, 56) << 1) | bits(block, 52, 52);
+    let b1 = (bits(block, 51, 51) << 3) | bits(block, 49, 47);
+    let r2 = bits(block, 46, 43);
+    let g2 = bits(block, 42, 39);
+    let b2 = bits(block, 38, 35);
+
+    let order = (((r1 << 8) | (g1 << 4) | b1) >= ((r2 << 8) | (g2 << 4) | b2)) as i32;
+    let d = ETC_DISTANCES[((bits(block, 34, 34) << 2) | (bits(block, 32, 32) << 1) | order) as usize];
+
+    let c1 = [extend4(r1), extend4(g1), extend4(b1)];
+    let c2 = [extend4(r2), extend4(g2), extend4(b2)];
+
+    let paint = [
+        [c1[0] + d, c1[1] + d, c1[2] + d],
+        [c1[0] - d, c1[1] - d, c1[2] - d],
+        [c2[0] + d, c2[1] + d, c2[2] + d],
+        [c2[0] - d, c2[1] - d, c2[2] - d],
+    ];
+    decode_etc_paint(block, paint, opaque, pixels);
+}
+
+fn decode_etc2_planar(block: u64, pixels: &mut [[u8; 4]; 16]) {
+    let o = [
+        extend6(bits(block, 62, 57)),
+        extend7((bits(block, 56, 56) << 6) | bits(block, 54, 49)),
+        extend6((bits(block, 48, 48) << 5) | (bits(block, 44, 43) << 3) | bits(block, 41, 39)),
+    ];
+    let h = [
+        extend6((bits(block, 38, 34) << 1) | bits(block, 32, 32)),
+        extend7(bits(block, 31, 25)),
+        extend6(bits(block, 24, 19)),
+    ];
+    let v = [
+        extend6(bits(block, 18, 13)),
+        extend7(bits(block, 12, 6)),
+        extend6(bits(block, 5, 0)),
+    ];
+
+    for y in 0..4 {
+        for x in 0..4 {
+            let mut color = [0u8; 4];
+            for channel in 0..3 {
+                let value = x as i32 * (h[channel] - o[channel]) + y as i32 * (v[channel] - o[channel]) + 4 * o[channel] + 2;
+                color[channel] = clamp8(value >> 2);
+            }
+            color[3] = 255;
+            pixels[x + y * 4] = color;
+        }
+    }
+}
+
+fn decode_eac_alpha(block: &[u8], pixels: &mut [[u8; 4]; 16]) {
+    let block = u64::from_be_bytes([block[0], block[1], block[2], block[3], block[4], block[5], block[6], block[7]]);
+
+    let base = bits(block, 63, 56);
+    let multiplier = bits(block, 55, 52);
+    let modifiers = EAC_MODIFIERS[bits(block, 51, 48) as usize];
+
+    for x in 0..4 {
+        for y in 0..4 {
+            let shift = 45 - (x * 4 + y) * 3;
+            let index = ((block >> shift) & 7) as usize;
+            pixels[x + y * 4][3] = clamp8(base + modifiers[index] * multiplier);
+        }
+    }
+}
+
+fn decode_etc2_rgba(block: &[u8], pixels: &mut [[u8; 4]; 16]) {
+    decode_etc2_rgb(&block[8..16], false, pixels);
+    decode_eac_alpha(&block[0..8], pixels);
+}
@@ -21,13 +21,80 @@
  *   SOFTWARE.
  */
 
-use crate::texture;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use engine_core::error_log;
 use freetype::Library;
- 
+use freetype::tt_os2::TrueTypeOS2Table;
+
+use crate::bmfont::BitmapFont;
+use crate::sdf::{DistanceField, Outline};
+use crate::texture::{self, CompositeOptions, Image, ImageBlendMode, Texture, TextureRegion};
+
+const INITIAL_ATLAS_SIZE: u32 = 256;
+const DEFAULT_MAX_ATLAS_SIZE: u32 = 2048;
+const GLYPH_PADDING: u32 = 1;
+
+// Glyphs are identified by the face in the fallback chain they come from and their index in that face
+type GlyphKey = (usize, u32);
+
 pub struct Font {
-    pub atlas: std::rc::Rc::<texture::Texture>,
-    glyphs: std::collections::HashMap::<char, Glyph>,
-    width: u32,
+    library: Option<Library>,
+    faces: Vec<freetype::Face>,
+    bitmap: Option<BitmapFont>,
+    #[cfg(feature = "shaping")]
+    face_data: Vec<(Rc<Vec<u8>>, u32)>,
+    #[cfg(feature = "shaping")]
+    shaping: bool,
+    cache: RefCell<GlyphCache>,
+    size: u32,
+    metrics: FontMetrics,
+    render_mode: FontRenderMode,
+}
+
+// Metrics of the primary face in pixels. All of them are positive, ascender, x height, cap height and the
+// strikeout go up from the baseline while descender and underline go down from it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FontMetrics {
+    pub size: f32,
+    pub ascender: f32,
+    pub descender: f32,
+    pub line_gap: f32,
+    pub line_height: f32,
+    pub x_height: f32,
+    pub cap_height: f32,
+    pub underline_position: f32,
+    pub underline_thickness: f32,
+    pub strikeout_position: f32,
+    pub strikeout_thickness: f32,
+}
+
+// Distance field glyphs stay sharp when scaled and are drawn with the outline, glow and shadow of TextEffects.
+// spread is how many pixels around the outline the distance is stored for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FontRenderMode {
+    Bitmap,
+    Sdf { spread: u32 },
+    Msdf { spread: u32 },
+}
+
+// A glyph placed by Font::shape, x and y are the pen position in pixels relative to the start of the run
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PositionedGlyph {
+    pub face: usize,
+    pub glyph_index: u32,
+    pub cluster: usize, // byte offset of the first character in the text the glyph was made from
+    pub x: f32,
+    pub y: f32,
+    pub advance: f32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GlyphRun {
+    pub glyphs: Vec<PositionedGlyph>,
+    pub width: f32,
 }
 
 #[derive(Clone)]
@@ -37,133 +104,852 @@ pub struct Glyph {
     advance: f32,
     texture: texture::TextureRegion,
 }
-    
-impl Font {
-    pub fn new(filepath: &str, size: u32) -> Font {
-        let font_height = size * 2;
-        let font_width = size;
-
-        // Init the library
-        let lib = Library::init().unwrap();
-        // Load a font face
-        let face = lib.new_face(filepath, 0).unwrap();
-        // Set the font size
-        face.set_pixel_sizes(font_width, font_height).unwrap();
-        // Load a character
-        
-        let mut glyphs = std::collections::HashMap::new();
-
-        let mut image_pack = texture::ImagePack::new();
-
-        for i in 33..127 {
-            face.load_char(i as usize as usize, freetype::face::LoadFlag::RENDER).unwrap();
-            // Get the glyph instance
-            let glyph = face.glyph();
-            let bitmap = glyph.bitmap();
-            
-            let width = bitmap.width();
-            let height = bitmap.rows();
-
-            let bearing_x = glyph.bitmap_left() as f32;
-            let bearing_y = glyph.bitmap_top() as f32;
-
-            let advance = (glyph.advance().x >> 6) as f32;
-
-            let mut bitmap_converted = texture::Image::from_color(width as u32, height as u32, 0x00_00_00_00);
-
-            for x in 0..width {
-                for y in 0..height {
-                    let pixel = bitmap.buffer()[(x + y * width) as usize];
-                    bitmap_converted.set_r8(x as u32, (height - y - 1) as u32, pixel);
-                    bitmap_converted.set_g8(x as u32, (height - y - 1) as u32, pixel);
-                    bitmap_converted.set_b8(x as u32, (height - y - 1) as u32, pixel);
-                    bitmap_converted.set_a8(x as u32, (height - y - 1) as u32, pixel);
+
+struct GlyphEntry {
+    glyph: Glyph,
+    bitmap: Option<Image>,
+    location: Option<(u32, u32)>,
+    last_used: u64,
+}
+
+struct GlyphCache {
+    chars: HashMap<char, Option<GlyphKey>>,
+    glyphs: HashMap<GlyphKey, GlyphEntry>,
+    atlas: Image,
+    packer: ShelfPacker,
+    texture: Option<Rc<Texture>>,
+    retired: Vec<Rc<Texture>>,
+    max_atlas_size: u32,
+    linear_filter: bool,
+    stamp: u64,
+}
+
+// Packs glyphs into rows, a glyph goes into the lowest row it fits in or starts a new one
+struct ShelfPacker {
+    width: u32,
+    height: u32,
+    shelves: Vec<(u32, u32, u32)>, // y, height and how far the row is filled
+    bottom: u32,
+}
+
+impl ShelfPacker {
+    fn new(width: u32, height: u32) -> ShelfPacker {
+        ShelfPacker {
+            width,
+            height,
+            shelves: Vec::new(),
+            bottom: 0,
+        }
+    }
+
+    fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let mut best: Option<usize> = None;
+        for (i, &(_, shelf_height, filled)) in self.shelves.iter().enumerate() {
+            if shelf_height >= height && filled + width <= self.width && shelf_height <= height + height / 2 + 2
+                && best.is_none_or(|b| shelf_height < self.shelves[b].1) {
+                best = Some(i);
+            }
+        }
+
+        if let Some(i) = best {
+            let shelf = &mut self.shelves[i];
+            let x = shelf.2;
+            shelf.2 += width;
+            return Some((x, shelf.0));
+        }
+
+        if width <= self.width && self.bottom + height <= self.height {
+            self.shelves.push((self.bottom, height, width));
+            self.bottom += height;
+            return Some((0, self.bottom - height));
+        }
+
+        None
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+    }
+}
+
+impl FontMetrics {
+    // Typical proportions for faces that don't have the metrics
+    fn estimate(size: f32) -> FontMetrics {
+        let thickness = (size / 16.0).max(1.0);
+        FontMetrics {
+            size,
+            ascender: size * 0.8,
+            descender: size * 0.2,
+            line_gap: 0.0,
+            line_height: size,
+            x_height: size * 0.5,
+            cap_height: size * 0.7,
+            underline_position: size * 0.1,
+            underline_thickness: thickness,
+            strikeout_position: size * 0.25,
+            strikeout_thickness: thickness,
+        }
+    }
+
+    fn from_face(face: &mut freetype::Face, size: u32) -> FontMetrics {
+        let mut metrics = FontMetrics::estimate(size as f32);
+        let size_metrics = match face.size_metrics() {
+            Some(size_metrics) => size_metrics,
+            None => return metrics,
+        };
+
+        metrics.ascender = size_metrics.ascender as f32 / 64.0;
+        metrics.descender = -size_metrics.descender as f32 / 64.0;
+        metrics.line_height = size_metrics.height as f32 / 64.0;
+        metrics.line_gap = (metrics.line_height - metrics.ascender - metrics.descender).max(0.0);
+        metrics.cap_height = glyph_top(face, 'H').unwrap_or(metrics.ascender * 0.7);
+        metrics.x_height = glyph_top(face, 'x').unwrap_or(metrics.cap_height * 0.7);
+        metrics.strikeout_position = metrics.x_height / 2.0;
+
+        if face.is_scalable() {
+            let scale = size_metrics.y_scale as f32 / 65536.0 / 64.0;
+            metrics.underline_position = -face.underline_position() as f32 * scale;
+            metrics.underline_thickness = (face.underline_thickness() as f32 * scale).max(1.0);
+            metrics.strikeout_thickness = metrics.underline_thickness;
+
+            if let Some(os2) = TrueTypeOS2Table::from_face(face) {
+                if os2.x_height() > 0 {
+                    metrics.x_height = os2.x_height() as f32 * scale;
+                }
+                if os2.y_strikeout_position() > 0 {
+                    metrics.strikeout_position = os2.y_strikeout_position() as f32 * scale;
+                }
+                if os2.y_strikeout_size() > 0 {
+                    metrics.strikeout_thickness = (os2.y_strikeout_size() as f32 * scale).max(1.0);
                 }
             }
+        }
+
+        metrics
+    }
 
-            let size = (width as f32, height as f32);
-            let bearing = (bearing_x as f32, bearing_y as f32);
+    fn from_bitmap_font(font: &BitmapFont) -> FontMetrics {
+        let descriptor = font.descriptor();
+        let size = if descriptor.size != 0 { descriptor.size.abs() as f32 } else { descriptor.line_height as f32 };
+        let base = descriptor.base as f32;
+        let top = |c: char| font.char(c as u32).filter(|character| character.height > 0).map(|character| base - character.y_offset as f32);
 
-            let glyph = Glyph {
-                size,
-                bearing,
-                advance,
-                texture: texture::TextureRegion::new_invalid(),
+        let mut metrics = FontMetrics::estimate(size);
+        metrics.ascender = base;
+        metrics.descender = (descriptor.line_height as f32 - base).max(0.0);
+        metrics.line_height = descriptor.line_height as f32;
+        metrics.cap_height = top('H').unwrap_or(base * 0.7);
+        metrics.x_height = top('x').unwrap_or(metrics.cap_height * 0.7);
+        metrics.underline_position = (metrics.descender / 2.0).max(1.0);
+        metrics.strikeout_position = metrics.x_height / 2.0;
+        metrics
+    }
+}
+
+fn glyph_top(face: &freetype::Face, c: char) -> Option<f32> {
+    let index = face.get_char_index(c as usize);
+    if index == 0 || face.load_glyph(index, freetype::face::LoadFlag::NO_BITMAP | freetype::face::LoadFlag::NO_HINTING).is_err() {
+        return None;
+    }
+    Some(face.glyph().metrics().horiBearingY as f32 / 64.0)
+}
+
+impl GlyphCache {
+    fn new() -> GlyphCache {
+        GlyphCache {
+            chars: HashMap::new(),
+            glyphs: HashMap::new(),
+            atlas: Image::from_color(INITIAL_ATLAS_SIZE, INITIAL_ATLAS_SIZE, 0x00_00_00_00),
+            packer: ShelfPacker::new(INITIAL_ATLAS_SIZE, INITIAL_ATLAS_SIZE),
+            texture: None,
+            retired: Vec::new(),
+            max_atlas_size: DEFAULT_MAX_ATLAS_SIZE,
+            linear_filter: false,
+            stamp: 0,
+        }
+    }
+
+    fn create_texture(&mut self) {
+        let texture = Texture::from_image(&self.atlas);
+        if self.linear_filter {
+            texture.set_linear_filter(true);
+        }
+        self.texture = Some(texture);
+        self.update_regions();
+    }
+
+    fn retire_texture(&mut self) {
+        if let Some(texture) = self.texture.take() {
+            self.retired.push(texture);
+        }
+    }
+
+    fn grow(&mut self) -> bool {
+        let width = self.atlas.width();
+        let height = self.atlas.height();
+        if width >= self.max_atlas_size && height >= self.max_atlas_size {
+            return false;
+        }
+
+        let (width, height) = if width <= height { ((width * 2).min(self.max_atlas_size), height) } else { (width, (height * 2).min(self.max_atlas_size)) };
+        self.atlas.resize(width, height);
+        self.packer.resize(width, height);
+        self.retire_texture();
+        true
+    }
+
+    // Starts over with an empty atlas and moves back the most recently used glyphs until half of it is used,
+    // glyphs used by the text currently being cached are always kept
+    fn evict(&mut self) {
+        let empty = Image::from_color(self.atlas.width(), self.atlas.height(), 0x00_00_00_00);
+        let old_atlas = std::mem::replace(&mut self.atlas, empty);
+        self.packer = ShelfPacker::new(old_atlas.width(), old_atlas.height());
+        self.retire_texture();
+
+        let mut keys: Vec<GlyphKey> = self.glyphs.iter().filter(|(_, entry)| entry.location.is_some()).map(|(key, _)| *key).collect();
+        keys.sort_by_key(|key| std::cmp::Reverse(self.glyphs[key].last_used));
+
+        let budget = old_atlas.width() as u64 * old_atlas.height() as u64 / 2;
+        let mut used = 0;
+
+        for key in keys {
+            let stamp = self.stamp;
+            let entry = self.glyphs.get_mut(&key).unwrap();
+            let (old_x, old_y) = entry.location.take().unwrap();
+            entry.glyph.texture = TextureRegion::new_invalid();
+
+            let width = entry.glyph.size.0 as u32;
+            let height = entry.glyph.size.1 as u32;
+            let area = (width + GLYPH_PADDING) as u64 * (height + GLYPH_PADDING) as u64;
+            if entry.last_used != stamp && used + area > budget {
+                continue;
+            }
+
+            if let Some((x, y)) = self.packer.allocate(width + GLYPH_PADDING, height + GLYPH_PADDING) {
+                let mut options = CompositeOptions::new();
+                options.blend_mode = ImageBlendMode::Replace;
+                self.atlas.composite(&old_atlas, (old_x, old_y, width, height), x as i32, y as i32, &options);
+                entry.location = Some((x, y));
+                used += area;
+            }
+        }
+    }
+
+    // Makes sure the glyph has a place in the atlas, returns the rect that has to be uploaded
+    fn place(&mut self, key: GlyphKey) -> Option<(u32, u32, u32, u32)> {
+        let stamp = self.stamp;
+        let entry = self.glyphs.get_mut(&key)?;
+        entry.last_used = stamp;
+        if entry.location.is_some() {
+            return None;
+        }
+
+        let width = entry.glyph.size.0 as u32;
+        let height = entry.glyph.size.1 as u32;
+        if width == 0 || height == 0 {
+            return None;
+        }
+
+        let mut location = self.packer.allocate(width + GLYPH_PADDING, height + GLYPH_PADDING);
+        while location.is_none() && self.grow() {
+            location = self.packer.allocate(width + GLYPH_PADDING, height + GLYPH_PADDING);
+        }
+        if location.is_none() {
+            self.evict();
+            location = self.packer.allocate(width + GLYPH_PADDING, height + GLYPH_PADDING);
+        }
+
+        let (x, y) = match location {
+            Some(location) => location,
+            None => {
+                error_log!("Glyph of size {}x{} doesn't fit in a {}x{} glyph atlas", width, height, self.atlas.width(), self.atlas.height());
+                return None;
+            }
+        };
+
+        let entry = self.glyphs.get_mut(&key).unwrap();
+        entry.location = Some((x, y));
+        if let Some(bitmap) = entry.bitmap.take() {
+            let mut options = CompositeOptions::new();
+            options.blend_mode = ImageBlendMode::Replace;
+            self.atlas.composite(&bitmap, (0, 0, width, height), x as i32, y as i32, &options);
+        }
+        Some((x, y, width, height))
+    }
+
+    fn update_regions(&mut self) {
+        let texture = match &self.texture {
+            Some(texture) => texture.clone(),
+            None => return,
+        };
+        for entry in self.glyphs.values_mut() {
+            entry.glyph.texture = match entry.location {
+                Some((x, y)) => TextureRegion::new(x, y, entry.glyph.size.0 as u32, entry.glyph.size.1 as u32, &texture),
+                None => TextureRegion::new_invalid(),
             };
+        }
+    }
+}
+
+fn rasterize(face: &freetype::Face, glyph_index: u32) -> Option<(Glyph, Image)> {
+    if let Err(e) = face.load_glyph(glyph_index, freetype::face::LoadFlag::RENDER | freetype::face::LoadFlag::COLOR) {
+        error_log!("Failed to load glyph {}: {}", glyph_index, e);
+        return None;
+    }
+
+    let slot = face.glyph();
+    let bitmap = slot.bitmap();
+    let width = bitmap.width().max(0) as u32;
+    let height = bitmap.rows().max(0) as u32;
+    let pitch = bitmap.pitch().unsigned_abs() as usize;
+    // Empty glyphs like space have no buffer at all
+    let buffer = if width > 0 && height > 0 { bitmap.buffer() } else { &[] };
+    let pixel_mode = bitmap.pixel_mode();
+
+    // FreeType bitmaps start with the top row while Image starts with the bottom one
+    let mut image = Image::from_color(width, height, 0x00_00_00_00);
+    for y in 0..height {
+        let row = &buffer[y as usize * pitch..];
+        for x in 0..width {
+            let color = match pixel_mode {
+                Ok(freetype::bitmap::PixelMode::Bgra) => {
+                    let px = &row[x as usize * 4..x as usize * 4 + 4];
+                    (px[2] as u32) << 24 | (px[1] as u32) << 16 | (px[0] as u32) << 8 | px[3] as u32
+                },
+                Ok(freetype::bitmap::PixelMode::Mono) => {
+                    if row[x as usize / 8] & (0x80 >> (x % 8)) != 0 { 0xFF_FF_FF_FF } else { 0x00_00_00_00 }
+                },
+                _ => {
+                    let coverage = row[x as usize] as u32;
+                    coverage << 24 | coverage << 16 | coverage << 8 | coverage
+                },
+            };
+            image.set_rgba8(x, height - y - 1, color);
+        }
+    }
 
-            glyphs.insert(i as u8 as char, glyph);
+    let glyph = Glyph {
+        size: (width as f32, height as f32),
+        bearing: (slot.bitmap_left() as f32, slot.bitmap_top() as f32),
+        advance: (slot.advance().x >> 6) as f32,
+        texture: TextureRegion::new_invalid(),
+    };
+    Some((glyph, image))
+}
+
+// Bitmap font glyphs are taken from the pages as they are, the distance field modes only make sense for fonts
+// that were exported as distance fields
+fn rasterize_bitmap_font(font: &BitmapFont, id: u32) -> Option<(Glyph, Image)> {
+    let character = font.char(id)?;
+    let image = font.glyph_image(id)?;
+    let glyph = Glyph {
+        size: (character.width as f32, character.height as f32),
+        bearing: (character.x_offset as f32, font.descriptor().base as f32 - character.y_offset as f32),
+        advance: character.x_advance as f32,
+        texture: TextureRegion::new_invalid(),
+    };
+    Some((glyph, image))
+}
+
+fn rasterize_distance_field(face: &freetype::Face, glyph_index: u32, field: DistanceField, spread: u32) -> Option<(Glyph, Image)> {
+    if let Err(e) = face.load_glyph(glyph_index, freetype::face::LoadFlag::NO_BITMAP | freetype::face::LoadFlag::NO_HINTING) {
+        error_log!("Failed to load glyph {}: {}", glyph_index, e);
+        return None;
+    }
+
+    let slot = face.glyph();
+    let advance = slot.advance().x as f32 / 64.0;
+    let point = |v: &freetype::Vector| (v.x as f32 / 64.0, v.y as f32 / 64.0);
 
-            image_pack.add_image(&(i as u8 as char).to_string(), bitmap_converted);
+    let mut outline = Outline::new();
+    if let Some(glyph_outline) = slot.outline() {
+        for contour in glyph_outline.contours_iter() {
+            let start = point(contour.start());
+            outline.move_to(start.0, start.1);
+            for curve in contour {
+                match curve {
+                    freetype::outline::Curve::Line(p) => {
+                        let p = point(&p);
+                        outline.line_to(p.0, p.1);
+                    },
+                    freetype::outline::Curve::Bezier2(c, p) => {
+                        let (c, p) = (point(&c), point(&p));
+                        outline.quad_to(c.0, c.1, p.0, p.1);
+                    },
+                    freetype::outline::Curve::Bezier3(c1, c2, p) => {
+                        let (c1, c2, p) = (point(&c1), point(&c2), point(&p));
+                        outline.cubic_to(c1.0, c1.1, c2.0, c2.1, p.0, p.1);
+                    },
+                }
+            }
         }
-        
-        let atlas = texture::TextureAtlas::from_image_pack(&image_pack);
-        
-        for (k, v) in glyphs.iter_mut() {
-            v.texture = atlas.get(&k.to_string());
+    }
+
+    // Glyphs without an outline, like space or bitmap only emoji, are left empty
+    if outline.is_empty() {
+        let glyph = Glyph { size: (0.0, 0.0), bearing: (0.0, 0.0), advance, texture: TextureRegion::new_invalid() };
+        return Some((glyph, Image::from_color(0, 0, 0x00_00_00_00)));
+    }
+
+    let (left, bottom, right, top) = outline.bounds();
+    let x = left.floor() - spread as f32;
+    let y = bottom.floor() - spread as f32;
+    let width = (right.ceil() - left.floor()) as u32 + spread * 2;
+    let height = (top.ceil() - bottom.floor()) as u32 + spread * 2;
+    let image = outline.distance_field(field, width, height, x, y, spread as f32);
+
+    let glyph = Glyph {
+        size: (width as f32, height as f32),
+        bearing: (x, y + height as f32),
+        advance,
+        texture: TextureRegion::new_invalid(),
+    };
+    Some((glyph, image))
+}
+
+impl Font {
+    pub fn new(filepath: &str, size: u32) -> Font {
+        Font::with_face(filepath, 0, size)
+    }
+
+    // face_index picks a font in a .ttc collection
+    pub fn with_face(filepath: &str, face_index: u32, size: u32) -> Font {
+        match std::fs::read(filepath) {
+            Ok(data) => Font::load(data, face_index, size, filepath),
+            Err(e) => {
+                error_log!("Failed to load font: {}\n{}", filepath, e);
+                Font::new_invalid()
+            }
         }
+    }
 
-        Font {
-            atlas: atlas.texture(),
-            glyphs,
-            width: size,
+    pub fn from_bytes(bytes: Vec<u8>, face_index: u32, size: u32) -> Font {
+        Font::load(bytes, face_index, size, "<memory>")
+    }
+
+    fn load(data: Vec<u8>, face_index: u32, size: u32, name: &str) -> Font {
+        let library = match Library::init() {
+            Ok(library) => library,
+            Err(e) => {
+                error_log!("Failed to initialize FreeType: {}", e);
+                return Font::new_invalid();
+            }
+        };
+
+        let mut font = Font {
+            library: Some(library),
+            faces: Vec::new(),
+            bitmap: None,
+            #[cfg(feature = "shaping")]
+            face_data: Vec::new(),
+            #[cfg(feature = "shaping")]
+            shaping: true,
+            cache: RefCell::new(GlyphCache::new()),
+            size,
+            metrics: FontMetrics::estimate(size as f32),
+            render_mode: FontRenderMode::Bitmap,
+        };
+
+        if !font.add_face(Rc::new(data), face_index, name) {
+            return Font::new_invalid();
+        }
+        font
+    }
+
+    // AngelCode BMFont .fnt files in the text or binary format, the page images are loaded from next to the file
+    pub fn from_bmfont(filepath: &str) -> Font {
+        match BitmapFont::from_file(filepath) {
+            Ok(bitmap_font) => Font::from_bitmap_font(bitmap_font),
+            Err(e) => {
+                error_log!("Failed to load bitmap font: {}\n{:?}", filepath, e);
+                Font::new_invalid()
+            }
         }
     }
 
+    // Bitmap fonts don't need FreeType, they have a fixed size and can't have fallbacks
+    pub fn from_bitmap_font(bitmap_font: BitmapFont) -> Font {
+        let metrics = FontMetrics::from_bitmap_font(&bitmap_font);
+        let mut font = Font::new_invalid();
+        font.size = metrics.size as u32;
+        font.metrics = metrics;
+        font.bitmap = Some(bitmap_font);
+        font
+    }
+
     pub fn new_invalid() -> Font {
         Font {
-            atlas: texture::Texture::from_color(1, 1, 0xFF_FF_FF_FF),
-            glyphs: std::collections::HashMap::new(),
-            width: 1,
+            library: None,
+            faces: Vec::new(),
+            bitmap: None,
+            #[cfg(feature = "shaping")]
+            face_data: Vec::new(),
+            #[cfg(feature = "shaping")]
+            shaping: true,
+            cache: RefCell::new(GlyphCache::new()),
+            size: 1,
+            metrics: FontMetrics::estimate(1.0),
+            render_mode: FontRenderMode::Bitmap,
         }
     }
 
-    pub fn text_width(&self, text: &str) -> f32 {
-        let mut text_width = 0.0;
+    // Number of fonts in a .ttc collection, 1 for regular font files
+    pub fn face_count(bytes: &[u8]) -> u32 {
+        if bytes.len() >= 12 && &bytes[0..4] == b"ttcf" {
+            u32::from_be_bytes([bytes[8], bytes[9], bytes[10], bytes[11]])
+        } else {
+            1
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        !self.faces.is_empty() || self.bitmap.is_some()
+    }
 
-        let no_glyph_advance = match self.glyph('?') {
-            Some(g) => g.advance,
-            None => self.width as f32,
+    fn face_total(&self) -> usize {
+        if self.bitmap.is_some() { 1 } else { self.faces.len() }
+    }
+
+    // Glyphs missing in the font are looked up in its fallbacks, in the order they were added
+    pub fn add_fallback(&mut self, filepath: &str) -> bool {
+        match std::fs::read(filepath) {
+            Ok(data) => self.add_face(Rc::new(data), 0, filepath),
+            Err(e) => {
+                error_log!("Failed to load font: {}\n{}", filepath, e);
+                false
+            }
+        }
+    }
+
+    pub fn add_fallback_bytes(&mut self, bytes: Vec<u8>, face_index: u32) -> bool {
+        self.add_face(Rc::new(bytes), face_index, "<memory>")
+    }
+
+    fn add_face(&mut self, data: Rc<Vec<u8>>, face_index: u32, name: &str) -> bool {
+        if self.bitmap.is_some() {
+            error_log!("Can't add the fallback font {} to a bitmap font", name);
+            return false;
+        }
+
+        let library = match &self.library {
+            Some(library) => library,
+            None => {
+                error_log!("Can't add the fallback font {} to an invalid font", name);
+                return false;
+            }
+        };
+
+        let mut face = match library.new_memory_face(data.clone(), face_index as isize) {
+            Ok(face) => face,
+            Err(e) => {
+                error_log!("Failed to load font: {}\n{}", name, e);
+                return false;
+            }
         };
 
-        for (i, c) in text.chars().enumerate() {
+        if let Err(e) = face.set_pixel_sizes(0, self.size) {
+            error_log!("Failed to set the size of font: {}\n{}", name, e);
+            return false;
+        }
 
-            if i == 0 {
-                match self.glyph(c) {
-                    Some(g) => {
-                        text_width += g.size().0 + g.bearing().0;
-                    }
-                    None => {
-                        text_width += no_glyph_advance;
-                    }
+        // Layout follows the primary face
+        if self.faces.is_empty() {
+            self.metrics = FontMetrics::from_face(&mut face, self.size);
+        }
+
+        self.faces.push(face);
+        #[cfg(feature = "shaping")]
+        self.face_data.push((data, face_index));
+        // Characters that were missing before might be in the new face
+        self.cache.borrow_mut().chars.retain(|_, key| key.is_some());
+        true
+    }
+
+    fn glyph_key(&self, c: char) -> Option<GlyphKey> {
+        if let Some(key) = self.cache.borrow().chars.get(&c) {
+            return *key;
+        }
+
+        // Bitmap fonts use the character code as the glyph index
+        let key = match &self.bitmap {
+            Some(bitmap) => bitmap.char(c as u32).map(|_| (0, c as u32)),
+            None => self.faces.iter().enumerate()
+                .map(|(i, face)| (i, face.get_char_index(c as usize)))
+                .find(|(_, index)| *index != 0),
+        };
+
+        if let Some(key) = key {
+            if !self.load_glyph(key) {
+                return None;
+            }
+        }
+
+        self.cache.borrow_mut().chars.insert(c, key);
+        key
+    }
+
+    fn load_glyph(&self, key: GlyphKey) -> bool {
+        if self.cache.borrow().glyphs.contains_key(&key) {
+            return true;
+        }
+
+        if let Some(bitmap) = &self.bitmap {
+            return match rasterize_bitmap_font(bitmap, key.1) {
+                Some((glyph, bitmap)) => {
+                    self.cache.borrow_mut().glyphs.insert(key, GlyphEntry { glyph, bitmap: Some(bitmap), location: None, last_used: 0 });
+                    true
+                },
+                None => false,
+            };
+        }
+
+        let face = &self.faces[key.0];
+        let rasterized = match self.render_mode {
+            FontRenderMode::Bitmap => rasterize(face, key.1),
+            FontRenderMode::Sdf { spread } => rasterize_distance_field(face, key.1, DistanceField::Sdf, spread),
+            FontRenderMode::Msdf { spread } => rasterize_distance_field(face, key.1, DistanceField::Msdf, spread),
+        };
+
+        match rasterized {
+            Some((glyph, bitmap)) => {
+                self.cache.borrow_mut().glyphs.insert(key, GlyphEntry { glyph, bitmap: Some(bitmap), location: None, last_used: 0 });
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn face_kerning(&self, face: usize, left: u32, right: u32) -> f32 {
+        if let Some(bitmap) = &self.bitmap {
+            return bitmap.kerning(left, right) as f32;
+        }
+        match self.faces[face].get_kerning(left, right, freetype::face::KerningMode::KerningDefault) {
+            Ok(kerning) => (kerning.x >> 6) as f32,
+            Err(_) => 0.0,
+        }
+    }
+
+    pub fn kerning(&self, left: char, right: char) -> f32 {
+        match (self.glyph_key(left), self.glyph_key(right)) {
+            (Some(left), Some(right)) if left.0 == right.0 => self.face_kerning(left.0, left.1, right.1),
+            _ => 0.0,
+        }
+    }
+
+    // Turns text into glyphs placed along the baseline. Without the shaping feature every character becomes
+    // one glyph spaced with the kern table, with it the text is shaped with rustybuzz which also applies GPOS
+    // kerning, ligatures and the rules of complex scripts. Bitmap fonts are never shaped and use their kerning
+    // pairs. Missing characters are replaced with '?'.
+    pub fn shape(&self, text: &str) -> GlyphRun {
+        let mut run = GlyphRun::default();
+
+        #[cfg(feature = "shaping")]
+        {
+            if self.shaping && self.bitmap.is_none() {
+                for (start, end, face) in self.segments(text) {
+                    self.shape_segment(&text[start..end], start, face, &mut run);
                 }
-            } else if i == text.len() - 1 {
-                match &self.glyph(c) {
-                    Some(g) => {
-                        text_width += g.advance() - g.bearing().0;
-                    }
-                    None => {
-                        text_width += no_glyph_advance;
-                    }
+                return run;
+            }
+        }
+
+        let mut previous: Option<GlyphKey> = None;
+        for (i, c) in text.char_indices() {
+            let key = match self.glyph_key(c).or_else(|| self.glyph_key('?')) {
+                Some(key) => key,
+                None => continue,
+            };
+
+            if let Some(previous) = previous {
+                if previous.0 == key.0 {
+                    run.width += self.face_kerning(key.0, previous.1, key.1);
                 }
-            } else {
-                match &self.glyph(c) {
-                    Some(g) => {
-                        text_width += g.advance();
-                    }
-                    None => {
-                        text_width += no_glyph_advance;
-                    }
+            }
+
+            let advance = self.cache.borrow().glyphs[&key].glyph.advance;
+            run.glyphs.push(PositionedGlyph { face: key.0, glyph_index: key.1, cluster: i, x: run.width, y: 0.0, advance });
+            run.width += advance;
+            previous = Some(key);
+        }
+
+        run
+    }
+
+    // Splits text into pieces that are covered by the same face
+    #[cfg(feature = "shaping")]
+    fn segments(&self, text: &str) -> Vec<(usize, usize, usize)> {
+        let mut segments: Vec<(usize, usize, usize)> = Vec::new();
+        for (i, c) in text.char_indices() {
+            let end = i + c.len_utf8();
+            let face = self.glyph_key(c).or_else(|| self.glyph_key('?')).map(|key| key.0);
+            match (segments.last_mut(), face) {
+                (Some(segment), Some(face)) if segment.2 == face => segment.1 = end,
+                (Some(segment), None) => segment.1 = end,
+                (_, Some(face)) => segments.push((i, end, face)),
+                (None, None) => {},
+            }
+        }
+        segments
+    }
+
+    #[cfg(feature = "shaping")]
+    fn shape_segment(&self, text: &str, offset: usize, face: usize, run: &mut GlyphRun) {
+        let (data, face_index) = &self.face_data[face];
+        let shaper_face = match rustybuzz::Face::from_slice(data, *face_index) {
+            Some(shaper_face) => shaper_face,
+            None => {
+                error_log!("Failed to parse font face {} for shaping", face);
+                return;
+            }
+        };
+
+        let mut buffer = rustybuzz::UnicodeBuffer::new();
+        buffer.push_str(text);
+        buffer.guess_segment_properties();
+        let output = rustybuzz::shape(&shaper_face, &[], buffer);
+
+        let scale = self.size as f32 / shaper_face.units_per_em() as f32;
+
+        for (info, position) in output.glyph_infos().iter().zip(output.glyph_positions()) {
+            let advance = position.x_advance as f32 * scale;
+            let key = if info.glyph_id == 0 { self.glyph_key('?') } else { Some((face, info.glyph_id)) };
+            let key = match key {
+                Some(key) if self.load_glyph(key) => key,
+                _ => {
+                    run.width += advance;
+                    continue;
                 }
+            };
+
+            run.glyphs.push(PositionedGlyph {
+                face: key.0,
+                glyph_index: key.1,
+                cluster: offset + info.cluster as usize,
+                x: run.width + position.x_offset as f32 * scale,
+                y: position.y_offset as f32 * scale,
+                advance,
+            });
+            run.width += advance;
+        }
+    }
+
+    // Shaping is on by default when the feature is enabled
+    #[cfg(feature = "shaping")]
+    pub fn set_shaping(&mut self, shaping: bool) {
+        self.shaping = shaping;
+    }
+
+    // Rasterizes and uploads every glyph in text that isn't in the atlas yet. Returns true if the atlas texture
+    // was replaced, anything already batched with the old texture has to be flushed before drawing more text.
+    pub fn cache_text(&self, text: &str) -> bool {
+        self.cache_run(&self.shape(text))
+    }
+
+    pub fn cache_run(&self, run: &GlyphRun) -> bool {
+        self.cache_glyphs(&run.glyphs)
+    }
+
+    pub fn cache_glyphs<'a, I: IntoIterator<Item = &'a PositionedGlyph>>(&self, glyphs: I) -> bool {
+        let keys: Vec<GlyphKey> = glyphs.into_iter().map(|glyph| (glyph.face, glyph.glyph_index)).collect();
+
+        let mut cache = self.cache.borrow_mut();
+        // Whoever got true back last time has flushed by now
+        cache.retired.clear();
+        cache.stamp += 1;
+
+        // Marked up front so eviction never throws out a glyph this text needs
+        let stamp = cache.stamp;
+        for key in &keys {
+            if let Some(entry) = cache.glyphs.get_mut(key) {
+                entry.last_used = stamp;
+            }
+        }
+
+        let mut dirty = Vec::new();
+        for key in keys {
+            if let Some(rect) = cache.place(key) {
+                dirty.push(rect);
             }
         }
 
-        
-        text_width
+        // Evicted glyphs have to be rasterized again
+        for key in cache.glyphs.iter().filter(|(_, entry)| entry.location.is_none() && entry.bitmap.is_none()).map(|(key, _)| *key).collect::<Vec<_>>() {
+            cache.glyphs.remove(&key);
+            cache.chars.retain(|_, value| *value != Some(key));
+        }
+
+        let replaced = !cache.retired.is_empty();
+        match cache.texture.clone() {
+            Some(texture) if !replaced => {
+                if !dirty.is_empty() {
+                    texture.bind(0);
+                    for (x, y, width, height) in dirty {
+                        texture.set_pixels_u32(x, y, width, height, cache.atlas.crop(x, y, width, height).get_buffer());
+                    }
+                    cache.update_regions();
+                }
+            },
+            _ => cache.create_texture(),
+        }
+
+        replaced
+    }
+
+    // The metrics are always valid but the texture only is once the glyph has been cached with cache_text
+    pub fn glyph(&self, c: char) -> Option<Glyph> {
+        let key = self.glyph_key(c)?;
+        self.cache.borrow().glyphs.get(&key).map(|entry| entry.glyph.clone())
+    }
+
+    pub fn shaped_glyph(&self, glyph: &PositionedGlyph) -> Option<Glyph> {
+        let key = (glyph.face, glyph.glyph_index);
+        if key.0 >= self.face_total() || !self.load_glyph(key) {
+            return None;
+        }
+        self.cache.borrow().glyphs.get(&key).map(|entry| entry.glyph.clone())
+    }
+
+    // A method instead of the old pub atlas field, the texture is created lazily and replaced when the atlas grows
+    pub fn atlas(&self) -> Rc<Texture> {
+        let mut cache = self.cache.borrow_mut();
+        if cache.texture.is_none() {
+            cache.create_texture();
+        }
+        cache.texture.clone().unwrap()
+    }
+
+    // Every glyph is generated again in the new mode
+    pub fn set_render_mode(&mut self, render_mode: FontRenderMode) {
+        self.render_mode = render_mode;
+        let mut cache = self.cache.borrow_mut();
+        let max_atlas_size = cache.max_atlas_size;
+        *cache = GlyphCache::new();
+        cache.max_atlas_size = max_atlas_size;
+        cache.linear_filter = render_mode != FontRenderMode::Bitmap;
+    }
+
+    pub fn render_mode(&self) -> FontRenderMode {
+        self.render_mode
     }
 
-    pub fn glyph(&self, glyph: char)     -> Option<&Glyph>  {  self.glyphs.get(&glyph)   }
-    pub fn width(&self)                  -> u32             {  self.width                }
-    pub fn height(&self)                 -> u32             {  self.width*2              }
+    // The atlas grows up to this size before old glyphs are evicted
+    pub fn set_max_atlas_size(&mut self, size: u32) {
+        self.cache.borrow_mut().max_atlas_size = size.max(INITIAL_ATLAS_SIZE);
+    }
+
+    pub fn text_width(&self, text: &str) -> f32 {
+        self.shape(text).width
+    }
+
+    pub fn metrics(&self) -> FontMetrics {
+        self.metrics
+    }
+
+    // The pixel size the font was loaded with
+    pub fn size(&self)                   -> u32             {  self.size                 }
+    pub fn width(&self)                  -> u32             {  self.size                 }
+    pub fn height(&self)                 -> u32             {  self.metrics.line_height.ceil() as u32 }
     
 }
 
@@ -175,4 +961,4 @@ impl Glyph {
     pub fn size(&self)      -> (f32, f32)               { self.size             }
     pub fn bearing(&self)   -> (f32, f32)               { self.bearing          }
     pub fn advance(&self)   -> f32                      { self.advance          }        
-}
\ No newline at end of file
+}
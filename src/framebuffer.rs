@@ -26,6 +26,7 @@ use engine_core::{error_log, info_log};
 use crate::texture::Texture;
 use std::rc::Rc;
 
+#[derive(Debug)]
 pub enum FrameBufferError {
     Undefined,
     IncompleteAttachment,
@@ -35,6 +36,9 @@ pub enum FrameBufferError {
     Unsupported,
     IncompleteMultisample,
     IncompleteLayerTargets,
+    InvalidSize(u32, u32),
+    TooManyColorAttachments(usize),
+    UnsupportedSampleCount(u32),
 }
 
 impl From<u32> for FrameBufferError {
@@ -56,62 +60,367 @@ impl From<u32> for FrameBufferError {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorFormat {
+    Rgba8,
+    Srgb8Alpha8,
+    Rgb10A2,
+    Rgba16f,
+    Rgba32f,
+    R11fG11fB10f,
+    R8,
+    Rg8,
+    R16f,
+    Rg16f,
+    R32f,
+    R32ui,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DepthStencilFormat {
+    Depth16,
+    Depth24,
+    Depth32f,
+    Stencil8,
+    Depth24Stencil8,
+    Depth32fStencil8,
+}
+
+// Renderbuffers are a bit faster to render to but can't be sampled afterwards
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AttachmentStorage {
+    Texture,
+    Renderbuffer,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthStencilAttachment {
+    pub format: DepthStencilFormat,
+    pub storage: AttachmentStorage,
+}
+
+// Colour attachment i is written by fragment shader output location i. With more than one sample the attachments
+// are multisampled, as textures they can only be read with sampler2DMS so they are usually resolved into a
+// framebuffer with a single sample before being drawn.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameBufferDescriptor {
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,
+    pub color_attachments: Vec<ColorFormat>,
+    pub color_storage: AttachmentStorage,
+    pub depth_stencil: Option<DepthStencilAttachment>,
+}
+
+enum Attachment {
+    Texture(Rc<Texture>),
+    MultisampleTexture(u32),
+    Renderbuffer(u32),
+}
+
 pub struct FrameBuffer {
     gl_buffer_id: u32,
-    texture: Rc<Texture>,
+    descriptor: FrameBufferDescriptor,
+    color_attachments: Vec<Attachment>,
+    depth_stencil: Option<Attachment>,
+}
+
+impl ColorFormat {
+    // Internal format, pixel format and data type
+    pub fn gl_formats(&self) -> (u32, u32, u32) {
+        match self {
+            Self::Rgba8         => (gl::RGBA8,          gl::RGBA,           gl::UNSIGNED_BYTE),
+            Self::Srgb8Alpha8   => (gl::SRGB8_ALPHA8,   gl::RGBA,           gl::UNSIGNED_BYTE),
+            Self::Rgb10A2       => (gl::RGB10_A2,       gl::RGBA,           gl::UNSIGNED_INT_2_10_10_10_REV),
+            Self::Rgba16f       => (gl::RGBA16F,        gl::RGBA,           gl::HALF_FLOAT),
+            Self::Rgba32f       => (gl::RGBA32F,        gl::RGBA,           gl::FLOAT),
+            Self::R11fG11fB10f  => (gl::R11F_G11F_B10F, gl::RGB,            gl::UNSIGNED_INT_10F_11F_11F_REV),
+            Self::R8            => (gl::R8,             gl::RED,            gl::UNSIGNED_BYTE),
+            Self::Rg8           => (gl::RG8,            gl::RG,             gl::UNSIGNED_BYTE),
+            Self::R16f          => (gl::R16F,           gl::RED,            gl::HALF_FLOAT),
+            Self::Rg16f         => (gl::RG16F,          gl::RG,             gl::HALF_FLOAT),
+            Self::R32f          => (gl::R32F,           gl::RED,            gl::FLOAT),
+            Self::R32ui         => (gl::R32UI,          gl::RED_INTEGER,    gl::UNSIGNED_INT),
+        }
+    }
+}
+
+impl DepthStencilFormat {
+    pub fn gl_formats(&self) -> (u32, u32, u32) {
+        match self {
+            Self::Depth16           => (gl::DEPTH_COMPONENT16,  gl::DEPTH_COMPONENT,    gl::UNSIGNED_SHORT),
+            Self::Depth24           => (gl::DEPTH_COMPONENT24,  gl::DEPTH_COMPONENT,    gl::UNSIGNED_INT),
+            Self::Depth32f          => (gl::DEPTH_COMPONENT32F, gl::DEPTH_COMPONENT,    gl::FLOAT),
+            Self::Stencil8          => (gl::STENCIL_INDEX8,     gl::STENCIL_INDEX,      gl::UNSIGNED_BYTE),
+            Self::Depth24Stencil8   => (gl::DEPTH24_STENCIL8,   gl::DEPTH_STENCIL,      gl::UNSIGNED_INT_24_8),
+            Self::Depth32fStencil8  => (gl::DEPTH32F_STENCIL8,  gl::DEPTH_STENCIL,      gl::FLOAT_32_UNSIGNED_INT_24_8_REV),
+        }
+    }
+
+    pub fn attachment_point(&self) -> u32 {
+        match self {
+            Self::Depth16 | Self::Depth24 | Self::Depth32f => gl::DEPTH_ATTACHMENT,
+            Self::Stencil8 => gl::STENCIL_ATTACHMENT,
+            Self::Depth24Stencil8 | Self::Depth32fStencil8 => gl::DEPTH_STENCIL_ATTACHMENT,
+        }
+    }
+
+    pub fn has_depth(&self) -> bool {
+        *self != Self::Stencil8
+    }
+
+    pub fn has_stencil(&self) -> bool {
+        matches!(self, Self::Stencil8 | Self::Depth24Stencil8 | Self::Depth32fStencil8)
+    }
+}
+
+impl FrameBufferDescriptor {
+    // A single RGBA8 colour attachment and no depth or stencil
+    pub fn new(width: u32, height: u32) -> FrameBufferDescriptor {
+        FrameBufferDescriptor {
+            width,
+            height,
+            samples: 1,
+            color_attachments: vec![ColorFormat::Rgba8],
+            color_storage: AttachmentStorage::Texture,
+            depth_stencil: None,
+        }
+    }
+
+    pub fn with_samples(mut self, samples: u32) -> FrameBufferDescriptor {
+        self.samples = samples.max(1);
+        self
+    }
+
+    pub fn with_color_attachment(mut self, format: ColorFormat) -> FrameBufferDescriptor {
+        self.color_attachments.push(format);
+        self
+    }
+
+    pub fn with_depth_stencil(mut self, format: DepthStencilFormat, storage: AttachmentStorage) -> FrameBufferDescriptor {
+        self.depth_stencil = Some(DepthStencilAttachment { format, storage });
+        self
+    }
 }
 
 impl FrameBuffer {
     pub fn new(width: u32, height: u32) -> FrameBuffer {
-        let texture = Texture::from_color(width, height, 0xFF_00_00_FF);
-        texture.bind(0);
+        let (frame_buffer, error) = FrameBuffer::create(FrameBufferDescriptor::new(width, height));
+        if let Some(error) = error {
+            error_log!("Failed to create OpenGL Framebuffer object! ERROR CODE : {:?}", error);
+        }
+        frame_buffer
+    }
+
+    pub fn from_descriptor(descriptor: &FrameBufferDescriptor) -> Result<FrameBuffer, FrameBufferError> {
+        if descriptor.width == 0 || descriptor.height == 0 {
+            return Err(FrameBufferError::InvalidSize(descriptor.width, descriptor.height));
+        }
 
+        let mut max_draw_buffers = 0;
+        unsafe {
+            gl_call!(gl::GetIntegerv(gl::MAX_DRAW_BUFFERS, &mut max_draw_buffers));
+        }
+        if descriptor.color_attachments.len() > max_draw_buffers.max(1) as usize {
+            return Err(FrameBufferError::TooManyColorAttachments(descriptor.color_attachments.len()));
+        }
+
+        let mut max_samples = 0;
+        unsafe {
+            gl_call!(gl::GetIntegerv(gl::MAX_SAMPLES, &mut max_samples));
+        }
+        if descriptor.samples == 0 || descriptor.samples > max_samples.max(1) as u32 {
+            return Err(FrameBufferError::UnsupportedSampleCount(descriptor.samples));
+        }
+
+        match FrameBuffer::create(descriptor.clone()) {
+            (frame_buffer, None) => Ok(frame_buffer),
+            (_, Some(error)) => Err(error),
+        }
+    }
+
+    // Always hands back the framebuffer so new can keep working like before, the error is its completeness status
+    fn create(descriptor: FrameBufferDescriptor) -> (FrameBuffer, Option<FrameBufferError>) {
         unsafe {
             let mut gl_buffer_id = 0;
             gl_call!(gl::GenFramebuffers(1, &mut gl_buffer_id));
             gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, gl_buffer_id));
 
-            
-            gl_call!(gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, gl_buffer_id, 0));
-
-            if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
-                let error = match FrameBufferError::from(gl::CheckFramebufferStatus(gl::FRAMEBUFFER)) {
-                    FrameBufferError::Undefined                     => "UNDEFINED",
-                    FrameBufferError::IncompleteAttachment          => "INCOMPLETE_ATTACHMENT",
-                    FrameBufferError::IncompleteMissingAttachment   => "INCOMPLETE_MISSING_ATTACHMENT",
-                    FrameBufferError::IncompleteDrawBuffer          => "INCOMPLETE_DRAW_BUFFER",
-                    FrameBufferError::IncompleteReadBuffer          => "INCOMPLETE_READ_BUFFER",
-                    FrameBufferError::Unsupported                   => "UNSUPPORTED",
-                    FrameBufferError::IncompleteMultisample         => "INCOMPLETE_MULTISAMPLE",
-                    FrameBufferError::IncompleteLayerTargets        => "INCOMPLETE_LAYER_TARGETS",
-                };
-                error_log!("Failed to create OpenGL Framebuffer object! ERROR CODE : {}", error);
+            let mut color_attachments = Vec::new();
+            let mut draw_buffers = Vec::new();
+            for (i, format) in descriptor.color_attachments.iter().enumerate() {
+                let attachment = gl::COLOR_ATTACHMENT0 + i as u32;
+                color_attachments.push(create_attachment(&descriptor, format.gl_formats(), descriptor.color_storage, attachment));
+                draw_buffers.push(attachment);
+            }
+
+            // Depth only framebuffers, like shadow maps, draw nothing and read nothing
+            if draw_buffers.is_empty() {
+                gl_call!(gl::DrawBuffer(gl::NONE));
+                gl_call!(gl::ReadBuffer(gl::NONE));
+            } else {
+                gl_call!(gl::DrawBuffers(draw_buffers.len() as i32, draw_buffers.as_ptr()));
+                gl_call!(gl::ReadBuffer(gl::COLOR_ATTACHMENT0));
             }
-            
-            
+
+            let depth_stencil = descriptor.depth_stencil.map(|attachment| {
+                create_attachment(&descriptor, attachment.format.gl_formats(), attachment.storage, attachment.format.attachment_point())
+            });
+
+            let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+            let error = if status != gl::FRAMEBUFFER_COMPLETE { Some(FrameBufferError::from(status)) } else { None };
+
             Texture::un_bind();
             Self::un_bind();
 
-            FrameBuffer {
+            let frame_buffer = FrameBuffer {
                 gl_buffer_id,
-                texture,
-            }
+                descriptor,
+                color_attachments,
+                depth_stencil,
+            };
+            (frame_buffer, error)
         }
     }
 
+    // Multisampled framebuffers have to be resolved before their pixels can be read
     pub fn get_pixels(&self, x: u32, y: u32, width: u32, height: u32) -> Vec<u8> {
+        self.get_attachment_pixels(0, x, y, width, height)
+    }
+
+    // RGBA8 pixels of a colour attachment, other formats are converted by OpenGL
+    pub fn get_attachment_pixels(&self, attachment: usize, x: u32, y: u32, width: u32, height: u32) -> Vec<u8> {
 		let mut data = vec![0; width as usize * height as usize * 4];
+        if attachment >= self.color_attachments.len() {
+            error_log!("Framebuffer has no colour attachment {}", attachment);
+            return data;
+        }
+
         self.bind();
 		unsafe {
+			gl_call!(gl::ReadBuffer(gl::COLOR_ATTACHMENT0 + attachment as u32));
 			gl_call!(gl::ReadPixels(x as i32, y as i32, width as i32, height as i32, gl::RGBA, gl::UNSIGNED_BYTE, data.as_mut_ptr() as *mut std::ffi::c_void));
+			gl_call!(gl::ReadBuffer(gl::COLOR_ATTACHMENT0));
 		}
 		data
 	}
 
+    // The first colour attachment, always there for framebuffers made with FrameBuffer::new. Depth only,
+    // renderbuffer backed and multisampled framebuffers don't have one, use color_attachment for those.
     pub fn texture(&self) -> &Rc<Texture> {
-        &self.texture
+        self.color_attachment(0).expect("Framebuffer has no colour texture, resolve it into one that has")
+    }
+
+    // Only there for attachments stored in single sampled textures
+    pub fn color_attachment(&self, index: usize) -> Option<&Rc<Texture>> {
+        match self.color_attachments.get(index) {
+            Some(Attachment::Texture(texture)) => Some(texture),
+            _ => None,
+        }
+    }
+
+    pub fn color_attachment_count(&self) -> usize {
+        self.color_attachments.len()
+    }
+
+    pub fn depth_stencil_texture(&self) -> Option<&Rc<Texture>> {
+        match &self.depth_stencil {
+            Some(Attachment::Texture(texture)) => Some(texture),
+            _ => None,
+        }
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.descriptor.samples
+    }
+
+    // Resolves the samples into target, which has to be the same size. Every colour attachment is copied into
+    // the one at the same index and depth and stencil are copied too if both framebuffers have them.
+    pub fn resolve_into(&self, target: &FrameBuffer) {
+        if (self.width(), self.height()) != (target.width(), target.height()) {
+            error_log!("Can't resolve a {}x{} framebuffer into a {}x{} one", self.width(), self.height(), target.width(), target.height());
+            return;
+        }
+
+        let rect = (0, 0, self.width(), self.height());
+        self.blit(target, rect, rect, gl::NEAREST);
+
+        let mut mask = 0;
+        if let (Some(source), Some(destination)) = (self.descriptor.depth_stencil, target.descriptor.depth_stencil) {
+            if source.format.has_depth() && destination.format.has_depth() {
+                mask |= gl::DEPTH_BUFFER_BIT;
+            }
+            if source.format.has_stencil() && destination.format.has_stencil() {
+                mask |= gl::STENCIL_BUFFER_BIT;
+            }
+        }
+        if mask != 0 {
+            unsafe {
+                gl_call!(gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.gl_buffer_id));
+                gl_call!(gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target.gl_buffer_id));
+                gl_call!(gl::BlitFramebuffer(0, 0, self.width() as i32, self.height() as i32, 0, 0, target.width() as i32, target.height() as i32, mask, gl::NEAREST));
+            }
+        }
+
+        Self::un_bind();
+    }
+
+    // Copies the colour attachments between rects given as (x, y, width, height) in pixels and scales them if the
+    // sizes differ. Multisampled framebuffers can only be blitted without scaling.
+    pub fn blit_into(&self, target: &FrameBuffer, source: (u32, u32, u32, u32), destination: (u32, u32, u32, u32), linear: bool) {
+        self.blit(target, source, destination, if linear { gl::LINEAR } else { gl::NEAREST });
+        Self::un_bind();
+    }
+
+    // Resolves or copies the first colour attachment into the window
+    pub fn blit_to_screen(&self, destination: (u32, u32, u32, u32), linear: bool) {
+        let filter = if linear { gl::LINEAR } else { gl::NEAREST };
+        unsafe {
+            gl_call!(gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.gl_buffer_id));
+            gl_call!(gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, 0));
+            gl_call!(gl::ReadBuffer(gl::COLOR_ATTACHMENT0));
+            let (x, y, width, height) = destination;
+            gl_call!(gl::BlitFramebuffer(0, 0, self.width() as i32, self.height() as i32, x as i32, y as i32, (x + width) as i32, (y + height) as i32, gl::COLOR_BUFFER_BIT, filter));
+        }
+        Self::un_bind();
     }
 
+    fn blit(&self, target: &FrameBuffer, source: (u32, u32, u32, u32), destination: (u32, u32, u32, u32), filter: u32) {
+        let (sx, sy, sw, sh) = source;
+        let (dx, dy, dw, dh) = destination;
+        let count = self.color_attachments.len().min(target.color_attachments.len());
+
+        unsafe {
+            gl_call!(gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.gl_buffer_id));
+            gl_call!(gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target.gl_buffer_id));
+
+            // Blits write to every draw buffer, so they are pointed at one attachment at a time
+            for i in 0..count {
+                let attachment = gl::COLOR_ATTACHMENT0 + i as u32;
+                gl_call!(gl::ReadBuffer(attachment));
+                gl_call!(gl::DrawBuffers(1, &attachment));
+                gl_call!(gl::BlitFramebuffer(sx as i32, sy as i32, (sx + sw) as i32, (sy + sh) as i32, dx as i32, dy as i32, (dx + dw) as i32, (dy + dh) as i32, gl::COLOR_BUFFER_BIT, filter));
+            }
+
+            let draw_buffers: Vec<u32> = (0..target.color_attachments.len() as u32).map(|i| gl::COLOR_ATTACHMENT0 + i).collect();
+            if !draw_buffers.is_empty() {
+                gl_call!(gl::DrawBuffers(draw_buffers.len() as i32, draw_buffers.as_ptr()));
+            }
+            if count > 0 {
+                gl_call!(gl::ReadBuffer(gl::COLOR_ATTACHMENT0));
+            }
+        }
+    }
+
+    pub fn descriptor(&self) -> &FrameBufferDescriptor {
+        &self.descriptor
+    }
+
+    pub fn width(&self)     -> u32 { self.descriptor.width  }
+    pub fn height(&self)    -> u32 { self.descriptor.height }
+
+    pub(crate) fn gl_id(&self) -> u32 { self.gl_buffer_id }
+
     pub fn get_pixels_standard_frame_buffer(x: u32, y: u32, width: u32, height: u32) -> Vec<u8> {
         let mut data = vec![0; width as usize * height as usize * 4];
         FrameBuffer::un_bind();
@@ -139,8 +448,54 @@ impl FrameBuffer {
 impl Drop for FrameBuffer {
     fn drop(&mut self) {
         unsafe {
+            for attachment in self.color_attachments.iter().chain(self.depth_stencil.iter()) {
+                match attachment {
+                    Attachment::Texture(_) => {},
+                    Attachment::MultisampleTexture(texture) => {
+                        gl_call!(gl::DeleteTextures(1, texture));
+                    },
+                    Attachment::Renderbuffer(renderbuffer) => {
+                        gl_call!(gl::DeleteRenderbuffers(1, renderbuffer));
+                    },
+                }
+            }
             gl_call!(gl::DeleteFramebuffers(1, &self.gl_buffer_id));
         }
     }
 }
 
+// Creates storage for one attachment and attaches it to the bound framebuffer
+unsafe fn create_attachment(descriptor: &FrameBufferDescriptor, formats: (u32, u32, u32), storage: AttachmentStorage, point: u32) -> Attachment {
+    let (internal_format, pixel_format, data_type) = formats;
+    let (width, height, samples) = (descriptor.width as i32, descriptor.height as i32, descriptor.samples as i32);
+
+    match storage {
+        AttachmentStorage::Texture if samples > 1 => {
+            let mut texture = 0;
+            gl_call!(gl::GenTextures(1, &mut texture));
+            gl_call!(gl::BindTexture(gl::TEXTURE_2D_MULTISAMPLE, texture));
+            gl_call!(gl::TexImage2DMultisample(gl::TEXTURE_2D_MULTISAMPLE, samples, internal_format, width, height, gl::TRUE));
+            gl_call!(gl::BindTexture(gl::TEXTURE_2D_MULTISAMPLE, 0));
+            gl_call!(gl::FramebufferTexture2D(gl::FRAMEBUFFER, point, gl::TEXTURE_2D_MULTISAMPLE, texture, 0));
+            Attachment::MultisampleTexture(texture)
+        },
+        AttachmentStorage::Texture => {
+            let texture = Texture::with_storage(descriptor.width, descriptor.height, internal_format, pixel_format, data_type);
+            gl_call!(gl::FramebufferTexture2D(gl::FRAMEBUFFER, point, gl::TEXTURE_2D, texture.gl_id(), 0));
+            Attachment::Texture(texture)
+        },
+        AttachmentStorage::Renderbuffer => {
+            let mut renderbuffer = 0;
+            gl_call!(gl::GenRenderbuffers(1, &mut renderbuffer));
+            gl_call!(gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffer));
+            if samples > 1 {
+                gl_call!(gl::RenderbufferStorageMultisample(gl::RENDERBUFFER, samples, internal_format, width, height));
+            } else {
+                gl_call!(gl::RenderbufferStorage(gl::RENDERBUFFER, internal_format, width, height));
+            }
+            gl_call!(gl::BindRenderbuffer(gl::RENDERBUFFER, 0));
+            gl_call!(gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, point, gl::RENDERBUFFER, renderbuffer));
+            Attachment::Renderbuffer(renderbuffer)
+        },
+    }
+}
@@ -21,17 +21,87 @@
  *   SOFTWARE.
  */
 
-use crate::renderer::graphics_renderer::{ShapeBatchRenderer, SpriteBatchRenderer};
+use crate::renderer::graphics_renderer::{ShapeBatchRenderer, SpriteBatchRenderer, TriangleBatchRenderer};
 use crate::renderer;
+use crate::renderer::std_renderer::{BlendEquation, BlendFactor, Capability, ClearTarget, StencilFunction, StencilOperation};
 use crate::shader::Shader;
 use crate::buffer::VertexBufferLayout;
 use crate::texture;
 use crate::font;
+use crate::framebuffer::FrameBuffer;
+use crate::render_target::DrawTarget;
+use crate::camera::{Camera2D, CoordinateMode};
+use crate::path::{FillRule, Path, StrokeStyle};
+use crate::text::{self, TextBatch};
 use crate::matrix;
+use crate::vector::Vec3f;
 use crate::color::*;
 
 use engine_core::{error_log, warn_log, window};
 
+// How draws are combined with what is already in the frame. Alpha expects straight alpha, Premultiplied expects
+// colours that are already multiplied by their alpha like the output of Image::premultiply_alpha.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BlendMode {
+    Alpha,
+    Premultiplied,
+    Additive,
+    Multiply,
+    Screen,
+    Replace,
+    Custom {
+        src_rgb: BlendFactor,
+        dst_rgb: BlendFactor,
+        src_alpha: BlendFactor,
+        dst_alpha: BlendFactor,
+        rgb_equation: BlendEquation,
+        alpha_equation: BlendEquation,
+    },
+}
+
+impl BlendMode {
+    // The same factors and equation for colour and alpha
+    pub fn custom(src: BlendFactor, dst: BlendFactor, equation: BlendEquation) -> BlendMode {
+        BlendMode::Custom { src_rgb: src, dst_rgb: dst, src_alpha: src, dst_alpha: dst, rgb_equation: equation, alpha_equation: equation }
+    }
+
+    // Source and destination factors for colour and alpha and the two equations, None for Replace which turns
+    // blending off
+    pub fn factors(&self) -> Option<([BlendFactor; 4], [BlendEquation; 2])> {
+        use crate::renderer::std_renderer::BlendMode::*;
+        let add = [BlendEquation::Add, BlendEquation::Add];
+        match *self {
+            BlendMode::Alpha            => Some(([SrcAlpha, OneMinusSrcAlpha, One, OneMinusSrcAlpha], add)),
+            BlendMode::Premultiplied    => Some(([One, OneMinusSrcAlpha, One, OneMinusSrcAlpha], add)),
+            BlendMode::Additive         => Some(([SrcAlpha, One, Zero, One], add)),
+            BlendMode::Multiply         => Some(([DstColor, OneMinusSrcAlpha, Zero, One], add)),
+            BlendMode::Screen           => Some(([One, OneMinusSrcColor, One, OneMinusSrcAlpha], add)),
+            BlendMode::Replace          => None,
+            BlendMode::Custom { src_rgb, dst_rgb, src_alpha, dst_alpha, rgb_equation, alpha_equation } => {
+                Some(([src_rgb, dst_rgb, src_alpha, dst_alpha], [rgb_equation, alpha_equation]))
+            },
+        }
+    }
+
+    unsafe fn apply(&self) {
+        match self.factors() {
+            Some(([src_rgb, dst_rgb, src_alpha, dst_alpha], [rgb_equation, alpha_equation])) => {
+                renderer::std_renderer::enable(Capability::Blending);
+                renderer::std_renderer::blend_func_separate(src_rgb, dst_rgb, src_alpha, dst_alpha);
+                renderer::std_renderer::blend_equation_separate(rgb_equation, alpha_equation);
+            },
+            None => renderer::std_renderer::disable(Capability::Blending),
+        }
+    }
+}
+
+// Where content is drawn after Graphics::end_mask
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MaskMode {
+    Inside,
+    Outside,
+}
+
 #[derive(Copy, Clone, PartialEq)]
 pub enum LastDraw {
     None,
@@ -40,12 +110,14 @@ pub enum LastDraw {
     Line,
     Ellipse,
     Triangle,
+    TriangleList,
+    TexturedTriangleList,
 }
 
 struct DrawingInformation {
     color:         Color,
-    translation:        (f32, f32),
-    scale:              (f32, f32),
+    transform:          matrix::Mat3x3f,
+    blend_mode:         BlendMode,
     line_width:         f32,
     ellipse_detail:     u32,
 }
@@ -54,8 +126,8 @@ impl DrawingInformation {
     pub fn new() -> DrawingInformation {
         DrawingInformation {
             color: Color::from(0xFF_FF_FF_FF),
-            translation: (0.0, 0.0),
-            scale: (1.0, 1.0),
+            transform: matrix::Mat3x3f::identity(),
+            blend_mode: BlendMode::Alpha,
             line_width: 0.01,
             ellipse_detail: 100,
         }
@@ -66,16 +138,25 @@ pub struct Graphics {
     has_texture:    bool,
     texture:        texture::TextureRegion, 
     font:           font::Font,
+    text_rendering: text::TextRendering,
     
     shape_ren:      ShapeBatchRenderer,
     sprite_ren:     SpriteBatchRenderer,
+    triangle_ren:   TriangleBatchRenderer,
 
     frame_buffer_listener: bus::BusReader::<(u32, u32)>,
 
     frame_width:      u32,
     frame_height:     u32,
+    draw_target:      DrawTarget,
+    camera:           Camera2D,
+    camera_changed:   bool,
+    clip_stack:       Vec<(i32, i32, i32, i32)>, // scissor rects in frame pixels from the bottom left
+    mask:             Option<MaskMode>,
+    writing_mask:     bool,
 
     dw:             DrawingInformation,
+    transform_stack: Vec<matrix::Mat3x3f>,
     last_draw:      LastDraw,
 }
 
@@ -94,6 +175,9 @@ impl Graphics {
                 let mut vbl = VertexBufferLayout::new();
                 vbl.push_f32(4);
                 vbl.push_f32(1);
+                vbl.push_f32(3);
+                vbl.push_f32(3);
+                vbl.push_f32(3);
                 vbl
             }),
             sprite_ren: SpriteBatchRenderer::new(Shader::from_source(SPRITE_SHADER_SOURCE), {
@@ -101,6 +185,19 @@ impl Graphics {
                 vbl.push_f32(4);
                 vbl.push_f32(4);
                 vbl.push_f32(1);
+                vbl.push_f32(3);
+                vbl.push_f32(3);
+                vbl.push_f32(3);
+                vbl
+            }),
+            triangle_ren: TriangleBatchRenderer::new(Shader::from_source(TRIANGLE_SHADER_SOURCE), {
+                let mut vbl = VertexBufferLayout::new();
+                vbl.push_f32(2);
+                vbl.push_f32(2);
+                vbl.push_f32(1);
+                vbl.push_f32(3);
+                vbl.push_f32(3);
+                vbl.push_f32(3);
                 vbl
             }),
             
@@ -108,9 +205,17 @@ impl Graphics {
 
             frame_width: win.get_width() as u32,
             frame_height: win.get_height() as u32,
+            draw_target: DrawTarget::new(win.get_width() as u32, win.get_height() as u32),
+            camera: window_camera(win),
+            camera_changed: true,
+            clip_stack: Vec::new(),
+            mask: None,
+            writing_mask: false,
 
             dw: DrawingInformation::new(),
+            transform_stack: Vec::new(),
             last_draw: LastDraw::None,
+            text_rendering: text::TextRendering::new(),
         }
     }
 
@@ -124,6 +229,9 @@ impl Graphics {
                 let mut vbl = VertexBufferLayout::new();
                 vbl.push_f32(4);
                 vbl.push_f32(1);
+                vbl.push_f32(3);
+                vbl.push_f32(3);
+                vbl.push_f32(3);
                 vbl
             }),
             sprite_ren: SpriteBatchRenderer::new(sprite_ren, {
@@ -131,6 +239,19 @@ impl Graphics {
                 vbl.push_f32(4);
                 vbl.push_f32(4);
                 vbl.push_f32(1);
+                vbl.push_f32(3);
+                vbl.push_f32(3);
+                vbl.push_f32(3);
+                vbl
+            }),
+            triangle_ren: TriangleBatchRenderer::new(Shader::from_source(TRIANGLE_SHADER_SOURCE), {
+                let mut vbl = VertexBufferLayout::new();
+                vbl.push_f32(2);
+                vbl.push_f32(2);
+                vbl.push_f32(1);
+                vbl.push_f32(3);
+                vbl.push_f32(3);
+                vbl.push_f32(3);
                 vbl
             }),
             
@@ -138,9 +259,17 @@ impl Graphics {
 
             frame_width: win.get_width() as u32,
             frame_height: win.get_height() as u32,
+            draw_target: DrawTarget::new(win.get_width() as u32, win.get_height() as u32),
+            camera: window_camera(win),
+            camera_changed: true,
+            clip_stack: Vec::new(),
+            mask: None,
+            writing_mask: false,
 
             dw: DrawingInformation::new(),
+            transform_stack: Vec::new(),
             last_draw: LastDraw::None,
+            text_rendering: text::TextRendering::new(),
         }
     }
 
@@ -149,9 +278,11 @@ impl Graphics {
         while !loop_done {
             match self.frame_buffer_listener.try_recv() {
                 Ok((width, height)) => {
-                    unsafe { gl_call!(gl::Viewport(0, 0, width as i32, height as i32)); };
-                    self.frame_width = width;
-                    self.frame_height = height;
+                    if self.draw_target.resize_window(width, height) {
+                        self.frame_width = width;
+                        self.frame_height = height;
+                        self.resize_camera();
+                    }
                 },
                 Err(_) => loop_done = true
             }
@@ -159,6 +290,17 @@ impl Graphics {
     }
 
     pub fn flush(&mut self) {
+        if self.last_draw != LastDraw::None {
+            if self.camera_changed {
+                upload_view(&self.shape_ren.shader, &self.camera.matrix());
+                upload_view(&self.sprite_ren.shader, &self.camera.matrix());
+                upload_view(&self.triangle_ren.shader, &self.camera.matrix());
+                self.camera_changed = false;
+            }
+            // Set on every flush since other code is free to change the GL blend state in between
+            unsafe { self.dw.blend_mode.apply(); }
+        }
+
         match self.last_draw {
             LastDraw::Rect => {
                 self.shape_ren.shader.bind();
@@ -181,8 +323,19 @@ impl Graphics {
                 self.shape_ren.flush();
             },
             LastDraw::Sprite => {
+                self.text_rendering.upload(&self.sprite_ren.shader);
                 self.sprite_ren.flush();
             },
+            LastDraw::TriangleList => {
+                self.triangle_ren.shader.bind();
+                self.triangle_ren.shader.upload_from_name_1i("u_textured", 0);
+                self.triangle_ren.flush();
+            },
+            LastDraw::TexturedTriangleList => {
+                self.triangle_ren.shader.bind();
+                self.triangle_ren.shader.upload_from_name_1i("u_textured", 1);
+                self.triangle_ren.flush();
+            },
             LastDraw::None => {}
         }
         
@@ -212,15 +365,47 @@ impl Graphics {
         self.font = font;
     }
 
+    pub fn set_text_effects(&mut self, effects: text::TextEffects) {
+        self.text_rendering.effects = effects;
+    }
+
+    pub fn text_effects(&self) -> text::TextEffects {
+        self.text_rendering.effects
+    }
+
     pub fn set_color(&mut self, color: Color) {
         self.dw.color = color;
     }
 
+    // Pending draws are flushed when the mode changes
+    pub fn set_blend_mode(&mut self, mode: BlendMode) {
+        if mode != self.dw.blend_mode {
+            self.flush();
+            self.dw.blend_mode = mode;
+        }
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.dw.blend_mode
+    }
+
     pub fn clear(&mut self, color: Color) {
         self.flush();
         unsafe {
+            // Letterbox bars around a virtual resolution stay black, a clip rect only clears inside of it
+            if matches!(self.camera.mode(), CoordinateMode::Virtual { .. }) && self.clip_stack.is_empty() {
+                let (x, y, width, height) = self.camera.pixel_viewport();
+                renderer::std_renderer::set_clear_color(BLACK);
+                renderer::std_renderer::clear(ClearTarget::Color);
+                renderer::std_renderer::enable(Capability::ScissorTest);
+                renderer::std_renderer::scissor(x, y, width, height);
+                renderer::std_renderer::set_clear_color(color);
+                renderer::std_renderer::clear(ClearTarget::Color);
+                renderer::std_renderer::disable(Capability::ScissorTest);
+                return;
+            }
             renderer::std_renderer::set_clear_color(color);
-            renderer::std_renderer::clear(renderer::std_renderer::ClearTarget::Color);
+            renderer::std_renderer::clear(ClearTarget::Color);
         }
     }
 
@@ -248,29 +433,148 @@ impl Graphics {
         Shader::un_bind();
     }
 
+    // Saves the current transform, pop goes back to it
+    pub fn push(&mut self) {
+        self.transform_stack.push(self.dw.transform);
+    }
+
+    pub fn pop(&mut self) {
+        match self.transform_stack.pop() {
+            Some(transform) => self.dw.transform = transform,
+            None => error_log!("Tried to pop a transform without a matching push!"),
+        }
+    }
+
+    // Transforms apply to what is drawn afterwards in the local space of the current transform, like nested nodes
+    pub fn translate(&mut self, x: f32, y: f32) {
+        self.transform(&matrix::Mat3x3f::translation(x, y));
+    }
+
+    pub fn rotate(&mut self, angle: f32) {
+        self.transform(&matrix::Mat3x3f::rotation(angle));
+    }
+
+    pub fn scale(&mut self, x: f32, y: f32) {
+        self.transform(&matrix::Mat3x3f::scale(x, y));
+    }
+
+    pub fn skew(&mut self, x_angle: f32, y_angle: f32) {
+        self.transform(&matrix::Mat3x3f::skew(x_angle, y_angle));
+    }
+
+    pub fn transform(&mut self, transform: &matrix::Mat3x3f) {
+        self.dw.transform = matrix::Mat3x3f::mult(&self.dw.transform, transform);
+    }
+
+    pub fn set_transform(&mut self, transform: &matrix::Mat3x3f) {
+        self.dw.transform = *transform;
+    }
+
+    pub fn reset_transform(&mut self) {
+        self.dw.transform = matrix::Mat3x3f::identity();
+    }
+
+    pub fn current_transform(&self) -> matrix::Mat3x3f {
+        self.dw.transform
+    }
+
+    // Replaces the translation of the current transform
     pub fn set_translation(&mut self, x: f32, y: f32) {
-        self.dw.translation = (x, y);
+        unsafe {
+            self.dw.transform.m[0][2] = x;
+            self.dw.transform.m[1][2] = y;
+        }
     }
 
+    // Replaces the scale of the current transform and keeps its rotation
     pub fn set_scale(&mut self, x: f32, y: f32) {
-        self.dw.scale = (x, y);
+        let (scale_x, scale_y) = self.scaling();
+        unsafe {
+            for (r, row) in self.dw.transform.m.iter_mut().take(2).enumerate() {
+                row[0] = if scale_x != 0.0 { row[0] / scale_x * x } else if r == 0 { x } else { 0.0 };
+                row[1] = if scale_y != 0.0 { row[1] / scale_y * y } else if r == 1 { y } else { 0.0 };
+            }
+        }
+    }
+
+    pub fn translation(&self) -> (f32, f32) {
+        unsafe { (self.dw.transform.m[0][2], self.dw.transform.m[1][2]) }
+    }
+
+    pub fn scaling(&self) -> (f32, f32) {
+        unsafe {
+            let m = &self.dw.transform.m;
+            ((m[0][0] * m[0][0] + m[1][0] * m[1][0]).sqrt(), (m[0][1] * m[0][1] + m[1][1] * m[1][1]).sqrt())
+        }
+    }
+
+    // Pending draws are flushed first, the camera follows the size of the window or the current target
+    pub fn set_camera(&mut self, camera: Camera2D) {
+        self.flush();
+        self.camera = camera;
+        self.resize_camera();
+    }
+
+    pub fn camera(&self) -> &Camera2D {
+        &self.camera
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera2D {
+        self.flush();
+        self.camera_changed = true;
+        &mut self.camera
+    }
+
+    pub fn set_coordinate_mode(&mut self, mode: CoordinateMode) {
+        self.camera_mut().set_mode(mode);
+        self.resize_camera();
+    }
+
+    // Screen coordinates are frame pixels with the origin in the top left, like cursor positions
+    pub fn screen_to_world(&self, x: f32, y: f32) -> (f32, f32) {
+        self.camera.screen_to_world(x, y)
     }
 
-    pub fn translation  (&self) -> (f32, f32) { self.dw.translation }
-    pub fn scaling      (&self) -> (f32, f32) { self.dw.scale       }
+    pub fn world_to_screen(&self, x: f32, y: f32) -> (f32, f32) {
+        self.camera.world_to_screen(x, y)
+    }
+
+    fn resize_camera(&mut self) {
+        self.camera.resize(self.frame_width, self.frame_height);
+        self.camera_changed = true;
+        let (x, y, width, height) = self.camera.pixel_viewport();
+        unsafe {
+            gl_call!(gl::Viewport(x, y, width, height));
+        }
+    }
+
+    // Keeps glyphs on whole pixels so they stay sharp, x and y are in the space of the current transform
+    fn snap_to_pixel(&self, x: f32, y: f32) -> (f32, f32) {
+        let inverse = match self.dw.transform.inverse() {
+            Some(inverse) => inverse,
+            None => return (x, y),
+        };
+        let world = matrix::Mat3x3f::mult_vec(&self.dw.transform, Vec3f::new(x, y, 1.0));
+        let (screen_x, screen_y) = self.camera.world_to_screen(world.x, world.y);
+        let (world_x, world_y) = self.camera.screen_to_world(screen_x.round(), screen_y.round());
+        let local = matrix::Mat3x3f::mult_vec(&inverse, Vec3f::new(world_x, world_y, 1.0));
+        (local.x, local.y)
+    }
+
+    // Every vertex carries the rows of the transform it was drawn with
+    fn transform_values(&self) -> [f32; 9] {
+        unsafe { self.dw.transform.values }
+    }
 
     pub fn draw_line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32) {
-        let x1 = x1 * self.dw.scale.0 + self.dw.translation.0;
-        let x2 = x2 * self.dw.scale.0 + self.dw.translation.0;
-        let y1 = y1 * self.dw.scale.1 + self.dw.translation.1;
-        let y2 = y2 * self.dw.scale.1 + self.dw.translation.1;
-        let vertices = vec!(
+        let vertices = [
             x1, y1, x2, y2,
             f32::from(self.dw.color),
-        );
+        ];
 
         self.should_flush(LastDraw::Line);
         self.shape_ren.add_vertex_data(&vertices);
+        self.shape_ren.add_vertex_data(&self.transform_values());
     }
 
     pub fn draw_rect(&mut self, x: f32, y: f32, width: f32, height: f32) {
@@ -280,72 +584,6 @@ impl Graphics {
         self.draw_line(x - self.dw.line_width, y + height, x + width + self.dw.line_width, y + height);
     }
 
-    /*fn fill_quad_no_texture(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x3: f32, y3: f32, x4: f32, y4: f32) {
-        let mut vertices = vec!(
-            x1, y1, self.dw.color_rgba, //(0, 0)
-            x2, y2, self.dw.color_rgba, //(1, 0)
-            x3, y3, self.dw.color_rgba, //(0, 1)
-    
-            x2, y2, self.dw.color_rgba, //(1, 0)
-            x4, y4, self.dw.color_rgba, //(1, 1)
-            x3, y3, self.dw.color_rgba, //(0, 1)
-        );
-
-        let mut i = 0;
-        while i < vertices.len()
-        {
-            vertices[i] = vertices[i] * self.dw.scale.0 + self.dw.translation.0;
-            vertices[i + 1] = vertices[i + 1] * self.dw.scale.1 + self.dw.translation.1;
-
-            i += 3;
-        }
-
-        self.should_flush(LastDraw::Shape);
-        self.shape_ren.add_vertex_data(vertices);
-    }*/
-
-    /*fn fill_quad_with_texture(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x3: f32, y3: f32, x4: f32, y4: f32) {
-        let coords = self.texture.tex_coords();
-        let uvx1 = coords.0;
-        let uvx2 = coords.2;
-        let uvy1 = coords.1;
-        let uvy2 = coords.3;
-
-        let mut vertices = vec!(
-            x1, y1, self.dw.color_rgba, uvx1, uvy1, //(0, 0) 
-            x2, y2, self.dw.color_rgba, uvx2, uvy1, //(1, 0)
-            x3, y3, self.dw.color_rgba, uvx1, uvy2, //(0, 1)
-    
-            x2, y2, self.dw.color_rgba, uvx2, uvy1, //(1, 0)
-            x4, y4, self.dw.color_rgba, uvx2, uvy2, //(1, 1)
-            x3, y3, self.dw.color_rgba, uvx1, uvy2, //(0, 1)
-        );
-
-        let mut i = 0;
-        while i < vertices.len()
-        {
-            vertices[i] = vertices[i] * self.dw.scale.0 + self.dw.translation.0;
-            vertices[i + 1] = vertices[i + 1] * self.dw.scale.1 + self.dw.translation.1;
-
-            i += 5;
-        }
-
-        self.should_flush(LastDraw::Texture);
-        self.sp.add_vertex_data(vertices);
-    }*/
-
-    #[deprecated]
-    pub fn fill_quad(&mut self, _x1: f32, _y1: f32, _x2: f32, _y2: f32, _x3: f32, _y3: f32, _x4: f32, _y4: f32) {
-        if self.has_texture {
-            //self.fill_quad_with_texture(x1, y1, x2, y2, x3, y3, x4, y4);
-            warn_log!("Tried to draw unsupported textured quad!");
-        }
-        else {
-            //self.fill_quad_no_texture(x1, y1, x2, y2, x3, y3, x4, y4);
-            warn_log!("Tried to draw unsupported colored quad!");
-        }
-    }
-
     fn fill_rect_with_texture(&mut self, x: f32, y: f32, width: f32, height: f32) {
         let coords = self.texture.norm();
         let uvx = coords.0;
@@ -353,27 +591,33 @@ impl Graphics {
         let uvy = coords.1;
         let uvh = coords.3;
         let vertices = [
-            x * self.dw.scale.0 + self.dw.translation.0, y * self.dw.scale.1 + self.dw.translation.1, width * self.dw.scale.0, height * self.dw.scale.1,
+            x, y, width, height,
             uvx, uvy, uvw, uvh,
             f32::from(self.dw.color),
         ];
 
         self.should_flush(LastDraw::Sprite);
-        self.sprite_ren.add_vertex_data(&vertices)
+        self.sprite_ren.add_vertex_data(&vertices);
+        self.sprite_ren.add_vertex_data(&self.transform_values());
     }
 
     fn fill_rect_no_texture(&mut self, x: f32, y: f32, width: f32, height: f32) {
         let vertices = [
-            x * self.dw.scale.0 + self.dw.translation.0, y * self.dw.scale.1 + self.dw.translation.1, width * self.dw.scale.0, height * self.dw.scale.1,
+            x, y, width, height,
             f32::from(self.dw.color),
         ];
 
         self.should_flush(LastDraw::Rect);
-        self.shape_ren.add_vertex_data(&vertices)
+        self.shape_ren.add_vertex_data(&vertices);
+        self.shape_ren.add_vertex_data(&self.transform_values());
     }
 
     pub fn fill_rect(&mut self, x: f32, y: f32, width: f32, height: f32) {
-        if self.has_texture {
+        // Textures are drawn upright when the y axis points down
+        if self.has_texture && self.camera.y_down() {
+            self.fill_rect_with_texture(x, y + height, width, -height);
+        }
+        else if self.has_texture {
             self.fill_rect_with_texture(x, y, width, height);
         }
         else {
@@ -382,66 +626,201 @@ impl Graphics {
     }
 
     pub fn fill_ellipse(&mut self, x: f32, y: f32, width: f32, height: f32) {
-        let vertices = vec!(
-            x * self.dw.scale.0 + self.dw.translation.0, y * self.dw.scale.1 + self.dw.translation.1, width * self.dw.scale.0, height * self.dw.scale.1,
+        let vertices = [
+            x, y, width, height,
             f32::from(self.dw.color),
-        );
+        ];
 
         self.should_flush(LastDraw::Ellipse);
-        self.shape_ren.add_vertex_data(&vertices)
+        self.shape_ren.add_vertex_data(&vertices);
+        self.shape_ren.add_vertex_data(&self.transform_values());
+    }
+
+    // How far curves may stray from the real path in local units to stay within a quarter of a frame pixel
+    fn path_tolerance(&self) -> f32 {
+        let matrix = matrix::Mat3x3f::mult(&self.camera.matrix(), &self.dw.transform);
+        let (_, _, width, height) = self.camera.pixel_viewport();
+        let m = unsafe { matrix.m };
+        let x_axis = (m[0][0] * width as f32 / 2.0, m[1][0] * height as f32 / 2.0);
+        let y_axis = (m[0][1] * width as f32 / 2.0, m[1][1] * height as f32 / 2.0);
+        let pixels_per_unit = (x_axis.0.hypot(x_axis.1)).max(y_axis.0.hypot(y_axis.1));
+        if pixels_per_unit > 0.0 { 0.25 / pixels_per_unit } else { 0.25 }
+    }
+
+    fn add_triangles(&mut self, points: &[(f32, f32)]) {
+        if points.is_empty() {
+            return;
+        }
+
+        self.should_flush(LastDraw::TriangleList);
+        for point in points {
+            self.add_triangle_vertex(*point, (0.0, 0.0), self.dw.color);
+        }
+    }
+
+    fn add_triangle_vertex(&mut self, position: (f32, f32), uv: (f32, f32), color: Color) {
+        self.triangle_ren.add_vertex_data(&[position.0, position.1, uv.0, uv.1, f32::from(color)]);
+        self.triangle_ren.add_vertex_data(&self.transform_values());
+    }
+
+    // With a texture it is stretched over the bounding box of the triangle the same way fill_rect draws it
+    pub fn fill_triangle(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x3: f32, y3: f32) {
+        let points = [(x1, y1), (x2, y2), (x3, y3)];
+        if self.has_texture {
+            self.fill_triangle_textured(points, bounding_box_uvs(points, self.camera.y_down()));
+        }
+        else {
+            self.fill_triangle_colored(points, [self.dw.color; 3]);
+        }
+    }
+
+    // Corners go around the quad, which has to be convex. The first corner shows the bottom left of a texture
+    // or the top left when the y axis points down, so a quad like fill_rect shows it upright.
+    pub fn fill_quad(&mut self, p1: (f32, f32), p2: (f32, f32), p3: (f32, f32), p4: (f32, f32)) {
+        let points = [p1, p2, p3, p4];
+        if self.has_texture && self.camera.y_down() {
+            self.fill_quad_textured(points, [(0.0, 1.0), (1.0, 1.0), (1.0, 0.0), (0.0, 0.0)]);
+        }
+        else if self.has_texture {
+            self.fill_quad_textured(points, [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]);
+        }
+        else {
+            self.fill_quad_colored(points, [self.dw.color; 4]);
+        }
+    }
+
+    // Colours are blended across the triangle, the current texture is not used
+    pub fn fill_triangle_colored(&mut self, points: [(f32, f32); 3], colors: [Color; 3]) {
+        self.should_flush(LastDraw::TriangleList);
+        for (i, point) in points.iter().enumerate() {
+            self.add_triangle_vertex(*point, (0.0, 0.0), colors[i]);
+        }
+    }
+
+    pub fn fill_quad_colored(&mut self, points: [(f32, f32); 4], colors: [Color; 4]) {
+        self.fill_triangle_colored([points[0], points[1], points[2]], [colors[0], colors[1], colors[2]]);
+        self.fill_triangle_colored([points[0], points[2], points[3]], [colors[0], colors[2], colors[3]]);
+    }
+
+    // UVs go from 0 to 1 over the current texture region and the texture is tinted with the current colour
+    pub fn fill_triangle_textured(&mut self, points: [(f32, f32); 3], uvs: [(f32, f32); 3]) {
+        if !self.has_texture {
+            warn_log!("Tried to draw a textured triangle without a texture!");
+            return;
+        }
+
+        self.should_flush(LastDraw::TexturedTriangleList);
+        let region = self.texture.norm();
+        for (i, point) in points.iter().enumerate() {
+            let uv = (region.0 + uvs[i].0 * region.2, region.1 + uvs[i].1 * region.3);
+            self.add_triangle_vertex(*point, uv, self.dw.color);
+        }
+    }
+
+    pub fn fill_quad_textured(&mut self, points: [(f32, f32); 4], uvs: [(f32, f32); 4]) {
+        self.fill_triangle_textured([points[0], points[1], points[2]], [uvs[0], uvs[1], uvs[2]]);
+        self.fill_triangle_textured([points[0], points[2], points[3]], [uvs[0], uvs[2], uvs[3]]);
+    }
+
+    pub fn fill_path(&mut self, path: &Path, rule: FillRule) {
+        let triangles = path.fill_triangles(rule, self.path_tolerance());
+        self.add_triangles(&triangles);
+    }
+
+    pub fn stroke_path(&mut self, path: &Path, style: &StrokeStyle) {
+        let triangles = path.stroke_triangles(style, self.path_tolerance());
+        self.add_triangles(&triangles);
     }
 
     pub fn draw_string(&mut self, text: &str, x: f32, y: f32) {
-        let sprite_texture = self.texture.clone();
-        let mut current_advance = 0.0;
+        let run = self.font.shape(text);
+        if self.font.cache_run(&run) {
+            self.flush();
+        }
 
-        for c in text.chars() {
-            if c == ' ' {
-                current_advance += self.font.width() as f32 / self.frame_width as f32 / 2.0;
-                continue;
-            }
+        self.begin_text(self.font.render_mode());
+        let sprite_texture = self.texture.clone();
+        let pixel = self.camera.pixel_scale();
 
-            let glyph = match self.font.glyph(c) {
+        for positioned in &run.glyphs {
+            let glyph = match self.font.shaped_glyph(positioned) {
                 Some(g) => g,
-                None => match self.font.glyph('?') {
-                    Some(g) => g,
-                    None => {
-                        current_advance += self.font.width() as f32 / self.frame_width as f32 / 2.0;
-                        continue;
-                    }
-                },
+                None => continue,
             };
 
             let texture = glyph.texture().clone();
             let bearing = glyph.bearing();
-            let advance = glyph.advance();
             let size = glyph.size();
 
-            let x = ((x + bearing.0 / self.frame_width as f32 + current_advance) * self.frame_width as f32).round() / self.frame_width as f32;
-            let y = ((y + bearing.1 / self.frame_height as f32 - size.1 / self.frame_height as f32) * self.frame_height as f32).round() / self.frame_height as f32;
-            let width = size.0 / self.frame_width as f32;
-            let height = size.1 / self.frame_height as f32;
+            let (x, y) = self.snap_to_pixel(x + (positioned.x + bearing.0) * pixel.0, y + (positioned.y + bearing.1 - size.1) * pixel.1);
+            let width = size.0 * pixel.0;
+            let height = size.1 * pixel.1;
 
-            current_advance += advance as f32 / self.frame_width as f32;
+            if size.0 == 0.0 || size.1 == 0.0 {
+                continue;
+            }
 
             self.texture(texture);
             self.fill_rect_with_texture(x, y, width, height);
         }
 
         self.texture(sprite_texture);
+        self.end_text();
     }  
 
+    // Draws a block laid out with this font, x and y is where the top left corner of the layout ends up
+    pub fn draw_text_layout(&mut self, layout: &text::TextLayout, x: f32, y: f32) {
+        self.draw_layout(layout, None, x, y);
+    }
+
+    // Draws a layout made with TextLayout::styled or RichText, fonts has to be the same fonts in the same order
+    pub fn draw_styled_text(&mut self, layout: &text::TextLayout, fonts: &[&font::Font], x: f32, y: f32) {
+        self.draw_layout(layout, Some(fonts), x, y);
+    }
+
+    fn draw_layout(&mut self, layout: &text::TextLayout, fonts: Option<&[&font::Font]>, x: f32, y: f32) {
+        let (changed, quads) = text::glyph_quads(layout, fonts.unwrap_or(&[&self.font]));
+        if changed {
+            self.flush();
+        }
+
+        let color = self.dw.color;
+        let sprite_texture = self.texture.clone();
+        let pixel = self.camera.pixel_scale();
+
+        for quad in quads {
+            let (qx, qy, width, height) = quad.rect;
+            let (x, y) = self.snap_to_pixel(x + qx * pixel.0, y - qy * pixel.1);
+
+            self.begin_text(quad.mode);
+            self.dw.color = quad.color.unwrap_or(color);
+            self.texture(quad.texture);
+            self.fill_rect_with_texture(x, y, width * pixel.0, height * pixel.1);
+        }
+
+        self.texture(sprite_texture);
+        self.end_text();
+
+        for decoration in layout.decorations() {
+            self.dw.color = decoration.color.unwrap_or(color);
+            self.fill_rect_no_texture(x + decoration.x * pixel.0, y - (decoration.y + decoration.thickness) * pixel.1, decoration.width * pixel.0, decoration.thickness * pixel.1);
+        }
+        self.dw.color = color;
+    }
+
     pub fn texture(&mut self, texture: texture::TextureRegion) {
         if !self.texture.has_same_texture(&texture) && self.has_texture {
             self.flush();
         }
         if texture.is_valid() {
             self.sprite_ren.texture = texture.clone();
+            self.triangle_ren.texture = texture.clone();
             self.texture = texture;
             self.has_texture = true;
         }
         else {
             self.sprite_ren.texture = texture.clone();
+            self.triangle_ren.texture = texture.clone();
             self.texture = texture;
             self.has_texture = false;
         }
@@ -458,14 +837,176 @@ impl Graphics {
     pub fn sprite_shader(&mut self) -> &mut Shader { &mut self.sprite_ren.shader }
     pub fn shape_shader (&mut self) -> &mut Shader { &mut self.shape_ren.shader  }
     
+    // Only draws inside of the rect until the matching pop_clip_rect, nested rects clip to the ones below them.
+    // The rect goes through the current transform and camera, rotated rects clip to their bounds on screen.
+    pub fn push_clip_rect(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        let rect = self.screen_rect(x, y, width, height);
+        let rect = match self.clip_stack.last() {
+            Some(top) => intersect_clip_rects(*top, rect),
+            None => rect,
+        };
+        self.flush();
+        self.clip_stack.push(rect);
+        self.apply_clip();
+    }
+
+    pub fn pop_clip_rect(&mut self) {
+        self.flush();
+        if self.clip_stack.pop().is_none() {
+            error_log!("Tried to pop a clip rect without a matching push!");
+        }
+        self.apply_clip();
+    }
+
+    // The current scissor rect in frame pixels with the origin in the bottom left
+    pub fn clip_rect(&self) -> Option<(i32, i32, i32, i32)> {
+        self.clip_stack.last().copied()
+    }
+
+    fn screen_rect(&self, x: f32, y: f32, width: f32, height: f32) -> (i32, i32, i32, i32) {
+        let mut min = (f32::MAX, f32::MAX);
+        let mut max = (f32::MIN, f32::MIN);
+        for (corner_x, corner_y) in [(x, y), (x + width, y), (x, y + height), (x + width, y + height)] {
+            let world = matrix::Mat3x3f::mult_vec(&self.dw.transform, Vec3f::new(corner_x, corner_y, 1.0));
+            let (screen_x, screen_y) = self.camera.world_to_screen(world.x, world.y);
+            min = (min.0.min(screen_x), min.1.min(screen_y));
+            max = (max.0.max(screen_x), max.1.max(screen_y));
+        }
+        let (left, right) = (min.0.round() as i32, max.0.round() as i32);
+        let (top, bottom) = (min.1.round() as i32, max.1.round() as i32);
+        (left, self.frame_height as i32 - bottom, right - left, bottom - top)
+    }
+
+    fn apply_clip(&self) {
+        unsafe {
+            match self.clip_stack.last() {
+                Some(&(x, y, width, height)) => {
+                    renderer::std_renderer::enable(Capability::ScissorTest);
+                    renderer::std_renderer::scissor(x, y, width, height);
+                },
+                None => renderer::std_renderer::disable(Capability::ScissorTest),
+            }
+        }
+    }
+
+    // Draws after this only go into the mask, any primitive, sprite or text can be used and fully transparent
+    // pixels are left out. Needs a stencil buffer, framebuffer targets need a depth/stencil attachment with one.
+    pub fn begin_mask(&mut self) {
+        self.flush();
+        unsafe {
+            renderer::std_renderer::enable(Capability::StencilTest);
+            renderer::std_renderer::stencil_mask(0xFF);
+            renderer::std_renderer::set_clear_stencil(0);
+            renderer::std_renderer::clear(ClearTarget::Stencil);
+            renderer::std_renderer::stencil_func(StencilFunction::Always, 1, 0xFF);
+            renderer::std_renderer::stencil_op(StencilOperation::Keep, StencilOperation::Keep, StencilOperation::Replace);
+            renderer::std_renderer::color_mask(false, false, false, false);
+        }
+        self.writing_mask = true;
+        self.set_discard_transparent(true);
+    }
+
+    // Stops drawing into the mask, what is drawn afterwards only shows up inside or outside of it
+    pub fn end_mask(&mut self, mode: MaskMode) {
+        self.flush();
+        unsafe {
+            renderer::std_renderer::color_mask(true, true, true, true);
+            renderer::std_renderer::stencil_mask(0x00);
+        }
+        self.writing_mask = false;
+        self.set_discard_transparent(false);
+        self.set_mask_mode(mode);
+    }
+
+    // Switches between drawing inside and outside of the last mask
+    pub fn set_mask_mode(&mut self, mode: MaskMode) {
+        if self.writing_mask {
+            error_log!("Tried to change the mask mode while drawing a mask, call end_mask instead!");
+            return;
+        }
+        self.flush();
+        let function = match mode {
+            MaskMode::Inside => StencilFunction::Equal,
+            MaskMode::Outside => StencilFunction::NotEqual,
+        };
+        unsafe {
+            renderer::std_renderer::enable(Capability::StencilTest);
+            renderer::std_renderer::stencil_func(function, 1, 0xFF);
+            renderer::std_renderer::stencil_op(StencilOperation::Keep, StencilOperation::Keep, StencilOperation::Keep);
+        }
+        self.mask = Some(mode);
+    }
+
+    pub fn clear_mask(&mut self) {
+        self.flush();
+        unsafe {
+            if self.writing_mask {
+                renderer::std_renderer::color_mask(true, true, true, true);
+                self.set_discard_transparent(false);
+            }
+            renderer::std_renderer::stencil_mask(0xFF);
+            renderer::std_renderer::disable(Capability::StencilTest);
+        }
+        self.writing_mask = false;
+        self.mask = None;
+    }
+
+    pub fn mask_mode(&self) -> Option<MaskMode> {
+        self.mask
+    }
+
+    fn set_discard_transparent(&self, discard: bool) {
+        if let Some(location) = self.sprite_ren.shader.find_uniform("u_discard_transparent") {
+            self.sprite_ren.shader.bind();
+            self.sprite_ren.shader.upload_1i(location, discard as i32);
+            Shader::un_bind();
+        }
+    }
+
+    // Draws into frame_buffer instead of the window until the target is set back to None. Pending draws are
+    // flushed first and pixel coordinates follow the size of the target.
+    pub fn set_target(&mut self, frame_buffer: Option<&FrameBuffer>) {
+        self.bind_target(DrawTarget::of(frame_buffer));
+    }
+
+    // Draws into frame_buffer inside f and goes back to the previous target afterwards. If f panics the
+    // framebuffer stays bound.
+    pub fn with_target<F: FnOnce(&mut Self)>(&mut self, frame_buffer: &FrameBuffer, f: F) {
+        let previous = self.draw_target.target();
+        // Clip rects are in the pixels of the target they were pushed on
+        let clip_stack = std::mem::take(&mut self.clip_stack);
+        self.set_target(Some(frame_buffer));
+        f(self);
+        self.flush();
+        self.clip_stack = clip_stack;
+        self.bind_target(previous);
+    }
+
+    pub fn has_target(&self) -> bool {
+        self.draw_target.target().is_some()
+    }
+
+    fn bind_target(&mut self, target: Option<(u32, u32, u32)>) {
+        self.flush();
+        (self.frame_width, self.frame_height) = self.draw_target.bind(target);
+        self.resize_camera();
+        self.apply_clip();
+    }
+
     pub fn frame_width(&self) -> u32  { self.frame_width    }
     pub fn frame_height(&self) -> u32 { self.frame_height   }
 }
 
+impl text::TextBatch for Graphics {
+    fn text_rendering(&mut self) -> &mut text::TextRendering { &mut self.text_rendering }
+    fn flush(&mut self) { Graphics::flush(self) }
+}
+
 pub struct Graphics2D {
     has_texture:    bool,
     texture:        texture::TextureRegion, 
     font:           font::Font,
+    text_rendering: text::TextRendering,
     
     shape_ren:      renderer::graphics_renderer::ShapeBatchRenderer,
     sprite_ren:     renderer::graphics_renderer::SpriteBatchRenderer,
@@ -474,8 +1015,10 @@ pub struct Graphics2D {
 
     frame_width:      u32,
     frame_height:     u32,
+    draw_target:      DrawTarget,
 
     dw:             DrawingInformation,
+    transform_stack: Vec<matrix::Mat3x3f>,
     last_draw:      LastDraw,
 }
 
@@ -510,9 +1053,12 @@ impl Graphics2D {
 
             frame_width: win.get_width() as u32,
             frame_height: win.get_height() as u32,
+            draw_target: DrawTarget::new(win.get_width() as u32, win.get_height() as u32),
 
             dw: DrawingInformation::new(),
+            transform_stack: Vec::new(),
             last_draw: LastDraw::None,
+            text_rendering: text::TextRendering::new(),
         }
     }
 
@@ -521,9 +1067,11 @@ impl Graphics2D {
         while !loop_done {
             match self.frame_buffer_listener.try_recv() {
                 Ok((width, height)) => {
-                    unsafe { gl_call!(gl::Viewport(0, 0, width as i32, height as i32)); };
-                    self.frame_width = width;
-                    self.frame_height = height;
+                    if self.draw_target.resize_window(width, height) {
+                        unsafe { gl_call!(gl::Viewport(0, 0, width as i32, height as i32)); };
+                        self.frame_width = width;
+                        self.frame_height = height;
+                    }
                     //info_log!("message: &str");
                 },
                 Err(_) => loop_done = true
@@ -554,9 +1102,10 @@ impl Graphics2D {
                 self.shape_ren.flush();
             },
             LastDraw::Sprite => {
+                self.text_rendering.upload(&self.sprite_ren.shader);
                 self.sprite_ren.flush();
             },
-            LastDraw::None => {}
+            LastDraw::TriangleList | LastDraw::TexturedTriangleList | LastDraw::None => {}
         }
         
         self.last_draw = LastDraw::None;
@@ -585,6 +1134,14 @@ impl Graphics2D {
         self.font = font;
     }
 
+    pub fn set_text_effects(&mut self, effects: text::TextEffects) {
+        self.text_rendering.effects = effects;
+    }
+
+    pub fn text_effects(&self) -> text::TextEffects {
+        self.text_rendering.effects
+    }
+
     pub fn set_color(&mut self, color: Color) {
         self.dw.color = color;
     }
@@ -621,21 +1178,65 @@ impl Graphics2D {
         Shader::un_bind();
     }
 
+    // Saves the current transform, pop goes back to it
+    pub fn push(&mut self) {
+        self.transform_stack.push(self.dw.transform);
+    }
+
+    pub fn pop(&mut self) {
+        match self.transform_stack.pop() {
+            Some(transform) => self.dw.transform = transform,
+            None => error_log!("Tried to pop a transform without a matching push!"),
+        }
+    }
+
+    // Same as in Graphics, the matrix passed to every draw call is applied in the space of the current transform
+    pub fn translate(&mut self, x: f32, y: f32) {
+        self.transform(&matrix::Mat3x3f::translation(x, y));
+    }
+
+    pub fn rotate(&mut self, angle: f32) {
+        self.transform(&matrix::Mat3x3f::rotation(angle));
+    }
+
+    pub fn scale(&mut self, x: f32, y: f32) {
+        self.transform(&matrix::Mat3x3f::scale(x, y));
+    }
+
+    pub fn skew(&mut self, x_angle: f32, y_angle: f32) {
+        self.transform(&matrix::Mat3x3f::skew(x_angle, y_angle));
+    }
+
+    pub fn transform(&mut self, transform: &matrix::Mat3x3f) {
+        self.dw.transform = matrix::Mat3x3f::mult(&self.dw.transform, transform);
+    }
+
+    pub fn set_transform(&mut self, transform: &matrix::Mat3x3f) {
+        self.dw.transform = *transform;
+    }
+
+    pub fn reset_transform(&mut self) {
+        self.dw.transform = matrix::Mat3x3f::identity();
+    }
+
+    pub fn current_transform(&self) -> matrix::Mat3x3f {
+        self.dw.transform
+    }
+
+    // Every vertex carries the rows of the current transform times the matrix it was drawn with
+    fn transform_values(&self, mat: &matrix::Mat3x3f) -> [f32; 9] {
+        unsafe { matrix::Mat3x3f::mult(&self.dw.transform, mat).values }
+    }
+
     pub fn draw_line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, mat: &matrix::Mat3x3f) {
-        let x1 = x1 * self.dw.scale.0 + self.dw.translation.0;
-        let x2 = x2 * self.dw.scale.0 + self.dw.translation.0;
-        let y1 = y1 * self.dw.scale.1 + self.dw.translation.1;
-        let y2 = y2 * self.dw.scale.1 + self.dw.translation.1;
-        
-        let mut vertices = vec!(
+        let vertices = [
             x1, y1, x2, y2,
             f32::from(self.dw.color),
-        );
-
-        unsafe { vertices.extend(mat.values.iter()); }
+        ];
 
         self.should_flush(LastDraw::Line);
         self.shape_ren.add_vertex_data(&vertices);
+        self.shape_ren.add_vertex_data(&self.transform_values(mat));
     }
 
     pub fn draw_rect(&mut self, x: f32, y: f32, width: f32, height: f32, mat: &matrix::Mat3x3f) {
@@ -646,77 +1247,111 @@ impl Graphics2D {
     }
 
     pub fn draw_string(&mut self, text: &str, x: f32, y: f32, mat: &matrix::Mat3x3f) {
-        let sprite_texture = self.texture.clone();
-        let mut current_advance = 0.0;
+        let run = self.font.shape(text);
+        if self.font.cache_run(&run) {
+            self.flush();
+        }
 
-        for c in text.chars() {
-            if c == ' ' {
-                current_advance += self.font.width() as f32 / self.frame_width as f32 / 2.0;
-                continue;
-            }
+        self.begin_text(self.font.render_mode());
+        let sprite_texture = self.texture.clone();
 
-            let glyph = match self.font.glyph(c) {
+        for positioned in &run.glyphs {
+            let glyph = match self.font.shaped_glyph(positioned) {
                 Some(g) => g,
-                None => match self.font.glyph('?') {
-                    Some(g) => g,
-                    None => {
-                        current_advance += self.font.width() as f32 / self.frame_width as f32 / 2.0;
-                        continue;
-                    }
-                },
+                None => continue,
             };
 
             let texture = glyph.texture().clone();
             let bearing = glyph.bearing();
-            let advance = glyph.advance();
             let size = glyph.size();
 
             //info_log!(("{}", bearing.1));
 
-            let x = (x * self.frame_width as f32).round() / self.frame_width as f32 + bearing.0 / self.frame_width as f32 + current_advance;
-            let y = (y * self.frame_height as f32).round() / self.frame_height as f32 + bearing.1 / self.frame_height as f32 - size.1 / self.frame_height as f32;
+            let x = (x * self.frame_width as f32).round() / self.frame_width as f32 + (positioned.x + bearing.0) / self.frame_width as f32;
+            let y = (y * self.frame_height as f32).round() / self.frame_height as f32 + (positioned.y + bearing.1 - size.1) / self.frame_height as f32;
             let width = size.0 / self.frame_width as f32;
             let height = size.1 / self.frame_height as f32;
 
-            current_advance += advance as f32 / self.frame_width as f32;
+            if size.0 == 0.0 || size.1 == 0.0 {
+                continue;
+            }
 
             self.texture(texture);
             self.fill_rect_with_texture(x, y, width, height, mat);
         }
 
         self.texture(sprite_texture);
+        self.end_text();
     }  
 
+    // Draws a block laid out with this font, x and y is where the top left corner of the layout ends up
+    pub fn draw_text_layout(&mut self, layout: &text::TextLayout, x: f32, y: f32, mat: &matrix::Mat3x3f) {
+        self.draw_layout(layout, None, x, y, mat);
+    }
+
+    // Draws a layout made with TextLayout::styled or RichText, fonts has to be the same fonts in the same order
+    pub fn draw_styled_text(&mut self, layout: &text::TextLayout, fonts: &[&font::Font], x: f32, y: f32, mat: &matrix::Mat3x3f) {
+        self.draw_layout(layout, Some(fonts), x, y, mat);
+    }
+
+    fn draw_layout(&mut self, layout: &text::TextLayout, fonts: Option<&[&font::Font]>, x: f32, y: f32, mat: &matrix::Mat3x3f) {
+        let (changed, quads) = text::glyph_quads(layout, fonts.unwrap_or(&[&self.font]));
+        if changed {
+            self.flush();
+        }
+
+        let color = self.dw.color;
+        let sprite_texture = self.texture.clone();
+
+        for quad in quads {
+            let (qx, qy, width, height) = quad.rect;
+            let x = (x * self.frame_width as f32).round() / self.frame_width as f32 + qx / self.frame_width as f32;
+            let y = (y * self.frame_height as f32).round() / self.frame_height as f32 - qy / self.frame_height as f32;
+            let width = width / self.frame_width as f32;
+            let height = height / self.frame_height as f32;
+
+            self.begin_text(quad.mode);
+            self.dw.color = quad.color.unwrap_or(color);
+            self.texture(quad.texture);
+            self.fill_rect_with_texture(x, y, width, height, mat);
+        }
+
+        self.texture(sprite_texture);
+        self.end_text();
+
+        for decoration in layout.decorations() {
+            self.dw.color = decoration.color.unwrap_or(color);
+            self.fill_rect_no_texture(x + decoration.x / self.frame_width as f32, y - (decoration.y + decoration.thickness) / self.frame_height as f32, decoration.width / self.frame_width as f32, decoration.thickness / self.frame_height as f32, mat);
+        }
+        self.dw.color = color;
+    }
+
     fn fill_rect_with_texture(&mut self, x: f32, y: f32, width: f32, height: f32, mat: &matrix::Mat3x3f) {
         let coords = self.texture.norm();
         let uvx = coords.0;
         let uvw = coords.2;
         let uvy = coords.1;
         let uvh = coords.3;
-        let mut vertices = vec!(
-            x * self.dw.scale.0 + self.dw.translation.0, y * self.dw.scale.1 + self.dw.translation.1, width * self.dw.scale.0, height * self.dw.scale.1,
+        let vertices = [
+            x, y, width, height,
             uvx, uvy, uvw, uvh,
             f32::from(self.dw.color),
-        );
-
-        unsafe { vertices.extend(mat.values.iter()); }
-
+        ];
 
         self.should_flush(LastDraw::Sprite);
-        self.sprite_ren.add_vertex_data(&vertices)
+        self.sprite_ren.add_vertex_data(&vertices);
+        self.sprite_ren.add_vertex_data(&self.transform_values(mat));
     }
 
     fn fill_rect_no_texture(&mut self, x: f32, y: f32, width: f32, height: f32, mat: &matrix::Mat3x3f) {
         let vertices = [
-            x * self.dw.scale.0 + self.dw.translation.0, y * self.dw.scale.1 + self.dw.translation.1, width * self.dw.scale.0, height * self.dw.scale.1,
+            x, y, width, height,
             f32::from(self.dw.color),
         ];
-        
-        //unsafe { vertices.extend_from_slice(&mat.values); }
 
         self.should_flush(LastDraw::Rect);
         self.shape_ren.add_vertex_data(&vertices);
-        self.shape_ren.add_vertex_data(unsafe { &mat.values });
+        self.shape_ren.add_vertex_data(&self.transform_values(mat));
     }
 
     pub fn fill_rect(&mut self, x: f32, y: f32, width: f32, height: f32, mat: &matrix::Mat3x3f) {
@@ -729,15 +1364,14 @@ impl Graphics2D {
     }
 
     pub fn fill_ellipse(&mut self, x: f32, y: f32, width: f32, height: f32, mat: &matrix::Mat3x3f) {
-        let mut vertices = vec!(
-            x * self.dw.scale.0 + self.dw.translation.0, y * self.dw.scale.1 + self.dw.translation.1, width * self.dw.scale.0, height * self.dw.scale.1,
+        let vertices = [
+            x, y, width, height,
             f32::from(self.dw.color),
-        );
-
-        unsafe { vertices.extend(mat.values.iter()); }
+        ];
 
         self.should_flush(LastDraw::Ellipse);
-        self.shape_ren.add_vertex_data(&vertices)
+        self.shape_ren.add_vertex_data(&vertices);
+        self.shape_ren.add_vertex_data(&self.transform_values(mat));
     }
 
     pub fn texture(&mut self, texture: texture::TextureRegion) {
@@ -767,22 +1401,103 @@ impl Graphics2D {
     pub fn sprite_shader(&mut self) -> &mut Shader { &mut self.sprite_ren.shader }
     pub fn shape_shader (&mut self) -> &mut Shader { &mut self.shape_ren.shader  }
 
+    // Draws into frame_buffer instead of the window until the target is set back to None. Pending draws are
+    // flushed first and pixel coordinates follow the size of the target.
+    pub fn set_target(&mut self, frame_buffer: Option<&FrameBuffer>) {
+        self.bind_target(DrawTarget::of(frame_buffer));
+    }
+
+    // Draws into frame_buffer inside f and goes back to the previous target afterwards. If f panics the
+    // framebuffer stays bound.
+    pub fn with_target<F: FnOnce(&mut Self)>(&mut self, frame_buffer: &FrameBuffer, f: F) {
+        let previous = self.draw_target.target();
+        self.set_target(Some(frame_buffer));
+        f(self);
+        self.bind_target(previous);
+    }
+
+    pub fn has_target(&self) -> bool {
+        self.draw_target.target().is_some()
+    }
+
+    fn bind_target(&mut self, target: Option<(u32, u32, u32)>) {
+        self.flush();
+        let (width, height) = self.draw_target.bind(target);
+        unsafe {
+            gl_call!(gl::Viewport(0, 0, width as i32, height as i32));
+        }
+        self.frame_width = width;
+        self.frame_height = height;
+    }
+
     pub fn frame_width(&self) -> u32  { self.frame_width    }
     pub fn frame_height(&self) -> u32 { self.frame_height   }
 }
 
+impl text::TextBatch for Graphics2D {
+    fn text_rendering(&mut self) -> &mut text::TextRendering { &mut self.text_rendering }
+    fn flush(&mut self) { Graphics2D::flush(self) }
+}
+
+// Where the points are inside their bounding box from 0 to 1, flipped when the y axis points down
+pub(crate) fn bounding_box_uvs(points: [(f32, f32); 3], y_down: bool) -> [(f32, f32); 3] {
+    let min_x = points[0].0.min(points[1].0).min(points[2].0);
+    let max_x = points[0].0.max(points[1].0).max(points[2].0);
+    let min_y = points[0].1.min(points[1].1).min(points[2].1);
+    let max_y = points[0].1.max(points[1].1).max(points[2].1);
+    let width = if max_x > min_x { max_x - min_x } else { 1.0 };
+    let height = if max_y > min_y { max_y - min_y } else { 1.0 };
+
+    let mut uvs = [(0.0, 0.0); 3];
+    for (i, point) in points.iter().enumerate() {
+        let v = (point.1 - min_y) / height;
+        uvs[i] = ((point.0 - min_x) / width, if y_down { 1.0 - v } else { v });
+    }
+    uvs
+}
+
+// The overlap of two scissor rects, empty rects have a size of zero
+pub(crate) fn intersect_clip_rects(a: (i32, i32, i32, i32), b: (i32, i32, i32, i32)) -> (i32, i32, i32, i32) {
+    let left = a.0.max(b.0);
+    let bottom = a.1.max(b.1);
+    let right = (a.0 + a.2).min(b.0 + b.2);
+    let top = (a.1 + a.3).min(b.1 + b.3);
+    (left, bottom, (right - left).max(0), (top - bottom).max(0))
+}
+
+fn window_camera(win: &window::Window) -> Camera2D {
+    let mut camera = Camera2D::new(CoordinateMode::Ndc);
+    camera.resize(win.get_width() as u32, win.get_height() as u32);
+    camera
+}
+
+// Shaders set with set_shape_shader or set_sprite_shader don't have to use the camera
+fn upload_view(shader: &Shader, view: &matrix::Mat3x3f) {
+    if let Some(location) = shader.find_uniform("u_view") {
+        shader.bind();
+        // GLSL matrices are column major
+        shader.upload_3x3f(location, unsafe { &view.transpose().values });
+    }
+}
+
 const SHAPE_SHADER_SOURCE: &str = "
 #shader vertex
 #version 330 core
 
 layout(location = 0) in vec4 v_bounds;
 layout(location = 1) in int v_color;
+layout(location = 2) in vec3 v_model_0;
+layout(location = 3) in vec3 v_model_1;
+layout(location = 4) in vec3 v_model_2;
 
 out int tight_color;
+out mat3 model;
 
 void main() {
     gl_Position = v_bounds;
     tight_color = v_color;
+    // The rows of the transform
+    model = transpose(mat3(v_model_0, v_model_1, v_model_2));
 }
 
 #shader geometry
@@ -792,6 +1507,7 @@ layout(points) in;
 layout(triangle_strip, max_vertices = 64) out;
 
 in int tight_color[];
+in mat3 model[];
 
 out vec4 color;
 
@@ -804,6 +1520,13 @@ uniform int u_primitive = 0;
 uniform int u_ellipse_detail = 100;
 uniform float u_line_width = 0.01;
 
+// Through the transform the primitive was drawn with and then the camera, see Camera2D
+uniform mat3 u_view = mat3(1.0);
+
+vec4 project(vec2 position) {
+    return vec4((u_view * model[0] * vec3(position, 1.0)).xy, 1.0, 1.0);
+}
+
 
 vec4 normal_color(int tight_color) {
     float a = tight_color & 255;
@@ -815,33 +1538,33 @@ vec4 normal_color(int tight_color) {
 
 void draw_rect(float x, float y, float width, float height) {
     //(0, 0)
-    gl_Position = vec4(x, y, 1.0, 1.0);
+    gl_Position = project(vec2(x, y));
     EmitVertex();
     
     //(1, 0)
-    gl_Position = vec4(x + width, y, 1.0, 1.0);
+    gl_Position = project(vec2(x + width, y));
     EmitVertex();
 
     //(0, 1)
-    gl_Position = vec4(x, y + height, 1.0, 1.0);
+    gl_Position = project(vec2(x, y + height));
     EmitVertex();
 
     //(1, 1)
-    gl_Position = vec4(x + width, y + height, 1.0, 1.0);
+    gl_Position = project(vec2(x + width, y + height));
     EmitVertex();
 }
 
 void draw_triangle(float x, float y, float width, float height) {
     //(0.5, 0)
-    gl_Position = vec4(x + width / 2.0, y + height, 1.0, 1.0);
+    gl_Position = project(vec2(x + width / 2.0, y + height));
     EmitVertex();
     
     //(1, 0)
-    gl_Position = vec4(x + width, y, 1.0, 1.0);
+    gl_Position = project(vec2(x + width, y));
     EmitVertex();
 
     //(0, 0)
-    gl_Position = vec4(x, y, 1.0, 1.0);
+    gl_Position = project(vec2(x, y));
     EmitVertex();
 }
 
@@ -852,10 +1575,10 @@ void draw_ellipse(float x, float y, float width, float height) {
         nx = x + width / 2.0 + nx;
         ny = y + height / 2.0 + ny;
 
-        gl_Position = vec4(nx, ny, 1.0, 1.0);
+        gl_Position = project(vec2(nx, ny));
         EmitVertex();
 
-        gl_Position = vec4(x + width / 2.0, y + height / 2.0, 1.0, 1.0);
+        gl_Position = project(vec2(x + width / 2.0, y + height / 2.0));
         EmitVertex();
     }
     float nx = cos(float(0) / float(u_ellipse_detail) * 2.0 * 3.14) * width / 2.0;
@@ -863,10 +1586,10 @@ void draw_ellipse(float x, float y, float width, float height) {
     nx = x + width / 2.0 + nx;
     ny = y + height / 2.0 + ny;
 
-    gl_Position = vec4(nx, ny, 1.0, 1.0);
+    gl_Position = project(vec2(nx, ny));
     EmitVertex();
 
-    gl_Position = vec4(x + width / 2.0, y + height / 2.0, 1.0, 1.0);
+    gl_Position = project(vec2(x + width / 2.0, y + height / 2.0));
     EmitVertex();
 }
 
@@ -885,19 +1608,19 @@ void draw_line(float x1, float y1, float x2, float y2) {
     ///////////////////////////////////////////////////////
 
     //(0, 0)
-    gl_Position = vec4(pos1.x, pos1.y, 1.0, 1.0);
+    gl_Position = project(vec2(pos1.x, pos1.y));
     EmitVertex();
     
     //(1, 0)
-    gl_Position = vec4(pos2.x, pos2.y, 1.0, 1.0);
+    gl_Position = project(vec2(pos2.x, pos2.y));
     EmitVertex();
 
     //(0, 1)
-    gl_Position = vec4(pos3.x, pos3.y, 1.0, 1.0);
+    gl_Position = project(vec2(pos3.x, pos3.y));
     EmitVertex();
 
     //(1, 1)
-    gl_Position = vec4(pos4.x, pos4.y, 1.0, 1.0);
+    gl_Position = project(vec2(pos4.x, pos4.y));
     EmitVertex();
 }
 
@@ -935,21 +1658,27 @@ void main() {
 }
 ";
 
-const SPRITE_SHADER_SOURCE: &str = "
+const SPRITE_SHADER_SOURCE: &str = concat!("
 #shader vertex
 #version 330 core
 
 layout(location = 0) in vec4 v_bounds;
 layout(location = 1) in vec4 v_uv_bounds;
 layout(location = 2) in int v_color;
+layout(location = 3) in vec3 v_model_0;
+layout(location = 4) in vec3 v_model_1;
+layout(location = 5) in vec3 v_model_2;
 
 out vec4 uv_bounds;
 out int tight_color;
+out mat3 model;
 
 void main() {
     gl_Position = v_bounds;
     uv_bounds = v_uv_bounds;
     tight_color = v_color;
+    // The rows of the transform
+    model = transpose(mat3(v_model_0, v_model_1, v_model_2));
 }
 
 #shader geometry
@@ -960,10 +1689,18 @@ layout(triangle_strip, max_vertices = 6) out;
 
 in vec4 uv_bounds[];
 in int tight_color[];
+in mat3 model[];
 
 out vec2 uv;
 out vec4 color;
 
+// Through the transform the primitive was drawn with and then the camera, see Camera2D
+uniform mat3 u_view = mat3(1.0);
+
+vec4 project(vec2 position) {
+    return vec4((u_view * model[0] * vec3(position, 1.0)).xy, 1.0, 1.0);
+}
+
 vec4 normal_color(int tight_color) {
     float a = tight_color & 255;
     float b = (tight_color >> 8) & 255;
@@ -986,22 +1723,22 @@ void main() {
     
 
     //(0, 0)
-    gl_Position = vec4(pos.x, pos.y, 1.0, 1.0);
+    gl_Position = project(vec2(pos.x, pos.y));
     uv = vec2(uv_bounds[0].x, uv_bounds[0].y);
     EmitVertex();
     
     //(1, 0)
-    gl_Position = vec4(pos.x + pos.z, pos.y, 1.0, 1.0);
+    gl_Position = project(vec2(pos.x + pos.z, pos.y));
     uv = vec2(uv_bounds[0].x + uv_bounds[0].z, uv_bounds[0].y);
     EmitVertex();
 
     //(0, 1)
-    gl_Position = vec4(pos.x, pos.y + pos.w, 1.0, 1.0);
+    gl_Position = project(vec2(pos.x, pos.y + pos.w));
     uv = vec2(uv_bounds[0].x, uv_bounds[0].y + uv_bounds[0].w);
     EmitVertex();
 
     //(1, 1)
-    gl_Position = vec4(pos.x + pos.z, pos.y + pos.w, 1.0, 1.0);
+    gl_Position = project(vec2(pos.x + pos.z, pos.y + pos.w));
     uv = vec2(uv_bounds[0].x + uv_bounds[0].z, uv_bounds[0].y + uv_bounds[0].w);
     EmitVertex();
 }
@@ -1016,8 +1753,74 @@ layout(location = 0) out vec4 out_color;
 
 uniform sampler2D u_Texture;
 
+// Set while drawing a mask so only the visible part of sprites ends up in it
+uniform int u_discard_transparent;
+", include_str!("../res/shaders/graphics/distance_field.glsl"), "
+
+void main() {
+    if (u_distance_field != 0) {
+        out_color = distance_field_color();
+    }
+    else {
+        vec4 texColor = texture(u_Texture, uv);
+        out_color = texColor * color;
+    }
+    if (u_discard_transparent != 0 && out_color.a <= 0.0) {
+        discard;
+    }
+}
+");
+
+const TRIANGLE_SHADER_SOURCE: &str = "
+#shader vertex
+#version 330 core
+
+layout(location = 0) in vec2 v_position;
+layout(location = 1) in vec2 v_uv;
+layout(location = 2) in int v_color;
+layout(location = 3) in vec3 v_model_0;
+layout(location = 4) in vec3 v_model_1;
+layout(location = 5) in vec3 v_model_2;
+
+out vec2 uv;
+out vec4 color;
+
+// Through the transform the triangles were drawn with and then the camera, see Camera2D
+uniform mat3 u_view = mat3(1.0);
+
+vec4 normal_color(int tight_color) {
+    float a = tight_color & 255;
+    float b = (tight_color >> 8) & 255;
+    float g = (tight_color >> 16) & 255;
+    float r = (tight_color >> 24) & 255;
+    return vec4(r / 255, g / 255, b / 255, a / 255);
+}
+
+void main() {
+    // The rows of the transform
+    mat3 model = transpose(mat3(v_model_0, v_model_1, v_model_2));
+    gl_Position = vec4((u_view * model * vec3(v_position, 1.0)).xy, 1.0, 1.0);
+    uv = v_uv;
+    color = normal_color(v_color);
+}
+
+#shader fragment
+#version 330 core
+
+in vec2 uv;
+in vec4 color;
+
+layout(location = 0) out vec4 out_color;
+
+uniform sampler2D u_Texture;
+uniform int u_textured;
+
 void main() {
-    vec4 texColor = texture(u_Texture, uv);
-	out_color = texColor * color;
+    if (u_textured != 0) {
+        out_color = texture(u_Texture, uv) * color;
+    }
+    else {
+        out_color = color;
+    }
 }
-";
\ No newline at end of file
+";
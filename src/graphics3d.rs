@@ -27,6 +27,9 @@ use crate::shader::Shader;
 use crate::buffer::VertexBufferLayout;
 use crate::texture;
 use crate::font;
+use crate::framebuffer::FrameBuffer;
+use crate::render_target::DrawTarget;
+use crate::text::{self, TextBatch};
 use crate::matrix;
 use crate::color::*;
 
@@ -69,6 +72,7 @@ pub struct Graphics3D {
     has_texture:    bool,
     texture:        texture::TextureRegion, 
     font:           font::Font,
+    text_rendering: text::TextRendering,
     
     shape_ren:      renderer::graphics_renderer::ShapeBatchRenderer,
     sprite_ren:     renderer::graphics_renderer::SpriteBatchRenderer,
@@ -77,6 +81,7 @@ pub struct Graphics3D {
 
     frame_width:      u32,
     frame_height:     u32,
+    draw_target:      DrawTarget,
 
     dw:             DrawingInformation,
     last_draw:      LastDraw,
@@ -117,9 +122,11 @@ impl Graphics3D {
 
             frame_width: win.get_width() as u32,
             frame_height: win.get_height() as u32,
+            draw_target: DrawTarget::new(win.get_width() as u32, win.get_height() as u32),
 
             dw: DrawingInformation::new(),
             last_draw: LastDraw::None,
+            text_rendering: text::TextRendering::new(),
         }
     }
 
@@ -128,9 +135,11 @@ impl Graphics3D {
         while !loop_done {
             match self.frame_buffer_listener.try_recv() {
                 Ok((width, height)) => {
-                    unsafe { gl_call!(gl::Viewport(0, 0, width as i32, height as i32)); };
-                    self.frame_width = width;
-                    self.frame_height = height;
+                    if self.draw_target.resize_window(width, height) {
+                        unsafe { gl_call!(gl::Viewport(0, 0, width as i32, height as i32)); };
+                        self.frame_width = width;
+                        self.frame_height = height;
+                    }
                     //info_log!("message: &str");
                 },
                 Err(_) => loop_done = true
@@ -163,21 +172,25 @@ impl Graphics3D {
             LastDraw::SpritePlane => {
                 self.sprite_ren.shader.bind();
                 self.sprite_ren.shader.upload_from_name_1i("u_primitive", 0);
+                self.text_rendering.upload(&self.sprite_ren.shader);
                 self.sprite_ren.flush();
             },
             LastDraw::SpriteLine => {
                 self.sprite_ren.shader.bind();
                 self.sprite_ren.shader.upload_from_name_1i("u_primitive", 1);
+                self.text_rendering.upload(&self.sprite_ren.shader);
                 self.sprite_ren.flush();
             },
             LastDraw::SpriteSphere => {
                 self.sprite_ren.shader.bind();
                 self.sprite_ren.shader.upload_from_name_1i("u_primitive", 2);
+                self.text_rendering.upload(&self.sprite_ren.shader);
                 self.sprite_ren.flush();
             },
             LastDraw::SpriteCube => {
                 self.sprite_ren.shader.bind();
                 self.sprite_ren.shader.upload_from_name_1i("u_primitive", 3);
+                self.text_rendering.upload(&self.sprite_ren.shader);
                 self.sprite_ren.flush();
             },
             LastDraw::None => {}
@@ -212,6 +225,14 @@ impl Graphics3D {
         self.font = font;
     }
 
+    pub fn set_text_effects(&mut self, effects: text::TextEffects) {
+        self.text_rendering.effects = effects;
+    }
+
+    pub fn text_effects(&self) -> text::TextEffects {
+        self.text_rendering.effects
+    }
+
     pub fn set_color(&mut self, color: Color) {
         self.dw.color = color;
     }
@@ -268,45 +289,83 @@ impl Graphics3D {
     }
 
     pub fn draw_string(&mut self, text: &str, x: f32, y: f32, z: f32, mat: &matrix::Mat4x4f) {
-        let sprite_texture = self.texture.clone();
-        let mut current_advance = 0.0;
+        let run = self.font.shape(text);
+        if self.font.cache_run(&run) {
+            self.flush();
+        }
 
-        for c in text.chars() {
-            if c == ' ' {
-                current_advance += self.font.width() as f32 / self.frame_width as f32 / 2.0;
-                continue;
-            }
+        self.begin_text(self.font.render_mode());
+        let sprite_texture = self.texture.clone();
 
-            let glyph = match self.font.glyph(c) {
+        for positioned in &run.glyphs {
+            let glyph = match self.font.shaped_glyph(positioned) {
                 Some(g) => g,
-                None => match self.font.glyph('?') {
-                    Some(g) => g,
-                    None => {
-                        current_advance += self.font.width() as f32 / self.frame_width as f32 / 2.0;
-                        continue;
-                    }
-                },
+                None => continue,
             };
 
             let texture = glyph.texture().clone();
             let bearing = glyph.bearing();
-            let advance = glyph.advance();
             let size = glyph.size();
 
             //info_log!(("{}", bearing.1));
 
-            let x = (x * self.frame_width as f32).round() / self.frame_width as f32 + bearing.0 / self.frame_width as f32 + current_advance;
-            let y = (y * self.frame_height as f32).round() / self.frame_height as f32 + bearing.1 / self.frame_height as f32 - size.1 / self.frame_height as f32;
+            let x = (x * self.frame_width as f32).round() / self.frame_width as f32 + (positioned.x + bearing.0) / self.frame_width as f32;
+            let y = (y * self.frame_height as f32).round() / self.frame_height as f32 + (positioned.y + bearing.1 - size.1) / self.frame_height as f32;
             let width = size.0 / self.frame_width as f32;
             let height = size.1 / self.frame_height as f32;
 
-            current_advance += advance as f32 / self.frame_width as f32;
+            if size.0 == 0.0 || size.1 == 0.0 {
+                continue;
+            }
 
             self.texture(texture);
             self.fill_plane_with_texture(x, y, z, width, height, mat);
         }
 
         self.texture(sprite_texture);
+        self.end_text();
+    }
+
+    // Draws a block laid out with this font, x and y is where the top left corner of the layout ends up
+    pub fn draw_text_layout(&mut self, layout: &text::TextLayout, x: f32, y: f32, z: f32, mat: &matrix::Mat4x4f) {
+        self.draw_layout(layout, None, x, y, z, mat);
+    }
+
+    // Draws a layout made with TextLayout::styled or RichText, fonts has to be the same fonts in the same order
+    pub fn draw_styled_text(&mut self, layout: &text::TextLayout, fonts: &[&font::Font], x: f32, y: f32, z: f32, mat: &matrix::Mat4x4f) {
+        self.draw_layout(layout, Some(fonts), x, y, z, mat);
+    }
+
+    fn draw_layout(&mut self, layout: &text::TextLayout, fonts: Option<&[&font::Font]>, x: f32, y: f32, z: f32, mat: &matrix::Mat4x4f) {
+        let (changed, quads) = text::glyph_quads(layout, fonts.unwrap_or(&[&self.font]));
+        if changed {
+            self.flush();
+        }
+
+        let color = self.dw.color;
+        let sprite_texture = self.texture.clone();
+
+        for quad in quads {
+            let (qx, qy, width, height) = quad.rect;
+            let x = (x * self.frame_width as f32).round() / self.frame_width as f32 + qx / self.frame_width as f32;
+            let y = (y * self.frame_height as f32).round() / self.frame_height as f32 - qy / self.frame_height as f32;
+            let width = width / self.frame_width as f32;
+            let height = height / self.frame_height as f32;
+
+            self.begin_text(quad.mode);
+            self.dw.color = quad.color.unwrap_or(color);
+            self.texture(quad.texture);
+            self.fill_plane_with_texture(x, y, z, width, height, mat);
+        }
+
+        self.texture(sprite_texture);
+        self.end_text();
+
+        for decoration in layout.decorations() {
+            self.dw.color = decoration.color.unwrap_or(color);
+            self.fill_plane_no_texture(x + decoration.x / self.frame_width as f32, y - (decoration.y + decoration.thickness) / self.frame_height as f32, z, decoration.width / self.frame_width as f32, decoration.thickness / self.frame_height as f32, mat);
+        }
+        self.dw.color = color;
     }
     
     fn fill_plane_with_texture(&mut self, x: f32, y: f32, z: f32, width: f32, height: f32, mat: &matrix::Mat4x4f) {
@@ -436,6 +495,40 @@ impl Graphics3D {
     pub fn sprite_shader(&mut self) -> &mut Shader { &mut self.sprite_ren.shader }
     pub fn shape_shader (&mut self) -> &mut Shader { &mut self.shape_ren.shader  }
 
+    // Draws into frame_buffer instead of the window until the target is set back to None. Pending draws are
+    // flushed first and pixel coordinates follow the size of the target.
+    pub fn set_target(&mut self, frame_buffer: Option<&FrameBuffer>) {
+        self.bind_target(DrawTarget::of(frame_buffer));
+    }
+
+    // Draws into frame_buffer inside f and goes back to the previous target afterwards. If f panics the
+    // framebuffer stays bound.
+    pub fn with_target<F: FnOnce(&mut Self)>(&mut self, frame_buffer: &FrameBuffer, f: F) {
+        let previous = self.draw_target.target();
+        self.set_target(Some(frame_buffer));
+        f(self);
+        self.bind_target(previous);
+    }
+
+    pub fn has_target(&self) -> bool {
+        self.draw_target.target().is_some()
+    }
+
+    fn bind_target(&mut self, target: Option<(u32, u32, u32)>) {
+        self.flush();
+        let (width, height) = self.draw_target.bind(target);
+        unsafe {
+            gl_call!(gl::Viewport(0, 0, width as i32, height as i32));
+        }
+        self.frame_width = width;
+        self.frame_height = height;
+    }
+
     pub fn frame_width(&self) -> u32  { self.frame_width    }
     pub fn frame_height(&self) -> u32 { self.frame_height   }
-}
\ No newline at end of file
+}
+
+impl text::TextBatch for Graphics3D {
+    fn text_rendering(&mut self) -> &mut text::TextRendering { &mut self.text_rendering }
+    fn flush(&mut self) { Graphics3D::flush(self) }
+}
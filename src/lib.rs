@@ -38,12 +38,35 @@ pub mod vector;
 pub mod graphics3d;
 pub mod color;
 pub mod framebuffer;
+pub mod render_target;
+pub mod postprocess;
+pub mod camera;
+pub mod path;
+pub mod compressed_texture;
+pub mod qoi;
+pub mod atlas;
+pub mod text;
+pub mod sdf;
+pub mod rich_text;
+pub mod bmfont;
 
 #[cfg(test)]
 mod tests {
     use renderer::init_gl;
 
-    use crate::{color, font::Font, framebuffer::FrameBuffer, renderer, shader::Shader, texture::{Image, TextureRegion, Texture}, vector::Vec3f};
+    use crate::atlas::AtlasDescriptor;
+    use crate::bmfont::{BitmapFont, BitmapFontDescriptor, ChannelContent};
+    use crate::font::FontRenderMode;
+    use crate::sdf::{DistanceField, Outline};
+    use crate::rich_text::{FontCollection, MarkupError, RichText, TextStyle};
+    use crate::text::{HorizontalAlign, TextLayout, TextLayoutSettings, TextWrap};
+    use crate::compressed_texture::{CompressedFormat, CompressedImage, CompressedLevel};
+    use crate::framebuffer::{AttachmentStorage, ColorFormat, DepthStencilFormat, FrameBufferDescriptor};
+    use crate::render_target::TargetSize;
+    use crate::postprocess::{lut_volume, ping_pong_targets, Lut, PostProcessError};
+    use crate::camera::{Camera2D, CoordinateMode};
+    use crate::path::{FillRule, LineCap, LineJoin, Path, StrokeStyle};
+    use crate::{color, font::Font, framebuffer::FrameBuffer, renderer, shader::Shader, texture::{CompositeOptions, Image, ImageBlendMode, ImageFormat, ImagePack, ImagePackSettings, ResampleFilter, TextureRegion, Texture}, vector::Vec3f};
     use std::time::SystemTime;
 
     use crate::graphics::*;
@@ -65,6 +88,16 @@ mod tests {
         }
     }
 
+    #[test]
+    fn shader_includes() {
+        for file in &["res/shaders/graphics/sprite2d.glsl", "res/shaders/graphics/sprite3d.glsl"] {
+            let source = Shader::load_file(file);
+            assert!(!source.contains("#include"), "{}", file);
+            assert_eq!(source.matches("vec4 distance_field_color()").count(), 1, "{}", file);
+            assert!(Shader::parse(&source)[1].contains("uniform float u_spread;"), "{}", file);
+        }
+    }
+
     #[test]
     fn text_width() {
         let mut win = engine_core::window::Window::new(600, 400, "Graphics").unwrap();
@@ -86,7 +119,7 @@ mod tests {
 
         let mut m = 0.0;
 
-        let font_texture = TextureRegion::new_whole(&gfx.font().atlas);
+        let font_texture = TextureRegion::new_whole(&gfx.font().atlas());
 
         while !win.should_close() {
 
@@ -121,7 +154,7 @@ mod tests {
 
         let mut gfx = Graphics3D::new(&mut win);
         //let font = Font::new("res/fonts/arial.ttf", 100);
-        //let tex = TextureRegion::new_whole(&gfx.font().atlas);
+        //let tex = TextureRegion::new_whole(&gfx.font().atlas());
         let tex = Texture::from_file("res/textures/test.png");
         let tex = TextureRegion::new_whole(&tex);
         gfx.texture(tex);
@@ -222,17 +255,14 @@ mod tests {
 
         let mut gfx = Graphics::new(&mut win);
 
-        let mut fb = FrameBuffer::new(win.get_width() as u32, win.get_height() as u32);
-        fb.bind();
-        info_log!("d");
-        //gfx.clear(color::ORANGE);
-        //&gfx.texture(TextureRegion::new_invalid());
-        gfx.set_color(color::BLUE);
-        gfx.fill_ellipse(-1.0, -1.0, 1.0, 1.0);
-        //gfx.fill_rect(-1.0, -1.0, 0.5, 1.0);
-        gfx.flush();
+        let fb = FrameBuffer::new(win.get_width() as u32, win.get_height() as u32);
+        gfx.with_target(&fb, |gfx| {
+            gfx.set_color(color::BLUE);
+            gfx.fill_ellipse(-1.0, -1.0, 1.0, 1.0);
+        });
+        assert!(!gfx.has_target());
 
-        Image::from_framebuffer(&fb).to_file("res/textures/kuk.png");
+        Image::from_framebuffer(&fb).to_file("res/textures/kuk.png").unwrap();
 
         let mut i = 0;
         FrameBuffer::un_bind();
@@ -243,7 +273,7 @@ mod tests {
             gfx.set_color(color::WHITE);
             gfx.fill_rect(-1.0, -1.0, 0.5, 0.5);
             gfx.flush();
-            Image::new(600, 400, FrameBuffer::get_pixels_standard_frame_buffer(0, 0, 600, 400)).to_file(&format!("res/textures/kuk{}.png", i));
+            Image::new(600, 400, FrameBuffer::get_pixels_standard_frame_buffer(0, 0, 600, 400)).to_file(&format!("res/textures/kuk{}.png", i)).unwrap();
             FrameBuffer::un_bind();
             i += 1;
 
@@ -260,4 +290,866 @@ mod tests {
             win.swap_buffers();
         }
     }
-}
\ No newline at end of file
+
+    fn dds_header(width: u32, height: u32, mip_count: u32, four_cc: &[u8; 4]) -> Vec<u8> {
+        let mut header = vec![0u8; 128];
+        header[0..4].copy_from_slice(b"DDS ");
+        header[4..8].copy_from_slice(&124u32.to_le_bytes());
+        header[12..16].copy_from_slice(&height.to_le_bytes());
+        header[16..20].copy_from_slice(&width.to_le_bytes());
+        header[28..32].copy_from_slice(&mip_count.to_le_bytes());
+        header[84..88].copy_from_slice(four_cc);
+        header
+    }
+
+    #[test]
+    fn dds_bc1() {
+        let mut bytes = dds_header(4, 4, 3, b"DXT1");
+        // Red and blue endpoints, every pixel picks the first third between them.
+        for _ in 0..3 {
+            bytes.extend_from_slice(&[0x00, 0xF8, 0x1F, 0x00, 0xAA, 0xAA, 0xAA, 0xAA]);
+        }
+
+        let image = CompressedImage::from_bytes(&bytes).unwrap();
+        assert!(image.format() == CompressedFormat::Bc1Alpha);
+        assert_eq!(image.levels().len(), 3);
+        assert_eq!((image.levels()[2].width, image.levels()[2].height), (1, 1));
+
+        let decoded = image.decode(0).unwrap();
+        assert_eq!(decoded.get_rgba8(3, 3), 0xAA_00_55_FF);
+
+        bytes.truncate(140);
+        assert!(CompressedImage::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn ktx2_etc2() {
+        let mut bytes = vec![0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+        for value in &[147u32, 1, 4, 4, 0, 0, 1, 1, 0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.resize(80, 0);
+        bytes.extend_from_slice(&104u64.to_le_bytes());
+        bytes.extend_from_slice(&8u64.to_le_bytes());
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        // Differential block with grey base colours and the smallest modifier table.
+        bytes.extend_from_slice(&[0x80, 0x80, 0x80, 0x02, 0x00, 0x00, 0x00, 0x00]);
+
+        let image = CompressedImage::from_bytes(&bytes).unwrap();
+        assert!(image.format() == CompressedFormat::Etc2Rgb);
+        assert!(!image.is_srgb());
+
+        let decoded = image.decode(0).unwrap();
+        for y in 0..4 {
+            for x in 0..4 {
+                assert_eq!(decoded.get_rgba8(x, y), 0x86_86_86_FF);
+            }
+        }
+    }
+
+    #[test]
+    fn compressed_orientation() {
+        // A red block on top of a blue one, DDS files start with the top row
+        let mut bytes = dds_header(4, 8, 1, b"DXT1");
+        bytes.extend_from_slice(&[0x00, 0xF8, 0x00, 0xF8, 0x00, 0x00, 0x00, 0x00]);
+        bytes.extend_from_slice(&[0x1F, 0x00, 0x1F, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        let image = CompressedImage::from_bytes(&bytes).unwrap();
+        assert!(!image.is_bottom_up());
+        let decoded = image.decode(0).unwrap();
+        assert_eq!(decoded.get_rgba8(0, 7), 0xFF_00_00_FF);
+        assert_eq!(decoded.get_rgba8(0, 0), 0x00_00_FF_FF);
+        assert_eq!(&image.level_data_bottom_up(0).unwrap()[0..2], &[0x1F, 0x00]);
+
+        // Alpha 0 in the top row only, flipped blocks decode to the same image
+        let mut block = vec![0xFF, 0x00, 0x49, 0x02, 0x00, 0x00, 0x00, 0x00];
+        block.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00]);
+        let bc3 = CompressedImage::new(CompressedFormat::Bc3, false, vec![CompressedLevel { width: 4, height: 4, data: block.clone() }]).unwrap();
+        let mut flipped = CompressedImage::new(CompressedFormat::Bc3, false, vec![CompressedLevel { width: 4, height: 4, data: bc3.level_data_bottom_up(0).unwrap() }]).unwrap();
+        flipped.set_bottom_up(true);
+        for y in 0..4 {
+            let alpha = if y == 3 { 0x00 } else { 0xFF };
+            assert_eq!(bc3.decode(0).unwrap().get_rgba8(2, y) & 0xFF, alpha);
+            assert_eq!(flipped.decode(0).unwrap().get_rgba8(2, y) & 0xFF, alpha);
+        }
+
+        // Levels shorter than a block keep their rows at the start of the block
+        let mut block = vec![0xFF, 0x00, 0x49, 0x00, 0x00, 0x00, 0x00, 0x00];
+        block.extend_from_slice(&[0x00, 0xF8, 0x1F, 0x00, 0x00, 0x01, 0x00, 0x00]);
+        for height in 1..3 {
+            let top_down = CompressedImage::new(CompressedFormat::Bc3, false, vec![CompressedLevel { width: 4, height, data: block.clone() }]).unwrap();
+            let mut flipped = CompressedImage::new(CompressedFormat::Bc3, false, vec![CompressedLevel { width: 4, height, data: top_down.level_data_bottom_up(0).unwrap() }]).unwrap();
+            flipped.set_bottom_up(true);
+            let (original, flipped) = (top_down.decode(0).unwrap(), flipped.decode(0).unwrap());
+            for y in 0..height {
+                for x in 0..4 {
+                    assert_eq!(original.get_rgba8(x, y), flipped.get_rgba8(x, y));
+                }
+            }
+        }
+
+        // Rows that would have to move between blocks are left to decode, so is a level that isn't there
+        let tall = CompressedImage::new(CompressedFormat::Bc1, false, vec![CompressedLevel { width: 4, height: 6, data: vec![0; 16] }]).unwrap();
+        assert!(tall.level_data_bottom_up(0).is_none());
+        assert!(tall.level_data_bottom_up(1).is_none());
+        assert!(tall.decode(1).is_none());
+        assert!(CompressedImage::new(CompressedFormat::Bc1, false, vec![CompressedLevel { width: 8, height: 4, data: vec![0; 8] }]).is_err());
+        assert!(CompressedImage::new(CompressedFormat::Bc1, false, Vec::new()).is_err());
+
+        // KTX2 files can say they start with the bottom row
+        let mut bytes = vec![0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+        for value in &[147u32, 1, 4, 4, 0, 0, 1, 1, 0, 0, 0, 104, 24] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.resize(80, 0);
+        for value in &[128u64, 8, 0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&18u32.to_le_bytes());
+        bytes.extend_from_slice(b"KTXorientation\0ru\0\0\0");
+        bytes.extend_from_slice(&[0x80, 0x80, 0x80, 0x02, 0x00, 0x00, 0x00, 0x00]);
+        assert!(CompressedImage::from_bytes(&bytes).unwrap().is_bottom_up());
+
+        // Broken level counts and offsets are errors instead of panics
+        bytes[40..44].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(CompressedImage::from_bytes(&bytes).is_err());
+        bytes[40..44].copy_from_slice(&1u32.to_le_bytes());
+        bytes[80..88].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(CompressedImage::from_bytes(&bytes).is_err());
+        let mut bytes = dds_header(4, 4, u32::MAX, b"DXT1");
+        bytes.extend_from_slice(&[0; 8]);
+        assert!(CompressedImage::from_bytes(&bytes).is_err());
+        let mut bytes = dds_header(u32::MAX, u32::MAX, 1, b"DXT1");
+        bytes.extend_from_slice(&[0; 8]);
+        assert!(CompressedImage::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn bc7_mode6() {
+        let mut bytes = dds_header(4, 4, 1, b"DX10");
+        bytes.extend_from_slice(&98u32.to_le_bytes());
+        bytes.extend_from_slice(&[0; 16]);
+        let mut block = [0xFFu8; 16];
+        block[0] = 0x40;
+        bytes.extend_from_slice(&block);
+
+        let image = CompressedImage::from_bytes(&bytes).unwrap();
+        assert!(image.format() == CompressedFormat::Bc7);
+        assert_eq!(image.decode(0).unwrap().get_rgba8(1, 2), 0xFF_FF_FF_FF);
+    }
+
+    #[test]
+    fn image_flip_and_rotate() {
+        let mut image = Image::from_color(3, 2, 0x00_00_00_FF);
+        image.set_rgba8(0, 0, 0xFF_00_00_FF);
+
+        assert_eq!(image.flip_horizontally().get_rgba8(2, 0), 0xFF_00_00_FF);
+        assert_eq!(image.flip_vertically().get_rgba8(0, 1), 0xFF_00_00_FF);
+
+        let rotated = image.rotate_90();
+        assert_eq!((rotated.width(), rotated.height()), (2, 3));
+        assert_eq!(rotated.get_rgba8(0, 2), 0xFF_00_00_FF);
+        assert_eq!(image.rotate_180().get_rgba8(2, 1), 0xFF_00_00_FF);
+        assert_eq!(image.rotate_270().get_rgba8(1, 0), 0xFF_00_00_FF);
+    }
+
+    #[test]
+    fn image_resample_and_blur() {
+        let image = Image::from_color(7, 5, 0x40_80_C0_FF);
+
+        for filter in &[ResampleFilter::Nearest, ResampleFilter::Bilinear, ResampleFilter::Bicubic, ResampleFilter::Lanczos3] {
+            let resampled = image.resample(16, 3, *filter);
+            assert_eq!((resampled.width(), resampled.height()), (16, 3));
+            assert_eq!(resampled.get_rgba8(15, 2), 0x40_80_C0_FF);
+        }
+
+        assert_eq!(image.blur(2.0).get_rgba8(3, 2), 0x40_80_C0_FF);
+    }
+
+    #[test]
+    fn image_draw_blends_and_clips() {
+        let mut image = Image::from_color(4, 4, 0x00_00_FF_FF);
+        image.draw(3, 3, Image::from_color(2, 2, 0xFF_00_00_80));
+
+        assert_eq!(image.get_rgba8(3, 3), 0x80_00_7F_FF);
+        assert_eq!(image.get_rgba8(2, 2), 0x00_00_FF_FF);
+
+        image.premultiply_alpha();
+        image.apply_color_matrix(&ColorMatrix::invert());
+        assert_eq!(image.get_rgba8(0, 0), 0xFF_FF_00_FF);
+    }
+
+    #[test]
+    fn image_composite_modes() {
+        let source = Image::from_color(2, 2, 0x80_80_80_FF);
+
+        let mut options = CompositeOptions::new();
+        options.blend_mode = ImageBlendMode::Multiply;
+        let mut image = Image::from_color(4, 4, 0xFF_FF_00_FF);
+        image.composite(&source, (0, 0, 2, 2), -1, -1, &options);
+        assert_eq!(image.get_rgba8(0, 0), 0x80_80_00_FF);
+        assert_eq!(image.get_rgba8(1, 1), 0xFF_FF_00_FF);
+
+        options.blend_mode = ImageBlendMode::Replace;
+        options.opacity = 0.5;
+        options.clip = Some((2, 2, 1, 1));
+        image.composite(&source, (0, 0, 2, 2), 2, 2, &options);
+        assert_eq!(image.get_rgba8(2, 2), 0x80_80_80_80);
+        assert_eq!(image.get_rgba8(3, 3), 0xFF_FF_00_FF);
+
+        options.blend_mode = ImageBlendMode::Additive;
+        options.opacity = 1.0;
+        options.clip = None;
+        let mut image = Image::from_color(1, 1, 0x80_00_00_FF);
+        image.composite(&source, (0, 0, 1, 1), 0, 0, &options);
+        assert_eq!(image.get_rgba8(0, 0), 0xFF_80_80_FF);
+    }
+
+    #[test]
+    fn image_encode_decode_round_trip() {
+        let mut image = Image::from_color(5, 3, 0x10_20_30_FF);
+        image.composite(&Image::from_color(2, 1, 0xFF_00_80_40), (0, 0, 2, 1), 1, 2, &CompositeOptions { blend_mode: ImageBlendMode::Replace, ..CompositeOptions::new() });
+
+        for &format in &[ImageFormat::Png, ImageFormat::Bmp, ImageFormat::Tga, ImageFormat::Qoi] {
+            let decoded = Image::decode(&image.encode(format).unwrap()).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (5, 3));
+            assert_eq!(decoded.get_rgba8(1, 2), 0xFF_00_80_40, "{:?}", format);
+            assert_eq!(decoded.get_rgba8(4, 0), 0x10_20_30_FF, "{:?}", format);
+        }
+
+        let mut bytes = Vec::new();
+        image.to_writer(&mut bytes, ImageFormat::Jpeg(100)).unwrap();
+        let decoded = Image::from_reader(&mut bytes.as_slice()).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (5, 3));
+
+        assert!(Image::decode(&[1, 2, 3]).is_err());
+
+        let decode = || -> Result<Image, Box<dyn std::error::Error>> { Ok(Image::decode(&[1, 2, 3])?) };
+        assert!(!decode().err().unwrap().to_string().is_empty());
+        assert_eq!(image.to_file("image.unknown").unwrap_err().to_string(), "unknown image format");
+    }
+
+    #[test]
+    fn image_pack_max_rects() {
+        let mut settings = ImagePackSettings::new();
+        settings.max_width = 64;
+        settings.max_height = 32;
+        settings.padding = 1;
+        settings.extrude = 1;
+        settings.allow_rotation = true;
+        settings.trim = true;
+        let mut pack = ImagePack::with_settings(settings);
+
+        let mut images = Vec::new();
+        for i in 0..20u32 {
+            let mut image = Image::from_color(10 + i % 7, 4 + i % 5, 0x00_00_00_00);
+            let inner = Image::from_color(image.width() - 2, image.height() - 1, 0x10_00_00_FF + (i << 16));
+            image.draw(1, 0, inner);
+            images.push((i.to_string(), image));
+        }
+        let mut tall = Image::from_color(3, 40, 0x00_00_00_00);
+        tall.draw(0, 1, Image::from_color(3, 38, 0xFF_FF_FF_FF));
+        images.push(("tall".to_string(), tall));
+
+        pack.add_images(images.clone());
+
+        for (name, image) in &images {
+            let packed = pack.get(name).unwrap();
+            assert!(packed.x >= 1 && packed.y >= 1);
+            assert_eq!(packed.offset_y, if name == "tall" { 1 } else { 0 });
+            let unpacked = pack.get_image(name);
+            assert_eq!((unpacked.width(), unpacked.height()), (image.width(), image.height()));
+            assert_eq!(unpacked.get_rgba8(0, 0), image.get_rgba8(0, 0), "{}", name);
+            assert_eq!(unpacked.get_rgba8(image.width() - 2, image.height() / 2), image.get_rgba8(image.width() - 2, image.height() / 2), "{}", name);
+        }
+
+        let all: Vec<_> = pack.images().values().cloned().collect();
+        for a in &all {
+            let page = pack.get_page(a.page);
+            assert!(a.x + a.width < page.width() && a.y + a.height < page.height());
+            assert_eq!(page.get_rgba8(a.x - 1, a.y), page.get_rgba8(a.x, a.y));
+            for b in &all {
+                if a != b && a.page == b.page {
+                    assert!(a.x + a.width + 1 < b.x || b.x + b.width + 1 < a.x || a.y + a.height + 1 < b.y || b.y + b.height + 1 < a.y);
+                }
+            }
+        }
+
+        assert!(pack.page_count() >= 2);
+        assert!(pack.get("tall").unwrap().rotated);
+        assert!(pack.efficiency() > 0.3 && pack.efficiency() <= 1.0);
+    }
+
+    #[test]
+    fn atlas_descriptor_round_trip() {
+        let mut settings = ImagePackSettings::new();
+        settings.trim = true;
+        let mut pack = ImagePack::with_settings(settings);
+        let mut image = Image::from_color(8, 8, 0x00_00_00_00);
+        image.draw(2, 1, Image::from_color(4, 5, 0xFF_00_00_FF));
+        pack.add_image("a", image);
+        pack.add_image("b", Image::from_color(3, 3, 0x00_FF_00_FF));
+
+        let descriptor = AtlasDescriptor::from_image_pack(&pack, "sprites");
+        assert_eq!(descriptor.pages[0].file, "sprites_0.png");
+        let a = descriptor.region("a").unwrap();
+        assert_eq!((a.width, a.height, a.offset_x, a.offset_y, a.source_width), (4, 5, 2, 1, 8));
+
+        assert_eq!(descriptor.to_json().parse::<AtlasDescriptor>().unwrap(), descriptor);
+
+        let directory = std::env::temp_dir().join("engine_renderer_atlas_test");
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("sprites.json");
+        pack.save(path.to_str().unwrap()).unwrap();
+        assert_eq!(AtlasDescriptor::from_file(path.to_str().unwrap()).unwrap(), descriptor);
+        assert_eq!(Image::from_file(directory.join("sprites_0.png").to_str().unwrap()).get_rgba8(a.x, a.y), 0xFF_00_00_FF);
+    }
+
+    #[test]
+    fn atlas_save_and_reload() {
+        let mut settings = ImagePackSettings::new();
+        settings.max_width = 16;
+        settings.max_height = 4;
+        settings.allow_rotation = true;
+        let mut pack = ImagePack::with_settings(settings);
+        let mut image = Image::from_color(2, 8, 0x00_00_00_FF);
+        for y in 0..8 {
+            image.set_rgba8(1, y, 0xFF_00_00_FF | (y << 8));
+        }
+        pack.add_image("tall", image.clone());
+        pack.add_image("small", Image::from_color(2, 2, 0x00_FF_00_FF));
+        assert!(pack.get("tall").unwrap().rotated);
+
+        let directory = std::env::temp_dir().join("engine_renderer_atlas_reload_test");
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("pack.json");
+        pack.save(path.to_str().unwrap()).unwrap();
+
+        let reloaded = ImagePack::from_file(path.to_str().unwrap(), settings).unwrap();
+        assert_eq!(reloaded.page_count(), pack.page_count());
+        for page in 0..pack.page_count() {
+            assert_eq!(reloaded.get_page(page).get_buffer(), pack.get_page(page).get_buffer());
+        }
+        for name in &["tall", "small"] {
+            assert_eq!(reloaded.get(name), pack.get(name));
+        }
+        assert_eq!(reloaded.get_image("tall").get_buffer(), image.get_buffer());
+    }
+
+    #[test]
+    fn atlas_import_texture_packer_and_aseprite() {
+        let hash = r#"{"frames": {
+            "hero.png": {"frame": {"x":0,"y":0,"w":10,"h":20}, "rotated": false, "trimmed": true,
+                "spriteSourceSize": {"x":1,"y":2,"w":10,"h":20}, "sourceSize": {"w":12,"h":24}, "pivot": {"x":0.5,"y":1}},
+            "gem.png": {"frame": {"x":10,"y":0,"w":8,"h":4}, "rotated": true, "trimmed": false,
+                "spriteSourceSize": {"x":0,"y":0,"w":8,"h":4}, "sourceSize": {"w":8,"h":4}}
+            },
+            "meta": {"app": "https://www.codeandweb.com/texturepacker", "image": "sheet.png", "size": {"w":32,"h":32}}}"#;
+        let descriptor = hash.parse::<AtlasDescriptor>().unwrap();
+        let hero = descriptor.region("hero.png").unwrap();
+        assert_eq!((hero.x, hero.y, hero.width, hero.height), (0, 12, 10, 20));
+        assert_eq!((hero.offset_x, hero.offset_y, hero.pivot), (1, 2, (0.5, 0.0)));
+        let gem = descriptor.region("gem.png").unwrap();
+        assert_eq!((gem.x, gem.y, gem.width, gem.height, gem.rotation), (10, 24, 4, 8, 90));
+
+        let array = r#"{"frames": [
+            {"filename": "walk 0", "frame": {"x":0,"y":0,"w":4,"h":4}, "duration": 100},
+            {"filename": "walk 1", "frame": {"x":4,"y":0,"w":4,"h":4}, "duration": 150},
+            {"filename": "walk 2", "frame": {"x":8,"y":0,"w":4,"h":4}, "duration": 100}],
+            "meta": {"app": "https://www.aseprite.org/", "image": "walk.png", "size": {"w":12,"h":4},
+                "frameTags": [{"name": "walk", "from": 0, "to": 2, "direction": "pingpong"}]}}"#;
+        let descriptor = array.parse::<AtlasDescriptor>().unwrap();
+        assert_eq!(descriptor.regions.len(), 3);
+        let frames: Vec<(&str, u32)> = descriptor.animations[0].frames.iter().map(|(name, duration)| (name.as_str(), *duration)).collect();
+        assert_eq!(frames, vec![("walk 0", 100), ("walk 1", 150), ("walk 2", 100), ("walk 1", 150)]);
+
+        assert!(AtlasDescriptor::from_texture_packer_json(r#"{"frames": {}, "meta": {}}"#).is_err());
+        let overflowing = hash.replace(r#""x":10,"y":0,"w":8"#, r#""x":4294967295,"y":0,"w":8"#);
+        assert!(overflowing.parse::<AtlasDescriptor>().is_err());
+    }
+
+    #[test]
+    fn atlas_import_libgdx() {
+        let legacy = "\nsheet.png\nsize: 64, 32\nformat: RGBA8888\nfilter: Nearest, Nearest\nrepeat: none\nbullet\n  rotate: true\n  xy: 2, 4\n  size: 6, 10\n  orig: 8, 12\n  offset: 1, 1\n  index: 3\n";
+        let descriptor = legacy.parse::<AtlasDescriptor>().unwrap();
+        let bullet = descriptor.region("bullet_3").unwrap();
+        assert_eq!((bullet.x, bullet.y, bullet.width, bullet.height, bullet.rotation), (2, 22, 10, 6, 270));
+        assert_eq!((bullet.offset_x, bullet.offset_y, bullet.source_width, bullet.source_height), (1, 1, 8, 12));
+
+        let modern = "first.png\nsize:16,16\nfilter:Linear,Linear\ntile\nbounds:0,0,8,8\n\nsecond.png\nsize:16,16\nbig\nbounds:0,0,16,16\noffsets:0,0,16,16\n";
+        let descriptor = modern.parse::<AtlasDescriptor>().unwrap();
+        assert_eq!(descriptor.pages.len(), 2);
+        assert_eq!(descriptor.region("tile").unwrap().y, 8);
+        assert_eq!(descriptor.region("big").unwrap().page, 1);
+        assert!(modern.replace("bounds:0,0,8,8", "bounds:4294967295,0,8,8").parse::<AtlasDescriptor>().is_err());
+    }
+
+    #[test]
+    fn font_glyphs_on_demand() {
+        let mut font = Font::new("res/fonts/arial.ttf", 32);
+
+        let space = font.glyph(' ').unwrap();
+        assert!(space.advance() > 0.0 && space.size() == (0.0, 0.0));
+        for c in "åäöÅÄÖ€".chars() {
+            let glyph = font.glyph(c).unwrap();
+            assert!(glyph.size().0 > 0.0 && glyph.size().1 > 0.0, "{}", c);
+            assert!(!glyph.texture().is_valid());
+        }
+        assert!(font.text_width("åäö") > font.text_width("å"));
+
+        // Glyphs the primary face has never come from a fallback, characters no face has stay missing
+        assert!(font.add_fallback("res/fonts/arial.ttf"));
+        assert!(font.shape("Aå").glyphs.iter().all(|glyph| glyph.face == 0));
+        assert!(font.glyph('\u{E000}').is_none());
+        assert!(!font.add_fallback("res/fonts/missing.ttf"));
+    }
+
+    #[test]
+    fn font_kerning_runs() {
+        let font = Font::new("res/fonts/arial.ttf", 32);
+
+        let a = font.glyph('A').unwrap().advance();
+        let v = font.glyph('V').unwrap().advance();
+        let run = font.shape("AV");
+        assert_eq!(run.glyphs.len(), 2);
+        assert_eq!(run.glyphs[1].cluster, 1);
+        assert!(run.width < a + v);
+        assert!(run.glyphs[1].x < a);
+        assert_eq!(font.text_width("AV"), run.width);
+
+        let run = font.shape("a\u{10FFFF}");
+        assert_eq!(run.glyphs.len(), 2);
+        assert_eq!(font.shaped_glyph(&run.glyphs[1]).unwrap().advance(), font.glyph('?').unwrap().advance());
+    }
+
+    #[test]
+    fn text_layout_wrapping_and_alignment() {
+        let font = Font::new("res/fonts/arial.ttf", 16);
+        let text = "The quick brown fox jumps over the lazy dog\nsecond paragraph";
+
+        let mut settings = TextLayoutSettings::new();
+        let layout = TextLayout::new(&font, text, &settings);
+        assert_eq!(layout.lines().len(), 2);
+        assert_eq!(&text[layout.lines()[1].start..layout.lines()[1].end], "second paragraph");
+
+        let max_width = font.text_width("The quick brown") + 1.0;
+        settings.max_width = Some(max_width);
+        settings.align = HorizontalAlign::Right;
+        let layout = TextLayout::new(&font, text, &settings);
+        assert!(layout.lines().len() > 3);
+        assert_eq!(&text[layout.lines()[0].start..layout.lines()[0].end], "The quick brown ");
+        for line in layout.lines() {
+            assert!(line.width <= max_width);
+            assert!((line.x + line.width - max_width).abs() < 0.01);
+        }
+        assert!(layout.height() >= layout.lines().len() as f32 * font.metrics().line_height);
+
+        settings.align = HorizontalAlign::Justify;
+        let layout = TextLayout::new(&font, text, &settings);
+        assert_eq!(layout.lines()[0].width, max_width);
+        assert!(layout.lines().last().unwrap().width < max_width);
+
+        settings.wrap = TextWrap::Character;
+        settings.max_width = Some(font.text_width("abc"));
+        let layout = TextLayout::new(&font, "abcdefgh", &settings);
+        assert_eq!(layout.lines().len(), 3);
+
+        let mut settings = TextLayoutSettings::new();
+        settings.max_width = Some(max_width);
+        settings.max_lines = Some(1);
+        settings.ellipsis = Some("...".to_string());
+        let layout = TextLayout::new(&font, text, &settings);
+        assert_eq!(layout.lines().len(), 1);
+        assert!(layout.lines()[0].truncated && layout.width() <= max_width);
+
+        let glyph = layout.glyphs()[2];
+        let (x, y, width, height) = glyph.rect;
+        assert_eq!(layout.glyph_at(x + width / 2.0, y + height / 2.0), Some(2));
+        assert_eq!(layout.glyph_at(-1.0, y), None);
+    }
+
+    #[test]
+    fn distance_field_glyphs() {
+        let mut square = Outline::new();
+        square.move_to(4.0, 4.0);
+        square.line_to(4.0, 20.0);
+        square.line_to(20.0, 20.0);
+        square.line_to(20.0, 4.0);
+        square.close();
+
+        let median = |rgba: u32| {
+            let (r, g, b) = (rgba >> 24, rgba >> 16 & 0xFF, rgba >> 8 & 0xFF);
+            r.max(g).min(r.min(g).max(b))
+        };
+        for field in [DistanceField::Sdf, DistanceField::Msdf].iter() {
+            let image = square.distance_field(*field, 24, 24, 0.0, 0.0, 4.0);
+            assert_eq!(image.get_rgba8(12, 12) & 0xFF, 0xFF);
+            // 3.5 pixels outside with a spread of 4
+            assert_eq!(image.get_rgba8(0, 12) & 0xFF, 16);
+            assert!(median(image.get_rgba8(12, 12)) > 128 && median(image.get_rgba8(1, 12)) < 128);
+            // Just outside the corner both sides are far enough to stay outside
+            assert!(median(image.get_rgba8(21, 21)) < 128);
+            assert!(median(image.get_rgba8(19, 19)) > 128);
+        }
+
+        let mut font = Font::new("res/fonts/arial.ttf", 32);
+        let bitmap = font.glyph('O').unwrap();
+        font.set_render_mode(FontRenderMode::Msdf { spread: 4 });
+        let field = font.glyph('O').unwrap();
+        assert!(field.size().0 >= bitmap.size().0 + 7.0 && field.size().1 >= bitmap.size().1 + 7.0);
+        assert!((field.advance() - bitmap.advance()).abs() <= 1.0);
+        assert_eq!(font.glyph(' ').unwrap().size(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn rich_text_markup() {
+        let base = TextStyle::new("arial", 16);
+        let text = RichText::parse("Hello [color=#ff0000]red [b]bold[/b][/color] [u][size=32]big[/size][/u] [[x]", &base).unwrap();
+        assert_eq!(text.text(), "Hello red bold big [x]");
+        let spans = text.spans();
+        assert_eq!(spans[1].text, "red ");
+        assert_eq!(spans[1].style.color, Some(Color::from(0xFF_00_00_FFu32)));
+        assert!(spans[2].style.bold && spans[2].style.color.is_some());
+        assert!(spans[4].style.underline && spans[4].style.size == 32);
+
+        assert_eq!(RichText::parse("[b]x[/i]", &base), Err(MarkupError::UnexpectedClosingTag("i".to_string())));
+        assert_eq!(RichText::parse("[wobble]x", &base), Err(MarkupError::UnknownTag("wobble".to_string())));
+        assert_eq!(RichText::parse("[color=red]x", &base), Err(MarkupError::InvalidValue("color=red".to_string())));
+        assert_eq!(RichText::parse("ab[b", &base), Err(MarkupError::UnterminatedTag(2)));
+
+        let mut fonts = FontCollection::new();
+        fonts.add_family("arial", "res/fonts/arial.ttf", None, None, None);
+        let mut settings = TextLayoutSettings::new();
+        settings.max_width = Some(120.0);
+        let layout = text.layout(&mut fonts, &settings);
+        assert_eq!(fonts.fonts().len(), 3);
+
+        // Wrapping works across the style changes and the line with the big font is higher
+        assert!(layout.lines().len() >= 2);
+        let lines = layout.lines();
+        assert!(lines.iter().any(|line| line.height > lines[0].height));
+
+        let plain = text.text();
+        let red = layout.glyphs().iter().find(|glyph| &plain[glyph.glyph.cluster..glyph.glyph.cluster + 1] == "r").unwrap();
+        assert_eq!(red.color, Some(Color::from(0xFF_00_00_FFu32)));
+        assert_eq!(layout.decorations().len(), 1);
+        assert!(layout.decorations()[0].width > 0.0);
+    }
+
+    #[test]
+    fn font_metrics() {
+        let bytes = std::fs::read("res/fonts/arial.ttf").unwrap();
+        assert_eq!(Font::face_count(&bytes), 1);
+        assert_eq!(Font::face_count(b"ttcf\0\x01\0\0\0\0\0\x03"), 3);
+
+        let font = Font::from_bytes(bytes.clone(), 0, 32);
+        assert!(font.is_valid());
+        let metrics = font.metrics();
+        assert_eq!(metrics.size, 32.0);
+        assert!(metrics.ascender > 25.0 && metrics.ascender < 32.0);
+        assert!(metrics.descender > 4.0 && metrics.descender < 10.0);
+        assert!(metrics.line_height >= metrics.ascender + metrics.descender);
+        assert!(metrics.x_height > 10.0 && metrics.x_height < metrics.cap_height && metrics.cap_height < metrics.ascender);
+        assert!(metrics.underline_position > 0.0 && metrics.underline_thickness >= 1.0);
+        assert!(metrics.strikeout_position > 0.0 && metrics.strikeout_position < metrics.x_height);
+        assert_eq!(font.height(), metrics.line_height.ceil() as u32);
+        assert_eq!(Font::new("res/fonts/arial.ttf", 32).metrics(), metrics);
+
+        assert!(!Font::from_bytes(bytes, 1, 32).is_valid());
+        assert!(!Font::from_bytes(vec![0; 16], 0, 32).is_valid());
+    }
+
+    #[test]
+    fn bitmap_fonts() {
+        let text = "info face=\"Pixel Font\" size=-8 bold=0 padding=0,0,0,0\n\
+            common lineHeight=10 base=8 scaleW=16 scaleH=8 pages=1 packed=1 alphaChnl=1 redChnl=0 greenChnl=0 blueChnl=0\n\
+            page id=0 file=\"pixel_0.png\"\n\
+            chars count=3\n\
+            char id=65 x=0 y=0 width=4 height=6 xoffset=0 yoffset=2 xadvance=5 page=0 chnl=15\n\
+            char id=86 x=4 y=0 width=4 height=6 xoffset=1 yoffset=2 xadvance=5 page=0 chnl=4\n\
+            char id=32 x=0 y=0 width=0 height=0 xoffset=0 yoffset=0 xadvance=3 page=0 chnl=15\n\
+            kernings count=1\n\
+            kerning first=65 second=86 amount=-1\n";
+        let descriptor = BitmapFontDescriptor::parse(text.as_bytes()).unwrap();
+        assert_eq!(descriptor.face, "Pixel Font");
+        assert!(descriptor.packed);
+        assert_eq!(descriptor.channels, [ChannelContent::Glyph, ChannelContent::Glyph, ChannelContent::Glyph, ChannelContent::Outline]);
+        assert_eq!(descriptor.chars[1].y, 2);
+
+        let block = |kind: u8, data: Vec<u8>| {
+            let mut bytes = vec![kind];
+            bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            bytes.extend(data);
+            bytes
+        };
+        let char_record = |id: u32, x: u16, width: u16, height: u16, x_offset: i16, y_offset: i16, x_advance: i16, channel: u8| {
+            let mut bytes = id.to_le_bytes().to_vec();
+            for value in &[x, 0, width, height, x_offset as u16, y_offset as u16, x_advance as u16] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            bytes.extend_from_slice(&[0, channel]);
+            bytes
+        };
+        let mut info = vec![0xF8, 0xFF, 0, 0, 100, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        info.extend_from_slice(b"Pixel Font\0");
+        let common = vec![10, 0, 8, 0, 16, 0, 8, 0, 1, 0, 0x80, 1, 0, 0, 0];
+        let mut chars = char_record(65, 0, 4, 6, 0, 2, 5, 15);
+        chars.extend(char_record(86, 4, 4, 6, 1, 2, 5, 4));
+        chars.extend(char_record(32, 0, 0, 0, 0, 0, 3, 15));
+        let mut kernings = 65u32.to_le_bytes().to_vec();
+        kernings.extend_from_slice(&86u32.to_le_bytes());
+        kernings.extend_from_slice(&(-1i16).to_le_bytes());
+
+        let mut binary = b"BMF\x03".to_vec();
+        binary.extend(block(1, info));
+        binary.extend(block(2, common));
+        binary.extend(block(3, b"pixel_0.png\0".to_vec()));
+        binary.extend(block(4, chars));
+        binary.extend(block(5, kernings));
+        assert_eq!(BitmapFontDescriptor::parse(&binary).unwrap(), descriptor);
+        assert!(BitmapFontDescriptor::parse(&binary[..binary.len() - 3]).is_err());
+        assert!(BitmapFontDescriptor::parse(text.replace("x=4 y=0", "x=-4 y=0").as_bytes()).is_err());
+        assert!(BitmapFontDescriptor::parse(text.replace("scaleH=8", "scaleH=-8").as_bytes()).is_err());
+        assert!(BitmapFontDescriptor::parse(text.replace("y=0 width=4", "y=4294967295 width=4").as_bytes()).is_err());
+        assert!(BitmapFontDescriptor::parse(text.replace("page id=0", "page id=1").as_bytes()).is_err());
+        assert!(BitmapFontDescriptor::parse(text.replace("pages=1 ", "").replace("page id=0", "page id=4000000000").as_bytes()).is_err());
+
+        // A is white, V is packed into the red channel
+        let mut page = Image::from_color(16, 8, 0x00_00_00_00);
+        page.set_rgba8(0, 7, 0xFF_FF_FF_FF);
+        page.set_rgba8(5, 2, 0x80_00_00_00);
+        let font = Font::from_bitmap_font(BitmapFont::new(descriptor.clone(), vec![page.clone()]).unwrap());
+        assert!(font.is_valid());
+        let mut overflowing = descriptor.clone();
+        overflowing.chars[0].x = u32::MAX;
+        assert!(BitmapFont::new(overflowing, vec![page.clone()]).is_err());
+        assert!(BitmapFont::new(descriptor, Vec::new()).is_err());
+
+        assert_eq!(font.metrics().line_height, 10.0);
+        assert_eq!(font.metrics().ascender, 8.0);
+        assert_eq!(font.height(), 10);
+        assert_eq!(font.kerning('A', 'V'), -1.0);
+        assert_eq!(font.text_width("AV A"), 5.0 - 1.0 + 5.0 + 3.0 + 5.0);
+
+        let glyph = font.glyph('V').unwrap();
+        assert_eq!((glyph.size(), glyph.bearing(), glyph.advance()), ((4.0, 6.0), (1.0, 6.0), 5.0));
+        let bitmap = BitmapFont::new(BitmapFontDescriptor::parse(text.as_bytes()).unwrap(), vec![page]).unwrap();
+        assert_eq!(bitmap.glyph_image('A' as u32).unwrap().get_rgba8(0, 5), 0xFF_FF_FF_FF);
+        assert_eq!(bitmap.glyph_image('V' as u32).unwrap().get_rgba8(1, 0), 0x80_80_80_80);
+    }
+
+    #[test]
+    fn framebuffer_descriptor() {
+        let descriptor = FrameBufferDescriptor::new(320, 180)
+            .with_color_attachment(ColorFormat::Rgba16f)
+            .with_color_attachment(ColorFormat::R32ui)
+            .with_depth_stencil(DepthStencilFormat::Depth24Stencil8, AttachmentStorage::Renderbuffer);
+        assert_eq!(descriptor.color_attachments, vec![ColorFormat::Rgba8, ColorFormat::Rgba16f, ColorFormat::R32ui]);
+        assert_eq!(ColorFormat::Rgba16f.gl_formats(), (gl::RGBA16F, gl::RGBA, gl::HALF_FLOAT));
+        assert_eq!(ColorFormat::R32ui.gl_formats().1, gl::RED_INTEGER);
+
+        let depth_stencil = descriptor.depth_stencil.unwrap();
+        assert_eq!(depth_stencil.format.attachment_point(), gl::DEPTH_STENCIL_ATTACHMENT);
+        assert!(depth_stencil.format.has_depth() && depth_stencil.format.has_stencil());
+        assert_eq!(DepthStencilFormat::Depth32f.attachment_point(), gl::DEPTH_ATTACHMENT);
+        assert!(!DepthStencilFormat::Depth32f.has_stencil());
+        assert_eq!(DepthStencilFormat::Stencil8.attachment_point(), gl::STENCIL_ATTACHMENT);
+        assert!(!DepthStencilFormat::Stencil8.has_depth());
+
+        let multisampled = FrameBufferDescriptor::new(320, 180).with_samples(4);
+        assert_eq!((multisampled.samples, multisampled.color_storage), (4, AttachmentStorage::Texture));
+        assert_eq!(FrameBufferDescriptor::new(320, 180).with_samples(0).samples, 1);
+
+        assert_eq!(TargetSize::Full.resolve(1280, 720), (1280, 720));
+        assert_eq!(TargetSize::Half.resolve(1280, 721), (640, 360));
+        assert_eq!(TargetSize::Scaled(0.25).resolve(1280, 720), (320, 180));
+        assert_eq!(TargetSize::Fixed(64, 64).resolve(1280, 720), (64, 64));
+        assert_eq!(TargetSize::Half.resolve(1, 1), (1, 1));
+    }
+
+    #[test]
+    fn color_grading_lut() {
+        let (size, texels) = lut_volume(&Lut::identity_image(4)).unwrap();
+        assert_eq!(size, 4);
+        assert_eq!(texels.len(), 4 * 4 * 4 * 4);
+
+        // Texels are ordered by blue, green and red, the identity table maps every texel to its own coordinate
+        let texel = |r: usize, g: usize, b: usize| &texels[((b * 4 + g) * 4 + r) * 4..][..4];
+        assert_eq!(texel(0, 0, 0), &[0, 0, 0, 255]);
+        assert_eq!(texel(1, 2, 3), &[85, 170, 255, 255]);
+        assert_eq!(texel(3, 0, 1), &[255, 0, 85, 255]);
+
+        assert!(matches!(lut_volume(&Image::from_color(20, 4, 0xFFFFFFFF)), Err(PostProcessError::InvalidLutSize(20, 4))));
+        assert!(matches!(lut_volume(&Image::from_color(1, 1, 0xFFFFFFFF)), Err(PostProcessError::InvalidLutSize(1, 1))));
+    }
+
+    #[test]
+    fn post_process_ping_pong() {
+        assert!(ping_pong_targets(0).is_empty());
+        assert_eq!(ping_pong_targets(1), vec![None]);
+        assert_eq!(ping_pong_targets(4), vec![Some(0), Some(1), Some(0), None]);
+
+        // Every pass reads what the one before it drew and never the buffer it draws into
+        let targets = ping_pong_targets(5);
+        for pair in targets.windows(2) {
+            assert_ne!(pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn camera_2d() {
+        let close = |a: (f32, f32), b: (f32, f32)| (a.0 - b.0).abs() < 1e-3 && (a.1 - b.1).abs() < 1e-3;
+
+        let mut camera = Camera2D::new(CoordinateMode::PixelsTopLeft);
+        camera.resize(800, 600);
+        assert!(close(camera.world_to_screen(10.0, 20.0), (10.0, 20.0)));
+        let ndc = Mat3x3f::mult_vec(&camera.matrix(), Vec3f::new(0.0, 0.0, 1.0));
+        assert!(close((ndc.x, ndc.y), (-1.0, 1.0)));
+        assert_eq!(camera.pixel_scale(), (1.0, -1.0));
+
+        // Zoom and rotation happen around the center of the viewport
+        camera.set_zoom(2.0);
+        assert!(close(camera.world_to_screen(400.0, 300.0), (400.0, 300.0)));
+        assert!(close(camera.world_to_screen(500.0, 300.0), (600.0, 300.0)));
+        camera.set_rotation(std::f32::consts::FRAC_PI_2);
+        camera.set_position(-25.0, 40.0);
+        let world = camera.screen_to_world(123.0, 456.0);
+        assert!(close(camera.world_to_screen(world.0, world.1), (123.0, 456.0)));
+
+        let mut camera = Camera2D::new(CoordinateMode::PixelsBottomLeft);
+        camera.resize(800, 600);
+        assert!(close(camera.world_to_screen(10.0, 20.0), (10.0, 580.0)));
+        camera.set_viewport(0.5, 0.0, 0.5, 1.0);
+        assert_eq!(camera.pixel_viewport(), (400, 0, 400, 600));
+        assert!(close(camera.world_to_screen(0.0, 0.0), (400.0, 600.0)));
+
+        // 320x180 letterboxed into 800x600 leaves bars at the top and bottom
+        let mut camera = Camera2D::new(CoordinateMode::Virtual { width: 320.0, height: 180.0 });
+        camera.resize(800, 600);
+        assert_eq!(camera.pixel_viewport(), (0, 75, 800, 450));
+        assert!(close(camera.world_to_screen(0.0, 0.0), (0.0, 75.0)));
+        assert!(close(camera.screen_to_world(400.0, 300.0), (160.0, 90.0)));
+        assert!(!camera.contains_screen_point(400.0, 10.0));
+        assert!(camera.contains_screen_point(400.0, 300.0));
+
+        let mut camera = Camera2D::new(CoordinateMode::Ndc);
+        camera.resize(800, 600);
+        assert_eq!(camera.pixel_scale(), (2.0 / 800.0, 2.0 / 600.0));
+        assert!(close(camera.world_to_screen(0.0, 0.0), (400.0, 300.0)));
+
+        assert!(Mat3x3f::scale(0.0, 1.0).inverse().is_none());
+        let inverse = Mat3x3f::rotation(0.3).inverse().unwrap();
+        let v = Mat3x3f::mult_vec(&Mat3x3f::mult(&inverse, &Mat3x3f::rotation(0.3)), Vec3f::new(2.0, 3.0, 1.0));
+        assert!(close((v.x, v.y), (2.0, 3.0)));
+    }
+
+    #[test]
+    fn transform_matrices() {
+        let close = |v: Vec3f, x: f32, y: f32| (v.x - x).abs() < 1e-4 && (v.y - y).abs() < 1e-4;
+
+        let skew = Mat3x3f::skew(std::f32::consts::FRAC_PI_4, 0.0);
+        assert!(close(Mat3x3f::mult_vec(&skew, Vec3f::new(0.0, 1.0, 1.0)), 1.0, 1.0));
+        assert!(close(Mat3x3f::mult_vec(&skew, Vec3f::new(1.0, 0.0, 1.0)), 1.0, 0.0));
+
+        // Children are placed in the space of their parent, the same order Graphics::translate and rotate use
+        let parent = Mat3x3f::mult(&Mat3x3f::translation(10.0, 0.0), &Mat3x3f::rotation(std::f32::consts::FRAC_PI_2));
+        let child = Mat3x3f::mult(&parent, &Mat3x3f::translation(2.0, 0.0));
+        assert!(close(Mat3x3f::mult_vec(&child, Vec3f::new(0.0, 0.0, 1.0)), 10.0, 2.0));
+
+        let transposed = Mat3x3f::translation(3.0, 4.0).transpose();
+        unsafe { assert_eq!((transposed.m[2][0], transposed.m[2][1]), (3.0, 4.0)); }
+    }
+
+    #[test]
+    fn clip_rects() {
+        assert_eq!(intersect_clip_rects((0, 0, 100, 100), (50, 25, 100, 50)), (50, 25, 50, 50));
+        assert_eq!(intersect_clip_rects((10, 10, 20, 20), (10, 10, 20, 20)), (10, 10, 20, 20));
+        // Rects that don't overlap clip everything away
+        assert_eq!(intersect_clip_rects((0, 0, 10, 10), (20, 20, 10, 10)).2, 0);
+        assert_eq!(intersect_clip_rects((0, 0, 10, 10), (20, 20, 10, 10)).3, 0);
+    }
+
+    #[test]
+    fn blend_modes() {
+        use crate::renderer::std_renderer::{BlendEquation, BlendFactor};
+
+        let (factors, equations) = BlendMode::Alpha.factors().unwrap();
+        assert_eq!(factors, [BlendFactor::SrcAlpha, BlendFactor::OneMinusSrcAlpha, BlendFactor::One, BlendFactor::OneMinusSrcAlpha]);
+        assert_eq!(equations, [BlendEquation::Add, BlendEquation::Add]);
+        assert_eq!(BlendMode::Premultiplied.factors().unwrap().0[0], BlendFactor::One);
+        assert!(BlendMode::Replace.factors().is_none());
+
+        let darken = BlendMode::custom(BlendFactor::One, BlendFactor::One, BlendEquation::Min);
+        assert_eq!(darken.factors().unwrap(), ([BlendFactor::One; 4], [BlendEquation::Min; 2]));
+        let split = BlendMode::Custom {
+            src_rgb: BlendFactor::One, dst_rgb: BlendFactor::One, src_alpha: BlendFactor::Zero, dst_alpha: BlendFactor::One,
+            rgb_equation: BlendEquation::ReverseSubtract, alpha_equation: BlendEquation::Add,
+        };
+        assert_eq!(split.factors().unwrap().1, [BlendEquation::ReverseSubtract, BlendEquation::Add]);
+    }
+
+    #[test]
+    fn vector_paths() {
+        let area = |triangles: &[(f32, f32)]| -> f32 {
+            triangles.chunks(3).map(|t| ((t[1].0 - t[0].0) * (t[2].1 - t[0].1) - (t[2].0 - t[0].0) * (t[1].1 - t[0].1)).abs() / 2.0).sum()
+        };
+
+        // A square with a hole going the same way only has a hole with even-odd
+        let mut frame = Path::new();
+        frame.rect(0.0, 0.0, 10.0, 10.0);
+        frame.rect(3.0, 3.0, 4.0, 4.0);
+        assert!((area(&frame.fill_triangles(FillRule::NonZero, 0.1)) - 100.0).abs() < 1e-3);
+        assert!((area(&frame.fill_triangles(FillRule::EvenOdd, 0.1)) - 84.0).abs() < 1e-3);
+
+        // Concave and self intersecting polygons
+        let mut arrow = Path::new();
+        arrow.polygon(&[(0.0, 0.0), (4.0, 2.0), (0.0, 4.0), (2.0, 2.0)]);
+        assert!((area(&arrow.fill_triangles(FillRule::NonZero, 0.1)) - 4.0).abs() < 1e-3);
+        let mut bowtie = Path::new();
+        bowtie.polygon(&[(0.0, 0.0), (2.0, 2.0), (2.0, 0.0), (0.0, 2.0)]);
+        assert!((area(&bowtie.fill_triangles(FillRule::EvenOdd, 0.1)) - 2.0).abs() < 1e-3);
+
+        let mut circle = Path::new();
+        circle.ellipse(0.0, 0.0, 10.0, 10.0);
+        let circle_area = area(&circle.fill_triangles(FillRule::NonZero, 0.01));
+        assert!((circle_area - std::f32::consts::PI * 100.0).abs() < 0.5);
+
+        let mut line = Path::new();
+        line.move_to(0.0, 0.0);
+        line.line_to(10.0, 0.0);
+        assert!((area(&line.stroke_triangles(&StrokeStyle::new(2.0), 0.1)) - 20.0).abs() < 1e-3);
+        let square_caps = StrokeStyle::new(2.0).with_cap(LineCap::Square);
+        assert!((area(&line.stroke_triangles(&square_caps, 0.1)) - 24.0).abs() < 1e-3);
+        let dashed = StrokeStyle::new(1.0).with_dashes(&[2.0, 2.0], 0.0);
+        assert_eq!(line.stroke_triangles(&dashed, 0.1).len(), 3 * 6);
+        assert!((area(&line.stroke_triangles(&dashed, 0.1)) - 6.0).abs() < 1e-3);
+
+        // A right angle gets a bevel triangle and a miter triangle on the outside
+        let mut corner = Path::new();
+        corner.polyline(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]);
+        assert_eq!(corner.stroke_triangles(&StrokeStyle::new(2.0).with_join(LineJoin::Bevel), 0.1).len(), 15);
+        assert_eq!(corner.stroke_triangles(&StrokeStyle::new(2.0), 0.1).len(), 18);
+        assert_eq!(corner.stroke_triangles(&StrokeStyle::new(2.0).with_miter_limit(1.0), 0.1).len(), 15);
+
+        // arc_to leaves the path where the arc touches the second line
+        let mut rounded = Path::new();
+        rounded.move_to(0.0, 0.0);
+        rounded.arc_to(10.0, 0.0, 10.0, 10.0, 2.0);
+        let (points, closed) = &rounded.flatten(0.01)[0];
+        assert!(!closed);
+        let end = points[points.len() - 1];
+        assert!((end.0 - 10.0).abs() < 1e-4 && (end.1 - 2.0).abs() < 1e-4);
+        assert!(points.iter().any(|p| (p.0 - 8.0).abs() < 1e-4 && p.1.abs() < 1e-4));
+    }
+
+    #[test]
+    fn triangle_uvs() {
+        let uvs = bounding_box_uvs([(10.0, 10.0), (30.0, 10.0), (20.0, 50.0)], false);
+        assert_eq!(uvs, [(0.0, 0.0), (1.0, 0.0), (0.5, 1.0)]);
+        let flipped = bounding_box_uvs([(10.0, 10.0), (30.0, 10.0), (20.0, 50.0)], true);
+        assert_eq!(flipped, [(0.0, 1.0), (1.0, 1.0), (0.5, 0.0)]);
+
+        // A flat triangle doesn't divide by zero
+        let flat = bounding_box_uvs([(0.0, 5.0), (4.0, 5.0), (2.0, 5.0)], false);
+        assert_eq!(flat, [(0.0, 0.0), (1.0, 0.0), (0.5, 0.0)]);
+    }
+}
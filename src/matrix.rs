@@ -23,6 +23,7 @@
 
 use crate::vector::*;
 
+#[derive(Copy, Clone)]
 pub union Mat3x3f {
     pub m: [[f32; 3]; 3],
     pub values: [f32; 3*3],
@@ -76,6 +77,16 @@ impl Mat3x3f {
         }
     }
 
+    // Angles in radians, x_angle leans vertical lines and y_angle horizontal lines
+    pub fn skew(x_angle: f32, y_angle: f32) -> Mat3x3f {
+        unsafe {
+            let mut skew = Mat3x3f::identity();
+            skew.m[0][1] = x_angle.tan();
+            skew.m[1][0] = y_angle.tan();
+            skew
+        }
+    }
+
     pub fn mult(lhs: &Mat3x3f, rhs: &Mat3x3f) -> Mat3x3f {
         unsafe {
             let mut new_matrix = Mat3x3f::new();
@@ -99,6 +110,46 @@ impl Mat3x3f {
             res
         }
     }
+
+    pub fn transpose(&self) -> Mat3x3f {
+        unsafe {
+            let mut transposed = Mat3x3f::new();
+            for r in 0..3 {
+                for c in 0..3 {
+                    transposed.m[c][r] = self.m[r][c];
+                }
+            }
+            transposed
+        }
+    }
+
+    // None when the matrix can't be inverted, like a scale of zero
+    pub fn inverse(&self) -> Option<Mat3x3f> {
+        unsafe {
+            let m = &self.m;
+            let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+
+            let mut inverse = Mat3x3f::new();
+            inverse.m[0][0] =  cofactor(1, 2, 1, 2);
+            inverse.m[0][1] = -cofactor(0, 2, 1, 2);
+            inverse.m[0][2] =  cofactor(0, 1, 1, 2);
+            inverse.m[1][0] = -cofactor(1, 2, 0, 2);
+            inverse.m[1][1] =  cofactor(0, 2, 0, 2);
+            inverse.m[1][2] = -cofactor(0, 1, 0, 2);
+            inverse.m[2][0] =  cofactor(1, 2, 0, 1);
+            inverse.m[2][1] = -cofactor(0, 2, 0, 1);
+            inverse.m[2][2] =  cofactor(0, 1, 0, 1);
+
+            let determinant = m[0][0] * inverse.m[0][0] + m[0][1] * inverse.m[1][0] + m[0][2] * inverse.m[2][0];
+            if determinant.abs() <= f32::EPSILON * f32::EPSILON {
+                return None;
+            }
+            for value in inverse.values.iter_mut() {
+                *value /= determinant;
+            }
+            Some(inverse)
+        }
+    }
 }
 
 pub union Mat4x4f {
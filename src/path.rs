@@ -0,0 +1,563 @@
+/*
+ *   Copyright (c) 2021 Ludwig Bogsveen
+ *   All rights reserved.
+
+ *   Permission is hereby granted, free of charge, to any person obtaining a copy
+ *   of this software and associated documentation files (the "Software"), to deal
+ *   in the Software without restriction, including without limitation the rights
+ *   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *   copies of the Software, and to permit persons to whom the Software is
+ *   furnished to do so, subject to the following conditions:
+
+ *   The above copyright notice and this permission notice shall be included in all
+ *   copies or substantial portions of the Software.
+
+ *   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *   SOFTWARE.
+ */
+
+// Flattening and tessellation of vector paths into triangle lists that Graphics can draw
+
+type Point = (f32, f32);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FillRule {
+    NonZero,
+    EvenOdd,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineJoin {
+    Miter,
+    Round,
+    Bevel,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineCap {
+    Butt,
+    Round,
+    Square,
+}
+
+// Dashes alternate between drawn and skipped lengths starting with a drawn one, an odd number of lengths is
+// repeated twice like in SVG. The offset moves the pattern along the path.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StrokeStyle {
+    pub width: f32,
+    pub join: LineJoin,
+    pub cap: LineCap,
+    pub miter_limit: f32,
+    pub dashes: Vec<f32>,
+    pub dash_offset: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Segment {
+    Line(Point),
+    Quad(Point, Point),
+    Cubic(Point, Point, Point),
+}
+
+#[derive(Clone, Debug)]
+struct Contour {
+    start: Point,
+    segments: Vec<Segment>,
+    closed: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Path {
+    contours: Vec<Contour>,
+    current: Option<Point>,
+}
+
+// An edge of a filled polygon going down in y, winding is 1 for edges that went up in the path
+#[derive(Clone, Copy)]
+struct FillEdge {
+    top: Point,
+    bottom: Point,
+    winding: i32,
+}
+
+fn add(a: Point, b: Point) -> Point { (a.0 + b.0, a.1 + b.1) }
+fn sub(a: Point, b: Point) -> Point { (a.0 - b.0, a.1 - b.1) }
+fn mul(a: Point, s: f32) -> Point { (a.0 * s, a.1 * s) }
+fn dot(a: Point, b: Point) -> f32 { a.0 * b.0 + a.1 * b.1 }
+fn cross(a: Point, b: Point) -> f32 { a.0 * b.1 - a.1 * b.0 }
+fn length(a: Point) -> f32 { dot(a, a).sqrt() }
+
+fn normalize(a: Point) -> Point {
+    let l = length(a);
+    if l == 0.0 { (0.0, 0.0) } else { (a.0 / l, a.1 / l) }
+}
+
+// To the left of a direction
+fn normal(direction: Point) -> Point { (-direction.1, direction.0) }
+
+impl StrokeStyle {
+    pub fn new(width: f32) -> StrokeStyle {
+        StrokeStyle {
+            width,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            miter_limit: 4.0,
+            dashes: Vec::new(),
+            dash_offset: 0.0,
+        }
+    }
+
+    pub fn with_join(mut self, join: LineJoin) -> StrokeStyle {
+        self.join = join;
+        self
+    }
+
+    pub fn with_cap(mut self, cap: LineCap) -> StrokeStyle {
+        self.cap = cap;
+        self
+    }
+
+    // Miters longer than limit times the width turn into bevels
+    pub fn with_miter_limit(mut self, limit: f32) -> StrokeStyle {
+        self.miter_limit = limit;
+        self
+    }
+
+    pub fn with_dashes(mut self, dashes: &[f32], offset: f32) -> StrokeStyle {
+        self.dashes = dashes.to_vec();
+        self.dash_offset = offset;
+        self
+    }
+}
+
+impl FillEdge {
+    fn x_at(&self, y: f32) -> f32 {
+        let t = (y - self.top.1) / (self.bottom.1 - self.top.1);
+        self.top.0 + (self.bottom.0 - self.top.0) * t
+    }
+
+    fn slope(&self) -> f32 {
+        (self.bottom.0 - self.top.0) / (self.bottom.1 - self.top.1)
+    }
+}
+
+impl FillRule {
+    fn is_inside(&self, winding: i32) -> bool {
+        match self {
+            FillRule::NonZero => winding != 0,
+            FillRule::EvenOdd => winding % 2 != 0,
+        }
+    }
+}
+
+impl Default for Path {
+    fn default() -> Self { Path::new() }
+}
+
+impl Path {
+    pub fn new() -> Path {
+        Path {
+            contours: Vec::new(),
+            current: None,
+        }
+    }
+
+    pub fn move_to(&mut self, x: f32, y: f32) {
+        self.contours.push(Contour { start: (x, y), segments: Vec::new(), closed: false });
+        self.current = Some((x, y));
+    }
+
+    pub fn line_to(&mut self, x: f32, y: f32) {
+        self.push_segment(Segment::Line((x, y)), (x, y));
+    }
+
+    pub fn quad_to(&mut self, cx: f32, cy: f32, x: f32, y: f32) {
+        self.push_segment(Segment::Quad((cx, cy), (x, y)), (x, y));
+    }
+
+    pub fn cubic_to(&mut self, c1x: f32, c1y: f32, c2x: f32, c2y: f32, x: f32, y: f32) {
+        self.push_segment(Segment::Cubic((c1x, c1y), (c2x, c2y), (x, y)), (x, y));
+    }
+
+    // A line towards (x1, y1) and a circular arc that turns to go towards (x2, y2), the same as arcTo in an HTML
+    // canvas. Points on a line and a radius of 0 just add the line to (x1, y1).
+    pub fn arc_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, radius: f32) {
+        let p0 = match self.current {
+            Some(p) => p,
+            None => {
+                self.move_to(x1, y1);
+                return;
+            },
+        };
+        let p1 = (x1, y1);
+        let d0 = normalize(sub(p0, p1));
+        let d1 = normalize(sub((x2, y2), p1));
+        let cos = dot(d0, d1).clamp(-1.0, 1.0);
+        if radius <= 0.0 || d0 == (0.0, 0.0) || d1 == (0.0, 0.0) || cross(d0, d1).abs() < 1e-6 {
+            self.line_to(x1, y1);
+            return;
+        }
+
+        // The arc touches both lines at the same distance from the corner
+        let half_angle = cos.acos() / 2.0;
+        let distance = radius / half_angle.tan();
+        let t0 = add(p1, mul(d0, distance));
+        let t1 = add(p1, mul(d1, distance));
+        self.line_to(t0.0, t0.1);
+
+        let sweep = std::f32::consts::PI - 2.0 * half_angle;
+        let k = 4.0 / 3.0 * (sweep / 4.0).tan() * radius;
+        let c1 = sub(t0, mul(d0, k));
+        let c2 = sub(t1, mul(d1, k));
+        self.cubic_to(c1.0, c1.1, c2.0, c2.1, t1.0, t1.1);
+    }
+
+    // Lines back to the start of the contour, drawing on without a move_to starts a new contour from there
+    pub fn close(&mut self) {
+        if let Some(contour) = self.contours.last_mut() {
+            contour.closed = true;
+            self.current = Some(contour.start);
+        }
+    }
+
+    pub fn rect(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        self.move_to(x, y);
+        self.line_to(x + width, y);
+        self.line_to(x + width, y + height);
+        self.line_to(x, y + height);
+        self.close();
+    }
+
+    // Four cubic curves, x and y is the center
+    pub fn ellipse(&mut self, x: f32, y: f32, radius_x: f32, radius_y: f32) {
+        let k = 0.552_284_8;
+        let (kx, ky) = (radius_x * k, radius_y * k);
+        self.move_to(x + radius_x, y);
+        self.cubic_to(x + radius_x, y + ky, x + kx, y + radius_y, x, y + radius_y);
+        self.cubic_to(x - kx, y + radius_y, x - radius_x, y + ky, x - radius_x, y);
+        self.cubic_to(x - radius_x, y - ky, x - kx, y - radius_y, x, y - radius_y);
+        self.cubic_to(x + kx, y - radius_y, x + radius_x, y - ky, x + radius_x, y);
+        self.close();
+    }
+
+    pub fn polyline(&mut self, points: &[(f32, f32)]) {
+        if let Some(first) = points.first() {
+            self.move_to(first.0, first.1);
+            for point in &points[1..] {
+                self.line_to(point.0, point.1);
+            }
+        }
+    }
+
+    pub fn polygon(&mut self, points: &[(f32, f32)]) {
+        self.polyline(points);
+        self.close();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contours.iter().all(|contour| contour.segments.is_empty())
+    }
+
+    fn push_segment(&mut self, segment: Segment, end: Point) {
+        let start_new = match self.contours.last() {
+            Some(contour) => contour.closed,
+            None => true,
+        };
+        if start_new {
+            let start = self.current.unwrap_or(end);
+            self.contours.push(Contour { start, segments: Vec::new(), closed: false });
+        }
+        self.contours.last_mut().unwrap().segments.push(segment);
+        self.current = Some(end);
+    }
+
+    // Every contour as a polyline and whether it is closed, curves are split until they are at most tolerance
+    // away from the lines
+    pub fn flatten(&self, tolerance: f32) -> Vec<(Vec<(f32, f32)>, bool)> {
+        let tolerance = tolerance.max(1e-6);
+        let mut polylines = Vec::new();
+        for contour in &self.contours {
+            let mut points = vec![contour.start];
+            let mut last = contour.start;
+            for segment in &contour.segments {
+                match *segment {
+                    Segment::Line(p) => points.push(p),
+                    Segment::Quad(c, p) => {
+                        let deviation = length(add(sub(last, mul(c, 2.0)), p)) / 4.0;
+                        let steps = subdivisions(deviation, tolerance);
+                        for i in 1..=steps {
+                            let t = i as f32 / steps as f32;
+                            let u = 1.0 - t;
+                            points.push(add(add(mul(last, u * u), mul(c, 2.0 * u * t)), mul(p, t * t)));
+                        }
+                    },
+                    Segment::Cubic(c1, c2, p) => {
+                        let deviation = length(add(sub(last, mul(c1, 2.0)), c2)).max(length(add(sub(c1, mul(c2, 2.0)), p))) * 0.75;
+                        let steps = subdivisions(deviation, tolerance);
+                        for i in 1..=steps {
+                            let t = i as f32 / steps as f32;
+                            let u = 1.0 - t;
+                            let point = add(add(mul(last, u * u * u), mul(c1, 3.0 * u * u * t)), add(mul(c2, 3.0 * u * t * t), mul(p, t * t * t)));
+                            points.push(point);
+                        }
+                    },
+                }
+                last = match *segment {
+                    Segment::Line(p) | Segment::Quad(_, p) | Segment::Cubic(_, _, p) => p,
+                };
+            }
+
+            points.dedup_by(|a, b| length(sub(*a, *b)) < 1e-6);
+            if contour.closed && points.len() > 1 && length(sub(points[0], *points.last().unwrap())) < 1e-6 {
+                points.pop();
+            }
+            if points.len() > 1 {
+                polylines.push((points, contour.closed));
+            }
+        }
+        polylines
+    }
+
+    // Open contours are filled as if they were closed. The triangles don't overlap so the fill can be drawn
+    // with any colour and blend mode.
+    pub fn fill_triangles(&self, rule: FillRule, tolerance: f32) -> Vec<(f32, f32)> {
+        let mut edges = Vec::new();
+        for (points, _) in self.flatten(tolerance) {
+            if points.len() < 3 {
+                continue;
+            }
+            for i in 0..points.len() {
+                let a = points[i];
+                let b = points[(i + 1) % points.len()];
+                if a.1 < b.1 {
+                    edges.push(FillEdge { top: a, bottom: b, winding: 1 });
+                } else if a.1 > b.1 {
+                    edges.push(FillEdge { top: b, bottom: a, winding: -1 });
+                }
+            }
+        }
+
+        let mut ys: Vec<f32> = edges.iter().flat_map(|edge| [edge.top.1, edge.bottom.1]).collect();
+        ys.sort_by(f32::total_cmp);
+        ys.dedup();
+
+        // Splits the shape into horizontal bands where no edges cross and fills the trapezoids between the edges
+        // that are inside by the fill rule
+        let mut triangles = Vec::new();
+        for band in ys.windows(2) {
+            let mut top = band[0];
+            let bottom = band[1];
+            let mut active: Vec<FillEdge> = edges.iter().filter(|edge| edge.top.1 <= top && edge.bottom.1 >= bottom).copied().collect();
+
+            while bottom - top > 1e-6 * (1.0 + top.abs()) {
+                active.sort_by(|a, b| a.x_at(top).total_cmp(&b.x_at(top)).then(a.slope().total_cmp(&b.slope())));
+
+                let mut end = bottom;
+                for pair in active.windows(2) {
+                    let closing = pair[0].slope() - pair[1].slope();
+                    if closing > 0.0 {
+                        let crossing = top + (pair[1].x_at(top) - pair[0].x_at(top)) / closing;
+                        if crossing > top && crossing < end {
+                            end = crossing;
+                        }
+                    }
+                }
+
+                let mut winding = 0;
+                let mut span_start = 0;
+                for (i, edge) in active.iter().enumerate() {
+                    let was_inside = rule.is_inside(winding);
+                    winding += edge.winding;
+                    let inside = rule.is_inside(winding);
+                    if !was_inside && inside {
+                        span_start = i;
+                    } else if was_inside && !inside {
+                        let (left, right) = (active[span_start], *edge);
+                        let top_left = (left.x_at(top), top);
+                        let top_right = (right.x_at(top), top);
+                        let bottom_left = (left.x_at(end), end);
+                        let bottom_right = (right.x_at(end), end);
+                        triangles.extend_from_slice(&[top_left, top_right, bottom_right, top_left, bottom_right, bottom_left]);
+                    }
+                }
+                top = end;
+            }
+        }
+        triangles
+    }
+
+    // Segments, joins and caps are separate triangles that overlap where they meet
+    pub fn stroke_triangles(&self, style: &StrokeStyle, tolerance: f32) -> Vec<(f32, f32)> {
+        let mut triangles = Vec::new();
+        let half_width = style.width / 2.0;
+        if half_width <= 0.0 {
+            return triangles;
+        }
+
+        let tolerance = tolerance.max(1e-6);
+        for (points, closed) in self.flatten(tolerance) {
+            if has_dashes(&style.dashes) {
+                for dash in dash_polyline(&points, closed, &style.dashes, style.dash_offset) {
+                    stroke_polyline(&mut triangles, &dash, false, style, half_width, tolerance);
+                }
+            } else {
+                stroke_polyline(&mut triangles, &points, closed, style, half_width, tolerance);
+            }
+        }
+        triangles
+    }
+}
+
+fn subdivisions(deviation: f32, tolerance: f32) -> u32 {
+    ((deviation / tolerance).sqrt().ceil() as u32).clamp(1, 1024)
+}
+
+fn has_dashes(dashes: &[f32]) -> bool {
+    !dashes.is_empty() && dashes.iter().all(|dash| *dash >= 0.0) && dashes.iter().sum::<f32>() > 0.0
+}
+
+// Splits a polyline into the drawn parts of a dash pattern
+fn dash_polyline(points: &[Point], closed: bool, dashes: &[f32], offset: f32) -> Vec<Vec<Point>> {
+    let pattern: Vec<f32> = if dashes.len() % 2 == 1 { dashes.iter().chain(dashes.iter()).copied().collect() } else { dashes.to_vec() };
+    let total: f32 = pattern.iter().sum();
+
+    let mut index = 0;
+    let mut remaining = pattern[0];
+    let mut skip = offset.rem_euclid(total);
+    while skip > 0.0 {
+        if skip >= remaining {
+            skip -= remaining;
+            index = (index + 1) % pattern.len();
+            remaining = pattern[index];
+        } else {
+            remaining -= skip;
+            skip = 0.0;
+        }
+    }
+
+    let mut dashes = Vec::new();
+    let mut on = index % 2 == 0;
+    let mut current = if on { vec![points[0]] } else { Vec::new() };
+    let segment_count = if closed { points.len() } else { points.len() - 1 };
+    for i in 0..segment_count {
+        let mut position = points[i];
+        let end = points[(i + 1) % points.len()];
+        let direction = normalize(sub(end, position));
+        let mut left = length(sub(end, position));
+        while left > 0.0 {
+            let step = remaining.min(left);
+            position = if step == left { end } else { add(position, mul(direction, step)) };
+            if on {
+                current.push(position);
+            }
+            remaining -= step;
+            left -= step;
+
+            if remaining <= 0.0 {
+                if on {
+                    if current.len() > 1 {
+                        dashes.push(std::mem::take(&mut current));
+                    }
+                    current.clear();
+                } else {
+                    current = vec![position];
+                }
+                on = !on;
+                index = (index + 1) % pattern.len();
+                remaining = pattern[index];
+            }
+        }
+    }
+    if on && current.len() > 1 {
+        dashes.push(current);
+    }
+    dashes
+}
+
+fn stroke_polyline(triangles: &mut Vec<Point>, points: &[Point], closed: bool, style: &StrokeStyle, half_width: f32, tolerance: f32) {
+    let count = points.len();
+    if count < 2 {
+        return;
+    }
+
+    let segment_count = if closed { count } else { count - 1 };
+    for i in 0..segment_count {
+        let mut a = points[i];
+        let mut b = points[(i + 1) % count];
+        let direction = normalize(sub(b, a));
+        if !closed && style.cap == LineCap::Square {
+            if i == 0 {
+                a = sub(a, mul(direction, half_width));
+            }
+            if i == segment_count - 1 {
+                b = add(b, mul(direction, half_width));
+            }
+        }
+        let offset = mul(normal(direction), half_width);
+        let (a_left, a_right) = (add(a, offset), sub(a, offset));
+        let (b_left, b_right) = (add(b, offset), sub(b, offset));
+        triangles.extend_from_slice(&[a_left, b_left, b_right, a_left, b_right, a_right]);
+    }
+
+    let joins = if closed { 0..count } else { 1..count - 1 };
+    for i in joins {
+        let point = points[i];
+        let d0 = normalize(sub(point, points[(i + count - 1) % count]));
+        let d1 = normalize(sub(points[(i + 1) % count], point));
+        let turn = cross(d0, d1);
+        if turn.abs() < 1e-6 && dot(d0, d1) > 0.0 {
+            continue;
+        }
+
+        // The gap to fill is on the outside of the turn, to the right of a left turn
+        let side = if turn > 0.0 { -half_width } else { half_width };
+        let n0 = mul(normal(d0), side);
+        let n1 = mul(normal(d1), side);
+        let (outer0, outer1) = (add(point, n0), add(point, n1));
+        match style.join {
+            LineJoin::Bevel => triangles.extend_from_slice(&[point, outer0, outer1]),
+            LineJoin::Miter => {
+                triangles.extend_from_slice(&[point, outer0, outer1]);
+                // 1 / cos of half the angle between the normals is how much longer the miter is than the width
+                let half_cos = ((1.0 + dot(d0, d1)) / 2.0).max(0.0).sqrt();
+                if half_cos > 1e-6 && 1.0 / half_cos <= style.miter_limit {
+                    let tip = add(point, mul(normalize(add(n0, n1)), half_width / half_cos));
+                    triangles.extend_from_slice(&[outer0, tip, outer1]);
+                }
+            },
+            LineJoin::Round => fan(triangles, point, n0, cross(n0, n1).atan2(dot(n0, n1)), tolerance),
+        }
+    }
+
+    if !closed && style.cap == LineCap::Round {
+        let start = normalize(sub(points[1], points[0]));
+        let end = normalize(sub(points[count - 1], points[count - 2]));
+        fan(triangles, points[0], mul(normal(start), half_width), std::f32::consts::PI, tolerance);
+        fan(triangles, points[count - 1], mul(normal(end), -half_width), std::f32::consts::PI, tolerance);
+    }
+}
+
+// Triangles from center around an arc that starts at center + from and turns by sweep radians
+fn fan(triangles: &mut Vec<Point>, center: Point, from: Point, sweep: f32, tolerance: f32) {
+    let radius = length(from);
+    if radius == 0.0 || sweep == 0.0 {
+        return;
+    }
+    let max_step = 2.0 * (1.0 - (tolerance / radius).min(1.0)).acos();
+    let max_step = max_step.clamp(0.01, std::f32::consts::FRAC_PI_4);
+    let steps = (sweep.abs() / max_step).ceil().max(1.0) as u32;
+
+    let start_angle = from.1.atan2(from.0);
+    let mut previous = add(center, from);
+    for i in 1..=steps {
+        let angle = start_angle + sweep * i as f32 / steps as f32;
+        let next = add(center, (angle.cos() * radius, angle.sin() * radius));
+        triangles.extend_from_slice(&[center, previous, next]);
+        previous = next;
+    }
+}
@@ -0,0 +1,842 @@
+/*
+ *   Copyright (c) 2021 Ludwig Bogsveen
+ *   All rights reserved.
+
+ *   Permission is hereby granted, free of charge, to any person obtaining a copy
+ *   of this software and associated documentation files (the "Software"), to deal
+ *   in the Software without restriction, including without limitation the rights
+ *   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *   copies of the Software, and to permit persons to whom the Software is
+ *   furnished to do so, subject to the following conditions:
+
+ *   The above copyright notice and this permission notice shall be included in all
+ *   copies or substantial portions of the Software.
+
+ *   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *   SOFTWARE.
+ */
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use engine_core::error_log;
+
+use crate::buffer::VertexArray;
+use crate::color::{self, Color};
+use crate::framebuffer::{ColorFormat, FrameBuffer, FrameBufferDescriptor, FrameBufferError};
+use crate::renderer::std_renderer::{self, RenderingPrimitive};
+use crate::shader::Shader;
+use crate::texture::{Image, ImageError, Texture};
+
+// A triangle that covers the whole target, uv goes from 0 to 1 across it
+const FULLSCREEN_VERTEX_SOURCE: &str = r#"
+#shader vertex
+#version 330 core
+
+out vec2 uv;
+
+void main() {
+    vec2 position = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+    uv = position;
+    gl_Position = vec4(position * 2.0 - 1.0, 0.0, 1.0);
+}
+"#;
+
+const COPY_SOURCE: &str = r#"
+#version 330 core
+
+in vec2 uv;
+out vec4 color;
+
+uniform sampler2D u_texture;
+
+void main() {
+    color = texture(u_texture, uv);
+}
+"#;
+
+const BLUR_SOURCE: &str = r#"
+#version 330 core
+
+in vec2 uv;
+out vec4 color;
+
+uniform sampler2D u_texture;
+uniform vec2 u_resolution;
+uniform vec2 u_direction;
+uniform float u_sigma;
+
+void main() {
+    float sigma = max(u_sigma, 0.001);
+    int radius = min(int(ceil(sigma * 3.0)), 64);
+    vec2 step = u_direction / u_resolution;
+
+    vec4 sum = texture(u_texture, uv);
+    float total = 1.0;
+    for (int i = 1; i <= radius; i++) {
+        float weight = exp(-float(i * i) / (2.0 * sigma * sigma));
+        sum += (texture(u_texture, uv + step * float(i)) + texture(u_texture, uv - step * float(i))) * weight;
+        total += 2.0 * weight;
+    }
+    color = sum / total;
+}
+"#;
+
+const BRIGHT_PASS_SOURCE: &str = r#"
+#version 330 core
+
+in vec2 uv;
+out vec4 color;
+
+uniform sampler2D u_texture;
+uniform float u_threshold;
+
+void main() {
+    vec3 c = texture(u_texture, uv).rgb;
+    float brightness = max(c.r, max(c.g, c.b));
+    float contribution = max(brightness - u_threshold, 0.0) / max(brightness, 0.0001);
+    color = vec4(c * contribution, 1.0);
+}
+"#;
+
+const BLOOM_SOURCE: &str = r#"
+#version 330 core
+
+in vec2 uv;
+out vec4 color;
+
+uniform sampler2D u_texture;
+uniform sampler2D u_bloom;
+uniform float u_intensity;
+
+void main() {
+    vec4 c = texture(u_texture, uv);
+    color = vec4(c.rgb + texture(u_bloom, uv).rgb * u_intensity, c.a);
+}
+"#;
+
+const VIGNETTE_SOURCE: &str = r#"
+#version 330 core
+
+in vec2 uv;
+out vec4 color;
+
+uniform sampler2D u_texture;
+uniform float u_intensity;
+uniform float u_radius;
+uniform float u_softness;
+uniform vec4 u_color;
+
+void main() {
+    vec4 c = texture(u_texture, uv);
+    float distance_to_center = distance(uv, vec2(0.5)) * 1.41421356;
+    float amount = smoothstep(u_radius - u_softness, u_radius, distance_to_center) * u_intensity * u_color.a;
+    color = vec4(mix(c.rgb, u_color.rgb, amount), c.a);
+}
+"#;
+
+const COLOR_GRADING_SOURCE: &str = r#"
+#version 330 core
+
+in vec2 uv;
+out vec4 color;
+
+uniform sampler2D u_texture;
+uniform sampler3D u_lut;
+uniform float u_intensity;
+
+void main() {
+    vec4 c = texture(u_texture, uv);
+    // Samples the centers of the first and last texels for 0 and 1
+    float size = float(textureSize(u_lut, 0).x);
+    vec3 coords = clamp(c.rgb, 0.0, 1.0) * ((size - 1.0) / size) + 0.5 / size;
+    color = vec4(mix(c.rgb, texture(u_lut, coords).rgb, u_intensity), c.a);
+}
+"#;
+
+const TONE_MAPPING_SOURCE: &str = r#"
+#version 330 core
+
+in vec2 uv;
+out vec4 color;
+
+uniform sampler2D u_texture;
+uniform int u_operator;
+uniform float u_exposure;
+uniform float u_gamma;
+
+void main() {
+    vec4 c = texture(u_texture, uv);
+    vec3 hdr = c.rgb * u_exposure;
+    vec3 mapped;
+    if (u_operator == 0) {
+        mapped = hdr / (hdr + vec3(1.0));
+    } else {
+        mapped = clamp((hdr * (2.51 * hdr + 0.03)) / (hdr * (2.43 * hdr + 0.59) + 0.14), 0.0, 1.0);
+    }
+    color = vec4(pow(mapped, vec3(1.0 / u_gamma)), c.a);
+}
+"#;
+
+const FXAA_SOURCE: &str = r#"
+#version 330 core
+
+in vec2 uv;
+out vec4 color;
+
+uniform sampler2D u_texture;
+uniform vec2 u_resolution;
+
+const float REDUCE_MIN = 1.0 / 128.0;
+const float REDUCE_MUL = 1.0 / 8.0;
+const float SPAN_MAX = 8.0;
+
+void main() {
+    vec2 texel = 1.0 / u_resolution;
+    vec3 luma = vec3(0.299, 0.587, 0.114);
+
+    vec4 center = texture(u_texture, uv);
+    float luma_nw = dot(texture(u_texture, uv + vec2(-1.0, -1.0) * texel).rgb, luma);
+    float luma_ne = dot(texture(u_texture, uv + vec2( 1.0, -1.0) * texel).rgb, luma);
+    float luma_sw = dot(texture(u_texture, uv + vec2(-1.0,  1.0) * texel).rgb, luma);
+    float luma_se = dot(texture(u_texture, uv + vec2( 1.0,  1.0) * texel).rgb, luma);
+    float luma_m  = dot(center.rgb, luma);
+
+    float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    vec2 direction = vec2(-((luma_nw + luma_ne) - (luma_sw + luma_se)), (luma_nw + luma_sw) - (luma_ne + luma_se));
+    float reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    float inverse_min = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+    direction = clamp(direction * inverse_min, vec2(-SPAN_MAX), vec2(SPAN_MAX)) * texel;
+
+    vec3 a = 0.5 * (texture(u_texture, uv + direction * (1.0 / 3.0 - 0.5)).rgb + texture(u_texture, uv + direction * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 b = a * 0.5 + 0.25 * (texture(u_texture, uv - direction * 0.5).rgb + texture(u_texture, uv + direction * 0.5).rgb);
+    float luma_b = dot(b, luma);
+    color = vec4((luma_b < luma_min || luma_b > luma_max) ? a : b, center.a);
+}
+"#;
+
+const CRT_SOURCE: &str = r#"
+#version 330 core
+
+in vec2 uv;
+out vec4 color;
+
+uniform sampler2D u_texture;
+uniform float u_curvature;
+uniform float u_scanline_intensity;
+uniform float u_scanlines;
+uniform float u_mask_intensity;
+
+void main() {
+    vec2 centered = uv * 2.0 - 1.0;
+    centered += centered * (centered.yx * centered.yx) * u_curvature;
+    vec2 screen_uv = centered * 0.5 + 0.5;
+    if (any(lessThan(screen_uv, vec2(0.0))) || any(greaterThan(screen_uv, vec2(1.0)))) {
+        color = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    vec4 c = texture(u_texture, screen_uv);
+    float scanline = sin(screen_uv.y * u_scanlines * 3.14159265) * 0.5 + 0.5;
+    c.rgb *= mix(1.0, scanline, u_scanline_intensity);
+
+    // Every pixel column lights up one of the phosphors
+    vec3 mask = vec3(0.7);
+    mask[int(gl_FragCoord.x) % 3] = 1.3;
+    c.rgb *= mix(vec3(1.0), mask, u_mask_intensity);
+    color = c;
+}
+"#;
+
+const PIXELATE_SOURCE: &str = r#"
+#version 330 core
+
+in vec2 uv;
+out vec4 color;
+
+uniform sampler2D u_texture;
+uniform vec2 u_resolution;
+uniform float u_pixel_size;
+
+void main() {
+    float block = max(u_pixel_size, 1.0);
+    vec2 pixel = (floor(uv * u_resolution / block) + 0.5) * block;
+    color = texelFetch(u_texture, clamp(ivec2(pixel), ivec2(0), ivec2(u_resolution) - 1), 0);
+}
+"#;
+
+#[derive(Debug)]
+pub enum PostProcessError {
+    Io(std::io::Error),
+    Image(ImageError),
+    FrameBuffer(FrameBufferError),
+    InvalidLutSize(u32, u32),
+}
+
+impl From<std::io::Error> for PostProcessError {
+    fn from(error: std::io::Error) -> Self { Self::Io(error) }
+}
+
+impl From<ImageError> for PostProcessError {
+    fn from(error: ImageError) -> Self { Self::Image(error) }
+}
+
+impl From<FrameBufferError> for PostProcessError {
+    fn from(error: FrameBufferError) -> Self { Self::FrameBuffer(error) }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMapper {
+    Reinhard,
+    Aces,
+}
+
+#[derive(Clone)]
+pub enum Uniform {
+    Int(i32),
+    Float(f32),
+    Vec2(f32, f32),
+    Vec3(f32, f32, f32),
+    Vec4(f32, f32, f32, f32),
+    Color(Color),
+    Mat4([f32; 16]),
+    Texture(Rc<Texture>),
+    Lut(Rc<Lut>),
+}
+
+// A colour grading lookup table stored in a 3D texture
+pub struct Lut {
+    gl_texture_id: u32,
+    size: u32,
+}
+
+// A fragment shader run over the whole image. It gets uv from the vertex shader and the image in the u_texture
+// sampler, u_resolution is the size of the image in pixels if the shader declares it.
+pub struct CustomEffect {
+    shader: Shader,
+    uniforms: Vec<(String, Uniform)>,
+}
+
+// Radii are the standard deviation of the blur in pixels, bloom is blurred at half resolution
+pub enum PostEffect {
+    Bloom { threshold: f32, intensity: f32, radius: f32 },
+    Blur { radius: f32 },
+    Vignette { intensity: f32, radius: f32, softness: f32, color: Color },
+    ColorGrading { lut: Rc<Lut>, intensity: f32 },
+    ToneMapping { mapper: ToneMapper, exposure: f32, gamma: f32 },
+    Fxaa,
+    Crt { curvature: f32, scanline_intensity: f32, scanlines: f32, mask_intensity: f32 },
+    Pixelate { pixel_size: f32 },
+    Custom(CustomEffect),
+}
+
+// Runs effects one after another over a framebuffer, ping-ponging between two intermediate targets
+pub struct PostProcessChain {
+    effects: Vec<(PostEffect, bool)>,
+    shaders: HashMap<&'static str, Shader>,
+    buffers: Vec<FrameBuffer>,
+    bloom_buffers: Vec<FrameBuffer>,
+    format: ColorFormat,
+    vertex_array: VertexArray,
+    sampler: u32,
+}
+
+#[derive(Clone, Copy)]
+struct PassTarget {
+    gl_buffer_id: u32,
+    viewport: (i32, i32, i32, i32),
+}
+
+impl PostEffect {
+    pub fn bloom() -> PostEffect {
+        PostEffect::Bloom { threshold: 1.0, intensity: 0.8, radius: 4.0 }
+    }
+
+    pub fn blur(radius: f32) -> PostEffect {
+        PostEffect::Blur { radius }
+    }
+
+    pub fn vignette() -> PostEffect {
+        PostEffect::Vignette { intensity: 0.8, radius: 1.0, softness: 0.6, color: color::BLACK }
+    }
+
+    pub fn color_grading(lut: Rc<Lut>) -> PostEffect {
+        PostEffect::ColorGrading { lut, intensity: 1.0 }
+    }
+
+    pub fn tone_mapping(mapper: ToneMapper) -> PostEffect {
+        PostEffect::ToneMapping { mapper, exposure: 1.0, gamma: 2.2 }
+    }
+
+    pub fn crt() -> PostEffect {
+        PostEffect::Crt { curvature: 0.08, scanline_intensity: 0.4, scanlines: 240.0, mask_intensity: 0.3 }
+    }
+
+    pub fn pixelate(pixel_size: f32) -> PostEffect {
+        PostEffect::Pixelate { pixel_size }
+    }
+}
+
+impl CustomEffect {
+    pub fn new(fragment_source: &str) -> CustomEffect {
+        CustomEffect {
+            shader: Shader::from_source(&fullscreen_source(fragment_source)),
+            uniforms: Vec::new(),
+        }
+    }
+
+    pub fn from_file(file_path: &str) -> CustomEffect {
+        CustomEffect::new(&Shader::load_file(file_path))
+    }
+
+    // Uploaded every time the effect runs, textures are bound to the units after the image
+    pub fn set_uniform(&mut self, name: &str, value: Uniform) {
+        match self.uniforms.iter_mut().find(|(uniform, _)| uniform == name) {
+            Some(uniform) => uniform.1 = value,
+            None => self.uniforms.push((name.to_string(), value)),
+        }
+    }
+
+    pub fn uniform(&self, name: &str) -> Option<&Uniform> {
+        self.uniforms.iter().find(|(uniform, _)| uniform == name).map(|(_, value)| value)
+    }
+
+    pub fn shader(&self) -> &Shader {
+        &self.shader
+    }
+}
+
+impl Lut {
+    // The usual strip layout of size slices next to each other, red goes right in every slice, green goes down
+    // from the top and blue picks the slice
+    pub fn from_image(image: &Image) -> Result<Rc<Lut>, PostProcessError> {
+        let (size, pixels) = lut_volume(image)?;
+        Ok(Lut::new(size, &pixels))
+    }
+
+    pub fn from_file(file_path: &str) -> Result<Rc<Lut>, PostProcessError> {
+        Lut::from_image(&Image::decode(&std::fs::read(file_path)?)?)
+    }
+
+    // Leaves colours as they are, a starting point for grading in an image editor
+    pub fn identity(size: u32) -> Rc<Lut> {
+        let (size, pixels) = lut_volume(&Lut::identity_image(size)).unwrap();
+        Lut::new(size, &pixels)
+    }
+
+    pub fn identity_image(size: u32) -> Image {
+        let size = size.max(2);
+        let mut image = Image::from_color(size * size, size, 0x00_00_00_FF);
+        let value = |i: u32| i * 255 / (size - 1);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    image.set_rgba8(b * size + r, size - 1 - g, value(r) << 24 | value(g) << 16 | value(b) << 8 | 0xFF);
+                }
+            }
+        }
+        image
+    }
+
+    fn new(size: u32, pixels: &[u8]) -> Rc<Lut> {
+        unsafe {
+            let mut texture_id: u32 = 0;
+            gl_call!(gl::GenTextures(1, &mut texture_id));
+            gl_call!(gl::BindTexture(gl::TEXTURE_3D, texture_id));
+
+            gl_call!(gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32));
+            gl_call!(gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32));
+            gl_call!(gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32));
+            gl_call!(gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32));
+            gl_call!(gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32));
+            gl_call!(gl::TexImage3D(gl::TEXTURE_3D, 0, gl::RGBA8 as i32, size as i32, size as i32, size as i32, 0, gl::RGBA, gl::UNSIGNED_BYTE, pixels.as_ptr() as *const std::ffi::c_void));
+
+            gl_call!(gl::BindTexture(gl::TEXTURE_3D, 0));
+            Rc::new(Lut { gl_texture_id: texture_id, size })
+        }
+    }
+
+    pub fn bind(&self, slot: u32) {
+        unsafe {
+            gl_call!(gl::ActiveTexture(gl::TEXTURE0 + slot));
+            gl_call!(gl::BindTexture(gl::TEXTURE_3D, self.gl_texture_id));
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+}
+
+impl Drop for Lut {
+    fn drop(&mut self) {
+        unsafe {
+            gl_call!(gl::DeleteTextures(1, &self.gl_texture_id));
+        }
+    }
+}
+
+// Turns a strip LUT into RGBA8 texels ordered by blue, green and then red
+pub(crate) fn lut_volume(image: &Image) -> Result<(u32, Vec<u8>), PostProcessError> {
+    let size = image.height();
+    if size < 2 || image.width() != size * size {
+        return Err(PostProcessError::InvalidLutSize(image.width(), image.height()));
+    }
+
+    let mut pixels = Vec::with_capacity((size * size * size * 4) as usize);
+    for b in 0..size {
+        for g in 0..size {
+            for r in 0..size {
+                pixels.extend_from_slice(&image.get_rgba8(b * size + r, size - 1 - g).to_be_bytes());
+            }
+        }
+    }
+    Ok((size, pixels))
+}
+
+impl Default for PostProcessChain {
+    fn default() -> Self { PostProcessChain::new() }
+}
+
+impl PostProcessChain {
+    // Intermediate results are kept in RGBA16F so HDR scenes can be tone mapped at the end
+    pub fn new() -> PostProcessChain {
+        let mut sampler = 0;
+        unsafe {
+            gl_call!(gl::GenSamplers(1, &mut sampler));
+            gl_call!(gl::SamplerParameteri(sampler, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32));
+            gl_call!(gl::SamplerParameteri(sampler, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32));
+            gl_call!(gl::SamplerParameteri(sampler, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32));
+            gl_call!(gl::SamplerParameteri(sampler, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32));
+        }
+
+        PostProcessChain {
+            effects: Vec::new(),
+            shaders: HashMap::new(),
+            buffers: Vec::new(),
+            bloom_buffers: Vec::new(),
+            format: ColorFormat::Rgba16f,
+            vertex_array: VertexArray::new(),
+            sampler,
+        }
+    }
+
+    pub fn set_format(&mut self, format: ColorFormat) {
+        self.format = format;
+        self.buffers.clear();
+        self.bloom_buffers.clear();
+    }
+
+    pub fn push(&mut self, effect: PostEffect) -> usize {
+        self.effects.push((effect, true));
+        self.effects.len() - 1
+    }
+
+    pub fn insert(&mut self, index: usize, effect: PostEffect) {
+        self.effects.insert(index.min(self.effects.len()), (effect, true));
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<PostEffect> {
+        if index < self.effects.len() {
+            Some(self.effects.remove(index).0)
+        } else {
+            None
+        }
+    }
+
+    pub fn effect(&self, index: usize) -> Option<&PostEffect> {
+        self.effects.get(index).map(|(effect, _)| effect)
+    }
+
+    pub fn effect_mut(&mut self, index: usize) -> Option<&mut PostEffect> {
+        self.effects.get_mut(index).map(|(effect, _)| effect)
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        if let Some(effect) = self.effects.get_mut(index) {
+            effect.1 = enabled;
+        }
+    }
+
+    pub fn is_enabled(&self, index: usize) -> bool {
+        self.effects.get(index).is_some_and(|(_, enabled)| *enabled)
+    }
+
+    pub fn len(&self) -> usize {
+        self.effects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    // Runs the enabled effects over the first colour attachment of source and writes the result to target, or to
+    // the current viewport of the window for None. Multisampled scenes have to be resolved first. The bound
+    // framebuffer, viewport, blending and depth test are the same afterwards.
+    pub fn apply(&mut self, source: &FrameBuffer, target: Option<&FrameBuffer>) {
+        let input = match source.color_attachment(0) {
+            Some(texture) => texture.clone(),
+            None => {
+                error_log!("Post processing needs a framebuffer with a colour texture, resolve multisampled framebuffers first");
+                return;
+            }
+        };
+
+        if let Err(e) = self.prepare(source.width(), source.height()) {
+            error_log!("Failed to create post processing buffers: {:?}", e);
+            return;
+        }
+
+        let mut previous_buffer = 0;
+        let mut viewport = [0i32; 4];
+        let blending;
+        let depth_test;
+        unsafe {
+            gl_call!(gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut previous_buffer));
+            gl_call!(gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr()));
+            blending = gl::IsEnabled(gl::BLEND) == gl::TRUE;
+            depth_test = gl::IsEnabled(gl::DEPTH_TEST) == gl::TRUE;
+            std_renderer::disable(std_renderer::Capability::Blending);
+            std_renderer::disable(std_renderer::Capability::DepthTest);
+        }
+
+        let output = match target {
+            Some(target) => PassTarget { gl_buffer_id: target.gl_id(), viewport: (0, 0, target.width() as i32, target.height() as i32) },
+            None => PassTarget { gl_buffer_id: 0, viewport: (viewport[0], viewport[1], viewport[2], viewport[3]) },
+        };
+
+        self.vertex_array.bind();
+        let effects = std::mem::take(&mut self.effects);
+        let active: Vec<&PostEffect> = effects.iter().filter(|(_, enabled)| *enabled).map(|(effect, _)| effect).collect();
+
+        if active.is_empty() {
+            self.builtin_pass("copy", &[("u_texture", &input)], output, &[]);
+        }
+
+        let mut input = input;
+        for (effect, buffer) in active.iter().zip(ping_pong_targets(active.len())) {
+            match buffer {
+                Some(buffer) => {
+                    self.run_effect(effect, &input, PassTarget::from(&self.buffers[buffer]));
+                    input = self.buffers[buffer].texture().clone();
+                },
+                None => self.run_effect(effect, &input, output),
+            }
+        }
+        self.effects = effects;
+
+        unsafe {
+            VertexArray::un_bind();
+            Shader::un_bind();
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, previous_buffer as u32));
+            gl_call!(gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]));
+            if blending {
+                std_renderer::enable(std_renderer::Capability::Blending);
+            }
+            if depth_test {
+                std_renderer::enable(std_renderer::Capability::DepthTest);
+            }
+        }
+    }
+
+    // Two buffers to ping-pong between and a third for the blur passes, all the size of the source
+    fn prepare(&mut self, width: u32, height: u32) -> Result<(), PostProcessError> {
+        if self.buffers.first().is_some_and(|buffer| (buffer.width(), buffer.height()) == (width, height)) {
+            return Ok(());
+        }
+
+        self.buffers.clear();
+        self.bloom_buffers.clear();
+        let mut descriptor = FrameBufferDescriptor::new(width, height);
+        descriptor.color_attachments = vec![self.format];
+        for _ in 0..3 {
+            self.buffers.push(FrameBuffer::from_descriptor(&descriptor)?);
+        }
+
+        descriptor.width = (width / 2).max(1);
+        descriptor.height = (height / 2).max(1);
+        for _ in 0..2 {
+            self.bloom_buffers.push(FrameBuffer::from_descriptor(&descriptor)?);
+        }
+        Ok(())
+    }
+
+    fn run_effect(&mut self, effect: &PostEffect, input: &Rc<Texture>, output: PassTarget) {
+        match effect {
+            PostEffect::Bloom { threshold, intensity, radius } => {
+                let bright = PassTarget::from(&self.bloom_buffers[0]);
+                self.builtin_pass("bright_pass", &[("u_texture", input)], bright, &[("u_threshold", Uniform::Float(*threshold))]);
+                let blurred = self.bloom_buffers[0].texture().clone();
+                let scratch = (PassTarget::from(&self.bloom_buffers[1]), self.bloom_buffers[1].texture().clone());
+                self.blur(&blurred, scratch, bright, radius / 2.0);
+                self.builtin_pass("bloom", &[("u_texture", input), ("u_bloom", &blurred)], output, &[("u_intensity", Uniform::Float(*intensity))]);
+            },
+            PostEffect::Blur { radius } => {
+                let scratch = (PassTarget::from(&self.buffers[2]), self.buffers[2].texture().clone());
+                self.blur(input, scratch, output, *radius);
+            },
+            PostEffect::Vignette { intensity, radius, softness, color } => {
+                let (r, g, b, a) = <(f32, f32, f32, f32)>::from(*color);
+                self.builtin_pass("vignette", &[("u_texture", input)], output, &[
+                    ("u_intensity", Uniform::Float(*intensity)),
+                    ("u_radius", Uniform::Float(*radius)),
+                    ("u_softness", Uniform::Float(*softness)),
+                    ("u_color", Uniform::Vec4(r, g, b, a)),
+                ]);
+            },
+            PostEffect::ColorGrading { lut, intensity } => {
+                self.builtin_pass("color_grading", &[("u_texture", input)], output, &[
+                    ("u_lut", Uniform::Lut(lut.clone())),
+                    ("u_intensity", Uniform::Float(*intensity)),
+                ]);
+            },
+            PostEffect::ToneMapping { mapper, exposure, gamma } => {
+                let operator = match mapper {
+                    ToneMapper::Reinhard => 0,
+                    ToneMapper::Aces => 1,
+                };
+                self.builtin_pass("tone_mapping", &[("u_texture", input)], output, &[
+                    ("u_operator", Uniform::Int(operator)),
+                    ("u_exposure", Uniform::Float(*exposure)),
+                    ("u_gamma", Uniform::Float(*gamma)),
+                ]);
+            },
+            PostEffect::Fxaa => self.builtin_pass("fxaa", &[("u_texture", input)], output, &[]),
+            PostEffect::Crt { curvature, scanline_intensity, scanlines, mask_intensity } => {
+                self.builtin_pass("crt", &[("u_texture", input)], output, &[
+                    ("u_curvature", Uniform::Float(*curvature)),
+                    ("u_scanline_intensity", Uniform::Float(*scanline_intensity)),
+                    ("u_scanlines", Uniform::Float(*scanlines)),
+                    ("u_mask_intensity", Uniform::Float(*mask_intensity)),
+                ]);
+            },
+            PostEffect::Pixelate { pixel_size } => {
+                self.builtin_pass("pixelate", &[("u_texture", input)], output, &[("u_pixel_size", Uniform::Float(*pixel_size))]);
+            },
+            PostEffect::Custom(custom) => {
+                let uniforms: Vec<(&str, Uniform)> = custom.uniforms.iter().map(|(name, value)| (name.as_str(), value.clone())).collect();
+                draw_pass(&custom.shader, self.sampler, &[("u_texture", input)], output, &uniforms);
+            },
+        }
+    }
+
+    // Separable gaussian blur, horizontally into scratch and vertically into output
+    fn blur(&mut self, input: &Rc<Texture>, scratch: (PassTarget, Rc<Texture>), output: PassTarget, sigma: f32) {
+        self.builtin_pass("blur", &[("u_texture", input)], scratch.0, &[("u_direction", Uniform::Vec2(1.0, 0.0)), ("u_sigma", Uniform::Float(sigma))]);
+        self.builtin_pass("blur", &[("u_texture", &scratch.1)], output, &[("u_direction", Uniform::Vec2(0.0, 1.0)), ("u_sigma", Uniform::Float(sigma))]);
+    }
+
+    fn builtin_pass(&mut self, name: &'static str, inputs: &[(&str, &Rc<Texture>)], output: PassTarget, uniforms: &[(&str, Uniform)]) {
+        let shader = self.shaders.entry(name).or_insert_with(|| {
+            let fragment_source = match name {
+                "blur" => BLUR_SOURCE,
+                "bright_pass" => BRIGHT_PASS_SOURCE,
+                "bloom" => BLOOM_SOURCE,
+                "vignette" => VIGNETTE_SOURCE,
+                "color_grading" => COLOR_GRADING_SOURCE,
+                "tone_mapping" => TONE_MAPPING_SOURCE,
+                "fxaa" => FXAA_SOURCE,
+                "crt" => CRT_SOURCE,
+                "pixelate" => PIXELATE_SOURCE,
+                _ => COPY_SOURCE,
+            };
+            Shader::from_source(&fullscreen_source(fragment_source))
+        });
+        draw_pass(shader, self.sampler, inputs, output, uniforms);
+    }
+}
+
+impl Drop for PostProcessChain {
+    fn drop(&mut self) {
+        unsafe {
+            gl_call!(gl::DeleteSamplers(1, &self.sampler));
+        }
+    }
+}
+
+impl From<&FrameBuffer> for PassTarget {
+    fn from(frame_buffer: &FrameBuffer) -> Self {
+        PassTarget { gl_buffer_id: frame_buffer.gl_id(), viewport: (0, 0, frame_buffer.width() as i32, frame_buffer.height() as i32) }
+    }
+}
+
+// Which of the two ping-pong buffers every pass draws into, None is the final output. A pass never reads the
+// buffer it draws into since it reads what the pass before it drew.
+pub(crate) fn ping_pong_targets(pass_count: usize) -> Vec<Option<usize>> {
+    (0..pass_count).map(|pass| if pass + 1 == pass_count { None } else { Some(pass % 2) }).collect()
+}
+
+fn fullscreen_source(fragment_source: &str) -> String {
+    format!("{}\n#shader fragment\n{}", FULLSCREEN_VERTEX_SOURCE, fragment_source)
+}
+
+// Inputs are bound from unit 0 with linear filtering, u_resolution is the size of the first one
+fn draw_pass(shader: &Shader, sampler: u32, inputs: &[(&str, &Rc<Texture>)], output: PassTarget, uniforms: &[(&str, Uniform)]) {
+    unsafe {
+        gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, output.gl_buffer_id));
+        let (x, y, width, height) = output.viewport;
+        gl_call!(gl::Viewport(x, y, width, height));
+    }
+
+    shader.bind();
+    let mut unit = 0;
+    for (name, texture) in inputs {
+        texture.bind(unit);
+        unsafe {
+            gl_call!(gl::BindSampler(unit, sampler));
+        }
+        upload_uniform(shader, name, &Uniform::Int(unit as i32), &mut unit);
+        unit += 1;
+    }
+    if let Some((_, texture)) = inputs.first() {
+        upload_uniform(shader, "u_resolution", &Uniform::Vec2(texture.width() as f32, texture.height() as f32), &mut unit);
+    }
+    for (name, value) in uniforms {
+        upload_uniform(shader, name, value, &mut unit);
+    }
+
+    unsafe {
+        std_renderer::draw_array(RenderingPrimitive::Triangles, 3);
+        for i in 0..inputs.len() as u32 {
+            gl_call!(gl::BindSampler(i, 0));
+        }
+    }
+}
+
+// Uniforms the shader doesn't use are skipped quietly, the compiler removes the ones that don't affect the output
+fn upload_uniform(shader: &Shader, name: &str, value: &Uniform, unit: &mut u32) {
+    let location = match shader.find_uniform(name) {
+        Some(location) => location,
+        None => return,
+    };
+
+    match value {
+        Uniform::Int(v) => shader.upload_1i(location, *v),
+        Uniform::Float(v) => shader.upload_1f(location, *v),
+        Uniform::Vec2(x, y) => shader.upload_2f(location, *x, *y),
+        Uniform::Vec3(x, y, z) => shader.upload_3f(location, *x, *y, *z),
+        Uniform::Vec4(x, y, z, w) => shader.upload_4f(location, *x, *y, *z, *w),
+        Uniform::Color(color) => {
+            let (r, g, b, a) = <(f32, f32, f32, f32)>::from(*color);
+            shader.upload_4f(location, r, g, b, a);
+        },
+        Uniform::Mat4(m) => shader.upload_4x4f(location, m),
+        Uniform::Texture(texture) => {
+            texture.bind(*unit);
+            shader.upload_1i(location, *unit as i32);
+            *unit += 1;
+        },
+        Uniform::Lut(lut) => {
+            lut.bind(*unit);
+            shader.upload_1i(location, *unit as i32);
+            *unit += 1;
+        },
+    }
+}
@@ -0,0 +1,177 @@
+/*
+ *   Copyright (c) 2021 Ludwig Bogsveen
+ *   All rights reserved.
+
+ *   Permission is hereby granted, free of charge, to any person obtaining a copy
+ *   of this software and associated documentation files (the "Software"), to deal
+ *   in the Software without restriction, including without limitation the rights
+ *   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *   copies of the Software, and to permit persons to whom the Software is
+ *   furnished to do so, subject to the following conditions:
+
+ *   The above copyright notice and this permission notice shall be included in all
+ *   copies or substantial portions of the Software.
+
+ *   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *   SOFTWARE.
+ */
+
+use crate::texture::ImageError;
+
+// https://qoiformat.org/qoi-specification.pdf
+
+pub const QOI_MAGIC: [u8; 4] = *b"qoif";
+
+const QOI_OP_INDEX: u8  = 0x00;
+const QOI_OP_DIFF: u8   = 0x40;
+const QOI_OP_LUMA: u8   = 0x80;
+const QOI_OP_RUN: u8    = 0xC0;
+const QOI_OP_RGB: u8    = 0xFE;
+const QOI_OP_RGBA: u8   = 0xFF;
+const QOI_MASK: u8      = 0xC0;
+
+const QOI_HEADER_SIZE: usize = 14;
+const QOI_PADDING: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+fn hash(px: [u8; 4]) -> usize {
+    (px[0] as usize * 3 + px[1] as usize * 5 + px[2] as usize * 7 + px[3] as usize * 11) % 64
+}
+
+// Pixels are tightly packed RGBA8 rows in file order (top row first).
+pub fn decode(bytes: &[u8]) -> Result<(u32, u32, Vec<u8>), ImageError> {
+    if bytes.len() < QOI_HEADER_SIZE + QOI_PADDING.len() || !bytes.starts_with(&QOI_MAGIC) {
+        return Err(ImageError::InvalidData("qoi header"));
+    }
+
+    let width = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+    let height = u32::from_be_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]);
+    let pixel_count = width as usize * height as usize;
+    if width == 0 || height == 0 || pixel_count > 400_000_000 {
+        return Err(ImageError::InvalidData("qoi dimensions"));
+    }
+
+    let mut pixels = Vec::with_capacity(pixel_count * 4);
+    let mut index = [[0u8; 4]; 64];
+    let mut px = [0u8, 0, 0, 255];
+    let mut run = 0;
+    let mut p = QOI_HEADER_SIZE;
+    let end = bytes.len() - QOI_PADDING.len();
+
+    for _ in 0..pixel_count {
+        if run > 0 {
+            run -= 1;
+        } else {
+            if p >= end {
+                return Err(ImageError::InvalidData("qoi stream is truncated"));
+            }
+            let b1 = bytes[p];
+            p += 1;
+
+            if b1 == QOI_OP_RGB || b1 == QOI_OP_RGBA {
+                let channels = if b1 == QOI_OP_RGB { 3 } else { 4 };
+                if p + channels > end {
+                    return Err(ImageError::InvalidData("qoi stream is truncated"));
+                }
+                px[..channels].copy_from_slice(&bytes[p..p + channels]);
+                p += channels;
+            } else {
+                match b1 & QOI_MASK {
+                    QOI_OP_INDEX => px = index[b1 as usize],
+                    QOI_OP_DIFF => {
+                        px[0] = px[0].wrapping_add(((b1 >> 4) & 0x03).wrapping_sub(2));
+                        px[1] = px[1].wrapping_add(((b1 >> 2) & 0x03).wrapping_sub(2));
+                        px[2] = px[2].wrapping_add((b1 & 0x03).wrapping_sub(2));
+                    },
+                    QOI_OP_LUMA => {
+                        if p >= end {
+                            return Err(ImageError::InvalidData("qoi stream is truncated"));
+                        }
+                        let b2 = bytes[p];
+                        p += 1;
+                        let vg = (b1 & 0x3F).wrapping_sub(32);
+                        px[0] = px[0].wrapping_add(vg.wrapping_sub(8).wrapping_add((b2 >> 4) & 0x0F));
+                        px[1] = px[1].wrapping_add(vg);
+                        px[2] = px[2].wrapping_add(vg.wrapping_sub(8).wrapping_add(b2 & 0x0F));
+                    },
+                    _ => run = b1 & 0x3F,
+                }
+            }
+
+            index[hash(px)] = px;
+        }
+
+        pixels.extend_from_slice(&px);
+    }
+
+    Ok((width, height, pixels))
+}
+
+pub fn encode(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(QOI_HEADER_SIZE + pixels.len() + QOI_PADDING.len());
+    bytes.extend_from_slice(&QOI_MAGIC);
+    bytes.extend_from_slice(&width.to_be_bytes());
+    bytes.extend_from_slice(&height.to_be_bytes());
+    bytes.push(4); // channels
+    bytes.push(0); // sRGB with linear alpha
+
+    let mut index = [[0u8; 4]; 64];
+    let mut prev = [0u8, 0, 0, 255];
+    let mut run = 0u8;
+    let pixel_count = width as usize * height as usize;
+
+    for i in 0..pixel_count {
+        let px = [pixels[i * 4], pixels[i * 4 + 1], pixels[i * 4 + 2], pixels[i * 4 + 3]];
+
+        if px == prev {
+            run += 1;
+            if run == 62 || i == pixel_count - 1 {
+                bytes.push(QOI_OP_RUN | (run - 1));
+                run = 0;
+            }
+            continue;
+        }
+
+        if run > 0 {
+            bytes.push(QOI_OP_RUN | (run - 1));
+            run = 0;
+        }
+
+        let h = hash(px);
+        if index[h] == px {
+            bytes.push(QOI_OP_INDEX | h as u8);
+        } else {
+            index[h] = px;
+
+            if px[3] == prev[3] {
+                let vr = px[0].wrapping_sub(prev[0]) as i8;
+                let vg = px[1].wrapping_sub(prev[1]) as i8;
+                let vb = px[2].wrapping_sub(prev[2]) as i8;
+                let vg_r = vr.wrapping_sub(vg);
+                let vg_b = vb.wrapping_sub(vg);
+
+                if vr > -3 && vr < 2 && vg > -3 && vg < 2 && vb > -3 && vb < 2 {
+                    bytes.push(QOI_OP_DIFF | (((vr + 2) as u8) << 4) | (((vg + 2) as u8) << 2) | (vb + 2) as u8);
+                } else if vg_r > -9 && vg_r < 8 && vg > -33 && vg < 32 && vg_b > -9 && vg_b < 8 {
+                    bytes.push(QOI_OP_LUMA | (vg + 32) as u8);
+                    bytes.push((((vg_r + 8) as u8) << 4) | (vg_b + 8) as u8);
+                } else {
+                    bytes.push(QOI_OP_RGB);
+                    bytes.extend_from_slice(&px[..3]);
+                }
+            } else {
+                bytes.push(QOI_OP_RGBA);
+                bytes.extend_from_slice(&px);
+            }
+        }
+
+        prev = px;
+    }
+
+    bytes.extend_from_slice(&QOI_PADDING);
+    bytes
+}
@@ -0,0 +1,223 @@
+/*
+ *   Copyright (c) 2021 Ludwig Bogsveen
+ *   All rights reserved.
+
+ *   Permission is hereby granted, free of charge, to any person obtaining a copy
+ *   of this software and associated documentation files (the "Software"), to deal
+ *   in the Software without restriction, including without limitation the rights
+ *   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *   copies of the Software, and to permit persons to whom the Software is
+ *   furnished to do so, subject to the following conditions:
+
+ *   The above copyright notice and this permission notice shall be included in all
+ *   copies or substantial portions of the Software.
+
+ *   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *   SOFTWARE.
+ */
+
+use std::rc::Rc;
+
+use engine_core::{error_log, window};
+
+use crate::framebuffer::{FrameBuffer, FrameBufferDescriptor, FrameBufferError};
+use crate::texture::Texture;
+
+// How big a render target is compared to the window framebuffer, Fixed targets never change size
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TargetSize {
+    Full,
+    Half,
+    Scaled(f32),
+    Fixed(u32, u32),
+}
+
+// Stays valid when the target is recreated after a resize, only remove invalidates it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RenderTargetHandle(usize);
+
+struct RenderTarget {
+    size: TargetSize,
+    descriptor: FrameBufferDescriptor,
+    frame_buffer: FrameBuffer,
+    version: u64,
+}
+
+// Owns framebuffers sized relative to the window and recreates their attachments when the window is resized
+pub struct RenderTargets {
+    frame_buffer_listener: bus::BusReader::<(u32, u32)>,
+    window_width: u32,
+    window_height: u32,
+    targets: Vec<Option<RenderTarget>>,
+}
+
+impl TargetSize {
+    pub fn resolve(&self, window_width: u32, window_height: u32) -> (u32, u32) {
+        let (width, height) = match *self {
+            TargetSize::Full => (window_width, window_height),
+            TargetSize::Half => (window_width / 2, window_height / 2),
+            TargetSize::Scaled(scale) => ((window_width as f32 * scale).round() as u32, (window_height as f32 * scale).round() as u32),
+            TargetSize::Fixed(width, height) => (width, height),
+        };
+        (width.max(1), height.max(1))
+    }
+}
+
+impl RenderTargets {
+    pub fn new(win: &mut window::Window) -> RenderTargets {
+        RenderTargets {
+            frame_buffer_listener: win.create_frame_buffer_listener(),
+            window_width: win.get_width() as u32,
+            window_height: win.get_height() as u32,
+            targets: Vec::new(),
+        }
+    }
+
+    // The width and height of the descriptor are replaced by the ones size gives
+    pub fn create(&mut self, size: TargetSize, descriptor: &FrameBufferDescriptor) -> Result<RenderTargetHandle, FrameBufferError> {
+        let mut descriptor = descriptor.clone();
+        let (width, height) = size.resolve(self.window_width, self.window_height);
+        descriptor.width = width;
+        descriptor.height = height;
+
+        let frame_buffer = FrameBuffer::from_descriptor(&descriptor)?;
+        self.targets.push(Some(RenderTarget { size, descriptor, frame_buffer, version: 0 }));
+        Ok(RenderTargetHandle(self.targets.len() - 1))
+    }
+
+    pub fn remove(&mut self, handle: RenderTargetHandle) {
+        if let Some(target) = self.targets.get_mut(handle.0) {
+            *target = None;
+        }
+    }
+
+    pub fn get(&self, handle: RenderTargetHandle) -> Option<&FrameBuffer> {
+        self.target(handle).map(|target| &target.frame_buffer)
+    }
+
+    // Texture regions made from the old texture stop working once the target is recreated
+    pub fn texture(&self, handle: RenderTargetHandle) -> Option<&Rc<Texture>> {
+        self.get(handle).and_then(|frame_buffer| frame_buffer.color_attachment(0))
+    }
+
+    // Goes up every time the target is recreated, so anything derived from its textures knows to refresh
+    pub fn version(&self, handle: RenderTargetHandle) -> Option<u64> {
+        self.target(handle).map(|target| target.version)
+    }
+
+    pub fn size(&self, handle: RenderTargetHandle) -> Option<TargetSize> {
+        self.target(handle).map(|target| target.size)
+    }
+
+    pub fn set_size(&mut self, handle: RenderTargetHandle, size: TargetSize) {
+        let (window_width, window_height) = (self.window_width, self.window_height);
+        if let Some(Some(target)) = self.targets.get_mut(handle.0) {
+            target.size = size;
+            RenderTargets::fit(target, window_width, window_height);
+        }
+    }
+
+    // Handles the resize events the window sent since the last call, returns true if the window size changed
+    pub fn update(&mut self) -> bool {
+        let mut size = None;
+        while let Ok(event) = self.frame_buffer_listener.try_recv() {
+            size = Some(event);
+        }
+
+        match size {
+            Some((width, height)) if (width, height) != (self.window_width, self.window_height) => {
+                self.resize(width, height);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    pub fn resize(&mut self, window_width: u32, window_height: u32) {
+        self.window_width = window_width;
+        self.window_height = window_height;
+        for target in self.targets.iter_mut().flatten() {
+            RenderTargets::fit(target, window_width, window_height);
+        }
+    }
+
+    pub fn window_size(&self) -> (u32, u32) {
+        (self.window_width, self.window_height)
+    }
+
+    fn target(&self, handle: RenderTargetHandle) -> Option<&RenderTarget> {
+        self.targets.get(handle.0).and_then(|target| target.as_ref())
+    }
+
+    // A minimized window reports a size of zero, the old attachments are kept until it comes back
+    fn fit(target: &mut RenderTarget, window_width: u32, window_height: u32) {
+        if window_width == 0 || window_height == 0 {
+            return;
+        }
+
+        let (width, height) = target.size.resolve(window_width, window_height);
+        if (width, height) == (target.descriptor.width, target.descriptor.height) {
+            return;
+        }
+
+        let mut descriptor = target.descriptor.clone();
+        descriptor.width = width;
+        descriptor.height = height;
+        match FrameBuffer::from_descriptor(&descriptor) {
+            Ok(frame_buffer) => {
+                target.frame_buffer = frame_buffer;
+                target.descriptor = descriptor;
+                target.version += 1;
+            },
+            Err(e) => error_log!("Failed to resize render target to {}x{}: {:?}", width, height, e),
+        }
+    }
+}
+
+// The framebuffer Graphics, Graphics2D and Graphics3D draw into, the window unless a target is set
+pub(crate) struct DrawTarget {
+    window_width: u32,
+    window_height: u32,
+    target: Option<(u32, u32, u32)>, // framebuffer id, width and height
+}
+
+impl DrawTarget {
+    pub fn new(window_width: u32, window_height: u32) -> DrawTarget {
+        DrawTarget {
+            window_width,
+            window_height,
+            target: None,
+        }
+    }
+
+    pub fn of(frame_buffer: Option<&FrameBuffer>) -> Option<(u32, u32, u32)> {
+        frame_buffer.map(|frame_buffer| (frame_buffer.gl_id(), frame_buffer.width(), frame_buffer.height()))
+    }
+
+    pub fn target(&self) -> Option<(u32, u32, u32)> {
+        self.target
+    }
+
+    // A framebuffer target keeps its own size, true when the frame follows the window to the new size
+    pub fn resize_window(&mut self, width: u32, height: u32) -> bool {
+        self.window_width = width;
+        self.window_height = height;
+        self.target.is_none()
+    }
+
+    // Binds the target, None being the window, and gives the size pixel coordinates follow from now on.
+    // Pending draws have to be flushed before.
+    pub fn bind(&mut self, target: Option<(u32, u32, u32)>) -> (u32, u32) {
+        let (gl_buffer_id, width, height) = target.unwrap_or((0, self.window_width, self.window_height));
+        unsafe {
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, gl_buffer_id));
+        }
+        self.target = target;
+        (width, height)
+    }
+}
@@ -36,10 +36,14 @@ pub mod std_renderer {
     pub enum Capability {
         Blending = gl::BLEND,
 		DepthTest = gl::DEPTH_TEST,
+        ScissorTest = gl::SCISSOR_TEST,
+        StencilTest = gl::STENCIL_TEST,
     }
 
     #[repr(u32)]
+    #[derive(Clone, Copy, Debug, PartialEq)]
     pub enum BlendMode {
+        Zero =              gl::ZERO,
         SrcColor =			gl::SRC_COLOR,
 		OneMinusSrcColor =	gl::ONE_MINUS_SRC_COLOR,
 		DstColor =			gl::DST_COLOR,
@@ -51,11 +55,52 @@ pub mod std_renderer {
         One =               gl::ONE,
     }
 
+    // The name graphics::BlendMode uses for a single factor
+    pub type BlendFactor = BlendMode;
+
+    #[repr(u32)]
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum BlendEquation {
+        Add =               gl::FUNC_ADD,
+        Subtract =          gl::FUNC_SUBTRACT,
+        ReverseSubtract =   gl::FUNC_REVERSE_SUBTRACT,
+        Min =               gl::MIN,
+        Max =               gl::MAX,
+    }
+
     #[repr(u32)]
     pub enum ClearTarget {
         Color = gl::COLOR_BUFFER_BIT,
         Depth = gl::DEPTH_BUFFER_BIT,
         Stencil = gl::STENCIL_BUFFER_BIT,
+        ColorDepth = gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT,
+        ColorStencil = gl::COLOR_BUFFER_BIT | gl::STENCIL_BUFFER_BIT,
+        DepthStencil = gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT,
+        All = gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT,
+    }
+
+    #[repr(u32)]
+    pub enum StencilFunction {
+        Never =         gl::NEVER,
+        Less =          gl::LESS,
+        LessEqual =     gl::LEQUAL,
+        Greater =       gl::GREATER,
+        GreaterEqual =  gl::GEQUAL,
+        Equal =         gl::EQUAL,
+        NotEqual =      gl::NOTEQUAL,
+        Always =        gl::ALWAYS,
+    }
+
+    #[repr(u32)]
+    pub enum StencilOperation {
+        Keep =          gl::KEEP,
+        Zero =          gl::ZERO,
+        Replace =       gl::REPLACE,
+        Increment =     gl::INCR,
+        IncrementWrap = gl::INCR_WRAP,
+        Decrement =     gl::DECR,
+        DecrementWrap = gl::DECR_WRAP,
+        Invert =        gl::INVERT,
     }
 
     pub unsafe fn draw_elements(primitive: RenderingPrimitive, n_vertices: i32) { gl_call!(gl::DrawElements(primitive as u32, n_vertices, gl::UNSIGNED_INT, 0 as *const std::ffi::c_void)); }
@@ -65,8 +110,41 @@ pub mod std_renderer {
     pub unsafe fn enable(cap: Capability) { gl_call!(gl::Enable(cap as u32)); }
 	pub unsafe fn disable(cap: Capability) { gl_call!(gl::Disable(cap as u32)); }
     pub unsafe fn blend_func(sfactor: BlendMode, dfactor: BlendMode) { gl_call!(gl::BlendFunc(sfactor as u32, dfactor as u32)); }
+    /// # Safety
+    /// A GL context has to be current on the calling thread.
+    pub unsafe fn blend_func_separate(src_rgb: BlendMode, dst_rgb: BlendMode, src_alpha: BlendMode, dst_alpha: BlendMode) {
+        gl_call!(gl::BlendFuncSeparate(src_rgb as u32, dst_rgb as u32, src_alpha as u32, dst_alpha as u32));
+    }
+    /// # Safety
+    /// A GL context has to be current on the calling thread.
+    pub unsafe fn blend_equation(equation: BlendEquation) { gl_call!(gl::BlendEquation(equation as u32)); }
+    /// # Safety
+    /// A GL context has to be current on the calling thread.
+    pub unsafe fn blend_equation_separate(rgb: BlendEquation, alpha: BlendEquation) { gl_call!(gl::BlendEquationSeparate(rgb as u32, alpha as u32)); }
     
     pub unsafe fn clear(target: ClearTarget) { gl_call!(gl::Clear(target as u32)); }
+    /// # Safety
+    /// A GL context has to be current on the calling thread.
+    pub unsafe fn set_clear_stencil(value: i32) { gl_call!(gl::ClearStencil(value)); }
+
+    /// x and y is the bottom left corner in framebuffer pixels
+    ///
+    /// # Safety
+    /// A GL context has to be current on the calling thread.
+    pub unsafe fn scissor(x: i32, y: i32, width: i32, height: i32) { gl_call!(gl::Scissor(x, y, width.max(0), height.max(0))); }
+
+    /// # Safety
+    /// A GL context has to be current on the calling thread.
+    pub unsafe fn stencil_func(function: StencilFunction, reference: i32, mask: u32) { gl_call!(gl::StencilFunc(function as u32, reference, mask)); }
+    /// # Safety
+    /// A GL context has to be current on the calling thread.
+    pub unsafe fn stencil_op(stencil_fail: StencilOperation, depth_fail: StencilOperation, pass: StencilOperation) { gl_call!(gl::StencilOp(stencil_fail as u32, depth_fail as u32, pass as u32)); }
+    /// # Safety
+    /// A GL context has to be current on the calling thread.
+    pub unsafe fn stencil_mask(mask: u32) { gl_call!(gl::StencilMask(mask)); }
+    /// # Safety
+    /// A GL context has to be current on the calling thread.
+    pub unsafe fn color_mask(r: bool, g: bool, b: bool, a: bool) { gl_call!(gl::ColorMask(r as u8, g as u8, b as u8, a as u8)); }
     
     pub unsafe fn set_clear_color(rgba: Color) { 
         let (r, g, b, a) = <(f32, f32, f32, f32)>::from(rgba);
@@ -159,6 +237,52 @@ pub mod graphics_renderer {
             self.batch.add_vertex_data(vertex_data);
         }
     }
+
+    // Draws the vertices as a plain triangle list without a geometry shader, the texture is only bound when valid
+    pub struct TriangleBatchRenderer {
+        pub shader: shader::Shader,
+        pub texture: texture::TextureRegion,
+        batch: batch::Batch,
+        pub layout: buffer::VertexBufferLayout,
+    }
+
+    impl TriangleBatchRenderer {
+        pub fn new(shader: shader::Shader, layout: buffer::VertexBufferLayout) -> TriangleBatchRenderer {
+            TriangleBatchRenderer {
+                shader,
+                texture: texture::TextureRegion::new_invalid(),
+                batch: batch::Batch::new(1024),
+                layout,
+            }
+        }
+
+        pub fn flush(&mut self) {
+            let batch = self.batch.get(&self.layout);
+            batch.0.bind();
+
+            self.shader.bind();
+            let textured = self.texture.is_valid();
+            if textured {
+                self.texture.bind(0);
+            }
+
+            unsafe { renderer::std_renderer::draw_array(renderer::std_renderer::RenderingPrimitive::Triangles, batch.2 as i32); }
+
+            buffer::VertexArray::un_bind();
+            shader::Shader::un_bind();
+            if textured {
+                texture::Texture::un_bind();
+            }
+        }
+
+        pub fn add_vertex_data(&mut self, vertex_data: &[f32]) {
+            if self.batch.capacity() - self.batch.len() < vertex_data.len() {
+                let data = self.batch.as_mut_vec();
+                data.reserve(data.capacity().max(vertex_data.len()));
+            }
+            self.batch.add_vertex_data(vertex_data);
+        }
+    }
 }
 
 use engine_core::{error_log, fatal_log, window};
@@ -226,4 +350,36 @@ pub fn gl_string(gl_enum: u32) -> String {
 
 pub fn gl_version() -> String {
     gl_string(gl::VERSION)
-}
\ No newline at end of file
+}
+
+pub fn gl_version_number() -> (u32, u32) {
+    unsafe {
+        let mut major = 0;
+        let mut minor = 0;
+        gl_call!(gl::GetIntegerv(gl::MAJOR_VERSION, &mut major));
+        gl_call!(gl::GetIntegerv(gl::MINOR_VERSION, &mut minor));
+        (major as u32, minor as u32)
+    }
+}
+
+pub fn gl_extensions() -> Vec<String> {
+    unsafe {
+        let mut n_extensions = 0;
+        gl_call!(gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut n_extensions));
+
+        let mut extensions = Vec::with_capacity(n_extensions as usize);
+        for i in 0..n_extensions {
+            let ptr = gl::GetStringi(gl::EXTENSIONS, i as u32);
+            if ptr.is_null() {
+                continue;
+            }
+            let name = std::ffi::CStr::from_ptr(ptr as *const std::os::raw::c_char);
+            extensions.push(name.to_string_lossy().into_owned());
+        }
+        extensions
+    }
+}
+
+pub fn gl_has_extension(name: &str) -> bool {
+    gl_extensions().iter().any(|extension| extension == name)
+}
@@ -0,0 +1,262 @@
+/*
+ *   Copyright (c) 2021 Ludwig Bogsveen
+ *   All rights reserved.
+
+ *   Permission is hereby granted, free of charge, to any person obtaining a copy
+ *   of this software and associated documentation files (the "Software"), to deal
+ *   in the Software without restriction, including without limitation the rights
+ *   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *   copies of the Software, and to permit persons to whom the Software is
+ *   furnished to do so, subject to the following conditions:
+
+ *   The above copyright notice and this permission notice shall be included in all
+ *   copies or substantial portions of the Software.
+
+ *   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *   SOFTWARE.
+ */
+
+use std::collections::HashMap;
+
+use crate::color::Color;
+use crate::font::{Font, FontRenderMode};
+use crate::text::{StyledRange, TextLayout, TextLayoutSettings};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarkupError {
+    UnterminatedTag(usize), // byte offset of the '['
+    UnknownTag(String),
+    UnexpectedClosingTag(String),
+    InvalidValue(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextStyle {
+    pub family: String,
+    pub size: u32,
+    pub bold: bool,
+    pub italic: bool,
+    pub color: Option<Color>, // None uses the color set on the graphics
+    pub underline: bool,
+    pub strikethrough: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextSpan {
+    pub text: String,
+    pub style: TextStyle,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RichText {
+    spans: Vec<TextSpan>,
+}
+
+// Fonts rich text can switch between. Families registered with font files get new sizes and styles loaded when
+// they are first used, otherwise the closest font that was added is used.
+pub struct FontCollection {
+    families: HashMap<String, [Option<String>; 4]>, // regular, bold, italic and bold italic files
+    fonts: Vec<Font>,
+    keys: Vec<(String, u32, bool, bool)>,
+    render_mode: FontRenderMode,
+}
+
+impl TextStyle {
+    pub fn new(family: &str, size: u32) -> TextStyle {
+        TextStyle {
+            family: family.to_string(),
+            size,
+            bold: false,
+            italic: false,
+            color: None,
+            underline: false,
+            strikethrough: false,
+        }
+    }
+}
+
+fn parse_color(value: &str) -> Option<Color> {
+    let hex = value.strip_prefix('#')?;
+    let digits: Vec<u8> = hex.chars().map(|c| c.to_digit(16).map(|d| d as u8)).collect::<Option<Vec<u8>>>()?;
+    let (r, g, b, a) = match digits.len() {
+        3 => (digits[0] * 17, digits[1] * 17, digits[2] * 17, 255),
+        4 => (digits[0] * 17, digits[1] * 17, digits[2] * 17, digits[3] * 17),
+        6 => (digits[0] << 4 | digits[1], digits[2] << 4 | digits[3], digits[4] << 4 | digits[5], 255),
+        8 => (digits[0] << 4 | digits[1], digits[2] << 4 | digits[3], digits[4] << 4 | digits[5], digits[6] << 4 | digits[7]),
+        _ => return None,
+    };
+    Some(Color::from((r, g, b, a)))
+}
+
+impl Default for RichText {
+    fn default() -> Self { RichText::new() }
+}
+
+impl RichText {
+    pub fn new() -> RichText {
+        RichText { spans: Vec::new() }
+    }
+
+    pub fn push(&mut self, text: &str, style: TextStyle) {
+        if text.is_empty() {
+            return;
+        }
+        match self.spans.last_mut() {
+            Some(span) if span.style == style => span.text.push_str(text),
+            _ => self.spans.push(TextSpan { text: text.to_string(), style }),
+        }
+    }
+
+    // Supports [b], [i], [u], [s], [color=#rrggbb], [size=24] and [font=name], each closed with [/tag].
+    // Tags still open at the end are closed automatically and [[ is a literal [.
+    pub fn parse(markup: &str, base: &TextStyle) -> Result<RichText, MarkupError> {
+        let mut text = RichText::new();
+        let mut stack: Vec<(String, TextStyle)> = Vec::new();
+        let mut style = base.clone();
+        let mut rest = markup;
+        let mut offset = 0;
+
+        while let Some(open) = rest.find('[') {
+            text.push(&rest[..open], style.clone());
+            if rest[open + 1..].starts_with('[') {
+                text.push("[", style.clone());
+                offset += open + 2;
+                rest = &rest[open + 2..];
+                continue;
+            }
+
+            let close = match rest[open..].find(']') {
+                Some(close) => open + close,
+                None => return Err(MarkupError::UnterminatedTag(offset + open)),
+            };
+            let tag = &rest[open + 1..close];
+            offset += close + 1;
+            rest = &rest[close + 1..];
+
+            if let Some(name) = tag.strip_prefix('/') {
+                match stack.pop() {
+                    Some((open_name, previous)) if open_name == name => style = previous,
+                    _ => return Err(MarkupError::UnexpectedClosingTag(name.to_string())),
+                }
+                continue;
+            }
+
+            let (name, value) = match tag.find('=') {
+                Some(i) => (&tag[..i], Some(tag[i + 1..].trim_matches('"'))),
+                None => (tag, None),
+            };
+            let previous = style.clone();
+            match (name, value) {
+                ("b", None) => style.bold = true,
+                ("i", None) => style.italic = true,
+                ("u", None) => style.underline = true,
+                ("s", None) => style.strikethrough = true,
+                ("color", Some(value)) => style.color = Some(parse_color(value).ok_or_else(|| MarkupError::InvalidValue(tag.to_string()))?),
+                ("size", Some(value)) => style.size = value.parse().ok().filter(|&size| size > 0).ok_or_else(|| MarkupError::InvalidValue(tag.to_string()))?,
+                ("font", Some(value)) => style.family = value.to_string(),
+                _ => return Err(MarkupError::UnknownTag(tag.to_string())),
+            }
+            stack.push((name.to_string(), previous));
+        }
+        text.push(rest, style);
+
+        Ok(text)
+    }
+
+    pub fn text(&self) -> String {
+        self.spans.iter().map(|span| span.text.as_str()).collect()
+    }
+
+    // Lays out the text with fonts from the collection, byte offsets in the layout refer to text()
+    pub fn layout(&self, fonts: &mut FontCollection, settings: &TextLayoutSettings) -> TextLayout {
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        for span in &self.spans {
+            let end = start + span.text.len();
+            if let Some(font) = fonts.font_index(&span.style) {
+                ranges.push(StyledRange {
+                    color: span.style.color,
+                    underline: span.style.underline,
+                    strikethrough: span.style.strikethrough,
+                    ..StyledRange::new(start, end, font)
+                });
+            }
+            start = end;
+        }
+
+        TextLayout::styled(fonts.fonts().as_slice(), &self.text(), &ranges, settings)
+    }
+
+    pub fn spans(&self) -> &Vec<TextSpan> { &self.spans }
+}
+
+impl Default for FontCollection {
+    fn default() -> Self { FontCollection::new() }
+}
+
+impl FontCollection {
+    pub fn new() -> FontCollection {
+        FontCollection {
+            families: HashMap::new(),
+            fonts: Vec::new(),
+            keys: Vec::new(),
+            render_mode: FontRenderMode::Bitmap,
+        }
+    }
+
+    pub fn add_family(&mut self, family: &str, regular: &str, bold: Option<&str>, italic: Option<&str>, bold_italic: Option<&str>) {
+        let files = [Some(regular.to_string()), bold.map(str::to_string), italic.map(str::to_string), bold_italic.map(str::to_string)];
+        self.families.insert(family.to_string(), files);
+    }
+
+    pub fn add_font(&mut self, family: &str, size: u32, bold: bool, italic: bool, mut font: Font) -> usize {
+        font.set_render_mode(self.render_mode);
+        self.fonts.push(font);
+        self.keys.push((family.to_string(), size, bold, italic));
+        self.fonts.len() - 1
+    }
+
+    // Finds or loads the font for a style. Without an exact match the family's regular file is loaded, then the
+    // closest size of the family is used and at last the first font of the collection.
+    pub fn font_index(&mut self, style: &TextStyle) -> Option<usize> {
+        let key = (style.family.clone(), style.size, style.bold, style.italic);
+        if let Some(i) = self.keys.iter().position(|k| *k == key) {
+            return Some(i);
+        }
+
+        if let Some(files) = self.families.get(&style.family) {
+            let variant = style.bold as usize + style.italic as usize * 2;
+            if let Some(file) = files[variant].clone().or_else(|| files[0].clone()) {
+                let font = Font::new(&file, style.size);
+                return Some(self.add_font(&style.family, style.size, style.bold, style.italic, font));
+            }
+        }
+
+        self.keys.iter().enumerate()
+            .filter(|(_, k)| k.0 == style.family)
+            .min_by_key(|(_, k)| ((k.2 != style.bold) as u32 + (k.3 != style.italic) as u32, (k.1 as i64 - style.size as i64).abs()))
+            .map(|(i, _)| i)
+            .or((!self.fonts.is_empty()).then_some(0))
+    }
+
+    // Applies to every font in the collection and the ones loaded later
+    pub fn set_render_mode(&mut self, render_mode: FontRenderMode) {
+        self.render_mode = render_mode;
+        for font in &mut self.fonts {
+            font.set_render_mode(render_mode);
+        }
+    }
+
+    pub fn fonts(&self) -> Vec<&Font> {
+        self.fonts.iter().collect()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Font> {
+        self.fonts.get(index)
+    }
+}
@@ -0,0 +1,350 @@
+/*
+ *   Copyright (c) 2021 Ludwig Bogsveen
+ *   All rights reserved.
+
+ *   Permission is hereby granted, free of charge, to any person obtaining a copy
+ *   of this software and associated documentation files (the "Software"), to deal
+ *   in the Software without restriction, including without limitation the rights
+ *   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *   copies of the Software, and to permit persons to whom the Software is
+ *   furnished to do so, subject to the following conditions:
+
+ *   The above copyright notice and this permission notice shall be included in all
+ *   copies or substantial portions of the Software.
+
+ *   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *   SOFTWARE.
+ */
+
+use crate::texture::Image;
+
+// Channels an edge contributes to in a multi-channel distance field
+const RED: u8 = 1;
+const GREEN: u8 = 2;
+const BLUE: u8 = 4;
+const CYAN: u8 = GREEN | BLUE;
+const MAGENTA: u8 = RED | BLUE;
+const YELLOW: u8 = RED | GREEN;
+const WHITE: u8 = RED | GREEN | BLUE;
+
+// sin(3), edges whose directions differ by more than pi - 3 radians (about 8 degrees) count as a corner
+const CORNER_THRESHOLD: f32 = 0.1411;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DistanceField {
+    Sdf,
+    Msdf,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Edge {
+    Line((f32, f32), (f32, f32)),
+    Quad((f32, f32), (f32, f32), (f32, f32)),
+    Cubic((f32, f32), (f32, f32), (f32, f32), (f32, f32)),
+}
+
+struct Segment {
+    a: (f32, f32),
+    b: (f32, f32),
+    color: u8,
+    first: bool, // the segment starts or ends its edge, distances past those ends are measured to the tangent line
+    last: bool,
+}
+
+// A glyph outline or any other closed shape built from lines and bezier curves
+pub struct Outline {
+    contours: Vec<Vec<Edge>>,
+    position: (f32, f32),
+}
+
+fn sub(a: (f32, f32), b: (f32, f32)) -> (f32, f32) { (a.0 - b.0, a.1 - b.1) }
+fn dot(a: (f32, f32), b: (f32, f32)) -> f32 { a.0 * b.0 + a.1 * b.1 }
+fn cross(a: (f32, f32), b: (f32, f32)) -> f32 { a.0 * b.1 - a.1 * b.0 }
+fn length(a: (f32, f32)) -> f32 { dot(a, a).sqrt() }
+
+fn normalize(a: (f32, f32)) -> (f32, f32) {
+    let l = length(a);
+    if l == 0.0 { (0.0, 0.0) } else { (a.0 / l, a.1 / l) }
+}
+
+fn lerp(a: (f32, f32), b: (f32, f32), t: f32) -> (f32, f32) {
+    (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t)
+}
+
+impl Edge {
+    fn point(&self, t: f32) -> (f32, f32) {
+        match *self {
+            Edge::Line(a, b) => lerp(a, b, t),
+            Edge::Quad(a, b, c) => lerp(lerp(a, b, t), lerp(b, c, t), t),
+            Edge::Cubic(a, b, c, d) => {
+                let ab = lerp(a, b, t);
+                let bc = lerp(b, c, t);
+                let cd = lerp(c, d, t);
+                lerp(lerp(ab, bc, t), lerp(bc, cd, t), t)
+            },
+        }
+    }
+
+    fn start_direction(&self) -> (f32, f32) {
+        match *self {
+            Edge::Line(a, b) => sub(b, a),
+            Edge::Quad(a, b, c) => if a == b { sub(c, a) } else { sub(b, a) },
+            Edge::Cubic(a, b, c, d) => if a != b { sub(b, a) } else if a != c { sub(c, a) } else { sub(d, a) },
+        }
+    }
+
+    fn end_direction(&self) -> (f32, f32) {
+        match *self {
+            Edge::Line(a, b) => sub(b, a),
+            Edge::Quad(a, b, c) => if b == c { sub(c, a) } else { sub(c, b) },
+            Edge::Cubic(a, b, c, d) => if c != d { sub(d, c) } else if b != d { sub(d, b) } else { sub(d, a) },
+        }
+    }
+
+    fn control_points(&self) -> Vec<(f32, f32)> {
+        match *self {
+            Edge::Line(a, b) => vec![a, b],
+            Edge::Quad(a, b, c) => vec![a, b, c],
+            Edge::Cubic(a, b, c, d) => vec![a, b, c, d],
+        }
+    }
+
+    fn pieces(&self) -> usize {
+        match self {
+            Edge::Line(..) => 1,
+            Edge::Quad(..) => 8,
+            Edge::Cubic(..) => 12,
+        }
+    }
+}
+
+fn is_corner(a: (f32, f32), b: (f32, f32)) -> bool {
+    let a = normalize(a);
+    let b = normalize(b);
+    dot(a, b) <= 0.0 || cross(a, b).abs() > CORNER_THRESHOLD
+}
+
+// Gives the edges of a contour colors so that the two edges meeting at a corner never share all their channels
+fn color_contour(edges: &[Edge]) -> Vec<u8> {
+    let count = edges.len();
+    let corners: Vec<usize> = (0..count).filter(|&i| is_corner(edges[(i + count - 1) % count].end_direction(), edges[i].start_direction())).collect();
+
+    match corners.len() {
+        0 => vec![WHITE; count],
+        1 => {
+            if count < 3 {
+                return vec![WHITE; count];
+            }
+            // A teardrop, the edges are split in three groups around the curve
+            let colors = [CYAN, WHITE, MAGENTA];
+            let mut result = vec![WHITE; count];
+            for j in 0..count {
+                result[(corners[0] + j) % count] = colors[(3 * j / count).min(2)];
+            }
+            result
+        },
+        splines => {
+            let mut result = vec![WHITE; count];
+            let mut spline = 0;
+            for j in 0..count {
+                let i = (corners[0] + j) % count;
+                if j > 0 && corners.contains(&i) {
+                    spline += 1;
+                }
+                result[i] = if spline == splines - 1 && spline % 2 == 0 { YELLOW } else if spline % 2 == 0 { CYAN } else { MAGENTA };
+            }
+            result
+        },
+    }
+}
+
+impl Default for Outline {
+    fn default() -> Self { Outline::new() }
+}
+
+impl Outline {
+    pub fn new() -> Outline {
+        Outline {
+            contours: Vec::new(),
+            position: (0.0, 0.0),
+        }
+    }
+
+    pub fn move_to(&mut self, x: f32, y: f32) {
+        self.close();
+        self.contours.push(Vec::new());
+        self.position = (x, y);
+    }
+
+    pub fn line_to(&mut self, x: f32, y: f32) {
+        if (x, y) != self.position {
+            self.push(Edge::Line(self.position, (x, y)));
+        }
+    }
+
+    pub fn quad_to(&mut self, cx: f32, cy: f32, x: f32, y: f32) {
+        self.push(Edge::Quad(self.position, (cx, cy), (x, y)));
+    }
+
+    pub fn cubic_to(&mut self, c1x: f32, c1y: f32, c2x: f32, c2y: f32, x: f32, y: f32) {
+        self.push(Edge::Cubic(self.position, (c1x, c1y), (c2x, c2y), (x, y)));
+    }
+
+    // Contours are closed automatically when a new one starts or the outline is rendered
+    pub fn close(&mut self) {
+        let start = match self.contours.last().and_then(|contour| contour.first()) {
+            Some(edge) => edge.point(0.0),
+            None => return,
+        };
+        if start != self.position {
+            self.push(Edge::Line(self.position, start));
+        }
+    }
+
+    fn push(&mut self, edge: Edge) {
+        if self.contours.is_empty() {
+            self.contours.push(Vec::new());
+        }
+        self.position = edge.point(1.0);
+        self.contours.last_mut().unwrap().push(edge);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contours.iter().all(|contour| contour.is_empty())
+    }
+
+    // Left, bottom, right and top of the control points
+    pub fn bounds(&self) -> (f32, f32, f32, f32) {
+        let mut bounds = (f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY);
+        for edge in self.contours.iter().flatten() {
+            for point in edge.control_points() {
+                bounds = (bounds.0.min(point.0), bounds.1.min(point.1), bounds.2.max(point.0), bounds.3.max(point.1));
+            }
+        }
+        bounds
+    }
+
+    fn segments(&self, field: DistanceField) -> Vec<Segment> {
+        let mut segments = Vec::new();
+        for contour in self.contours.iter().filter(|contour| !contour.is_empty()) {
+            let colors = match field {
+                DistanceField::Sdf => vec![WHITE; contour.len()],
+                DistanceField::Msdf => color_contour(contour),
+            };
+            for (edge, color) in contour.iter().zip(colors) {
+                let pieces = edge.pieces();
+                for i in 0..pieces {
+                    segments.push(Segment {
+                        a: edge.point(i as f32 / pieces as f32),
+                        b: edge.point((i + 1) as f32 / pieces as f32),
+                        color,
+                        first: i == 0,
+                        last: i == pieces - 1,
+                    });
+                }
+            }
+        }
+        segments
+    }
+
+    // Renders the distance field of the outline into an image of the given size, (x, y) is the point of the outline
+    // that ends up in the bottom left corner of the image. Distances are clamped to spread pixels and stored so
+    // that 0.5 is on the outline and values above are inside. Sdf fields store the distance in every channel,
+    // Msdf fields store the multi-channel distances in rgb and the true distance in alpha.
+    pub fn distance_field(&mut self, field: DistanceField, width: u32, height: u32, x: f32, y: f32, spread: f32) -> Image {
+        self.close();
+        let segments = self.segments(field);
+
+        // Signed area tells which side of the edges is inside, TrueType and PostScript outlines turn opposite ways
+        let area: f32 = segments.iter().map(|segment| cross(segment.a, segment.b)).sum();
+        let orientation = if area < 0.0 { -1.0 } else { 1.0 };
+
+        let mut image = Image::from_color(width, height, 0x00_00_00_00);
+        let spread = spread.max(1.0);
+        let encode = |distance: f32| ((0.5 + distance / (2.0 * spread)).clamp(0.0, 1.0) * 255.0).round() as u32;
+
+        for py in 0..height {
+            for px in 0..width {
+                let p = (x + px as f32 + 0.5, y + py as f32 + 0.5);
+
+                // Closest segment overall and for each channel, ties are broken by how straight on the point is
+                let mut closest = f32::INFINITY;
+                let mut channels: [(f32, f32, Option<usize>); 3] = [(f32::INFINITY, 0.0, None); 3];
+                let mut winding = 0;
+
+                for (i, segment) in segments.iter().enumerate() {
+                    let ab = sub(segment.b, segment.a);
+                    let ap = sub(p, segment.a);
+                    let t = if dot(ab, ab) == 0.0 { 0.0 } else { (dot(ap, ab) / dot(ab, ab)).clamp(0.0, 1.0) };
+                    let distance = length(sub(p, lerp(segment.a, segment.b, t)));
+                    closest = closest.min(distance);
+
+                    if field == DistanceField::Msdf {
+                        let orthogonality = cross(normalize(ab), normalize(sub(p, lerp(segment.a, segment.b, t)))).abs();
+                        for (channel, best) in channels.iter_mut().enumerate() {
+                            if segment.color & (1 << channel) == 0 {
+                                continue;
+                            }
+                            if distance < best.0 - 1e-4 || ((distance - best.0).abs() <= 1e-4 && orthogonality > best.1) {
+                                *best = (distance, orthogonality, Some(i));
+                            }
+                        }
+                    }
+
+                    // Non-zero winding rule
+                    if (segment.a.1 <= p.1) != (segment.b.1 <= p.1) {
+                        let side = cross(ab, ap);
+                        if segment.b.1 > segment.a.1 && side > 0.0 {
+                            winding += 1;
+                        } else if segment.b.1 <= segment.a.1 && side < 0.0 {
+                            winding -= 1;
+                        }
+                    }
+                }
+
+                let inside = winding != 0;
+                let true_distance = if inside { closest } else { -closest };
+                let alpha = encode(true_distance);
+
+                let color = match field {
+                    DistanceField::Sdf => alpha << 24 | alpha << 16 | alpha << 8 | alpha,
+                    DistanceField::Msdf => {
+                        let mut values = [true_distance; 3];
+                        for (channel, best) in channels.iter().enumerate() {
+                            let segment = match best.2 {
+                                Some(i) => &segments[i],
+                                None => continue,
+                            };
+                            let ab = sub(segment.b, segment.a);
+                            let ap = sub(p, segment.a);
+                            let t = if dot(ab, ab) == 0.0 { 0.0 } else { dot(ap, ab) / dot(ab, ab) };
+                            let side = orientation * cross(ab, ap).signum();
+                            // Past the ends of an edge the distance to its tangent line keeps corners sharp
+                            let distance = if (t < 0.0 && segment.first) || (t > 1.0 && segment.last) {
+                                cross(normalize(ab), ap).abs().min(best.0)
+                            } else {
+                                best.0
+                            };
+                            values[channel] = side * distance;
+                        }
+
+                        // Where the channels disagree with the real inside test the plain distance is used
+                        let median = values[0].max(values[1]).min(values[0].min(values[1]).max(values[2]));
+                        if (median > 0.0) != inside && true_distance.abs() > 0.5 {
+                            values = [true_distance; 3];
+                        }
+                        encode(values[0]) << 24 | encode(values[1]) << 16 | encode(values[2]) << 8 | alpha
+                    },
+                };
+                image.set_rgba8(px, py, color);
+            }
+        }
+
+        image
+    }
+}
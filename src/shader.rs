@@ -147,11 +147,29 @@ impl Shader {
 		shaders
 	}
     
+    // #include "file" lines are replaced with the file, the path is relative to the including file
     pub fn load_file(file_path: &str) -> std::string::String {
         let mut file = File::open(file_path).expect(&format!("Unable to open shader file: {}", file_path));
         let mut contents = String::new();
         file.read_to_string(&mut contents).expect(&format!("Unable to read shader file: {}", file_path));
-        contents
+
+        if !contents.contains("#include") {
+            return contents;
+        }
+
+        let directory = std::path::Path::new(file_path).parent().unwrap_or_else(|| std::path::Path::new(""));
+        let mut source = String::with_capacity(contents.len());
+        for line in contents.lines() {
+            match line.trim().strip_prefix("#include") {
+                Some(include) => {
+                    let include = directory.join(include.trim().trim_matches('"'));
+                    source.push_str(&Shader::load_file(&include.to_string_lossy()));
+                },
+                None => source.push_str(line),
+            }
+            source.push('\n');
+        }
+        source
 	}
 
     pub fn upload_from_name_1i(&self, uniform: &str, v1: i32)							    { self.upload_1i(self.uniform_location(uniform), v1); }
@@ -202,6 +220,16 @@ impl Shader {
 		}
 	}
 
+	// Like uniform_location but quiet, for uniforms a shader is allowed to leave out
+	pub fn find_uniform(&self, uniform: &str) -> Option<i32> {
+		let location;
+		unsafe {
+			let name = std::ffi::CString::new(uniform).unwrap();
+			gl_call!(location = gl::GetUniformLocation(self.gl_buffer_id, name.as_ptr()));
+		}
+		if location == -1 { None } else { Some(location) }
+	}
+
 	pub fn uniform_names(&self) -> Vec<String> {
 		unsafe {
 			let mut uniforms = Vec::new();
@@ -0,0 +1,610 @@
+/*
+ *   Copyright (c) 2021 Ludwig Bogsveen
+ *   All rights reserved.
+
+ *   Permission is hereby granted, free of charge, to any person obtaining a copy
+ *   of this software and associated documentation files (the "Software"), to deal
+ *   in the Software without restriction, including without limitation the rights
+ *   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *   copies of the Software, and to permit persons to whom the Software is
+ *   furnished to do so, subject to the following conditions:
+
+ *   The above copyright notice and this permission notice shall be included in all
+ *   copies or substantial portions of the Software.
+
+ *   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *   SOFTWARE.
+ */
+
+use engine_core::error_log;
+
+use crate::color::Color;
+use crate::font::{Font, FontRenderMode, PositionedGlyph};
+use crate::shader::Shader;
+use crate::texture::TextureRegion;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextWrap {
+    None,
+    Word,
+    Character,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HorizontalAlign {
+    Left,
+    Center,
+    Right,
+    Justify,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VerticalAlign {
+    Top,
+    Middle,
+    Bottom,
+}
+
+#[derive(Clone, Debug)]
+pub struct TextLayoutSettings {
+    pub max_width: Option<f32>,
+    pub max_height: Option<f32>,
+    pub max_lines: Option<usize>,
+    pub wrap: TextWrap,
+    pub align: HorizontalAlign,
+    pub vertical_align: VerticalAlign, // only used together with max_height
+    pub line_height: f32, // multiple of the font height
+    pub ellipsis: Option<String>,
+}
+
+impl Default for TextLayoutSettings {
+    fn default() -> Self { TextLayoutSettings::new() }
+}
+
+impl TextLayoutSettings {
+    pub fn new() -> TextLayoutSettings {
+        TextLayoutSettings {
+            max_width: None,
+            max_height: None,
+            max_lines: None,
+            wrap: TextWrap::Word,
+            align: HorizontalAlign::Left,
+            vertical_align: VerticalAlign::Top,
+            line_height: 1.0,
+            ellipsis: None,
+        }
+    }
+}
+
+// Effects for text drawn with a distance field font, sizes and offsets are in pixels of the glyphs as they were
+// generated and can't reach further out than the spread of the font
+#[derive(Clone, Copy)]
+pub struct TextEffects {
+    pub outline_width: f32,
+    pub outline_color: Color,
+    pub glow_width: f32,
+    pub glow_color: Color,
+    pub shadow_offset: (f32, f32),
+    pub shadow_softness: f32,
+    pub shadow_color: Color,
+}
+
+impl Default for TextEffects {
+    fn default() -> Self { TextEffects::new() }
+}
+
+impl TextEffects {
+    pub fn new() -> TextEffects {
+        TextEffects {
+            outline_width: 0.0,
+            outline_color: Color::from(0x00_00_00_FF),
+            glow_width: 0.0,
+            glow_color: Color::from(0xFF_FF_FF_FF),
+            shadow_offset: (0.0, 0.0),
+            shadow_softness: 0.0,
+            shadow_color: Color::from(0x00_00_00_00),
+        }
+    }
+}
+
+// Keeps track of the distance field uniforms of a sprite shader
+pub(crate) struct TextRendering {
+    pub effects: TextEffects,
+    pub mode: FontRenderMode, // render mode of the text in the sprite batch
+    uploaded: bool,
+}
+
+impl TextRendering {
+    pub fn new() -> TextRendering {
+        TextRendering {
+            effects: TextEffects::new(),
+            mode: FontRenderMode::Bitmap,
+            uploaded: false,
+        }
+    }
+
+    // Has to be called before every sprite flush
+    pub fn upload(&mut self, shader: &Shader) {
+        let (mode, spread) = match self.mode {
+            FontRenderMode::Bitmap => (0, 0),
+            FontRenderMode::Sdf { spread } => (1, spread),
+            FontRenderMode::Msdf { spread } => (2, spread),
+        };
+
+        if mode == 0 {
+            if self.uploaded {
+                shader.bind();
+                shader.upload_from_name_1i("u_distance_field", 0);
+                self.uploaded = false;
+            }
+            return;
+        }
+
+        let color = |color: Color| -> (f32, f32, f32, f32) { color.into() };
+        let effects = &self.effects;
+        let outline = color(effects.outline_color);
+        let glow = color(effects.glow_color);
+        let shadow = color(effects.shadow_color);
+
+        shader.bind();
+        shader.upload_from_name_1i("u_distance_field", mode);
+        shader.upload_from_name_1f("u_spread", spread as f32);
+        shader.upload_from_name_1f("u_outline_width", effects.outline_width);
+        shader.upload_from_name_4f("u_outline_color", outline.0, outline.1, outline.2, outline.3);
+        shader.upload_from_name_1f("u_glow_width", effects.glow_width);
+        shader.upload_from_name_4f("u_glow_color", glow.0, glow.1, glow.2, glow.3);
+        shader.upload_from_name_2f("u_shadow_offset", effects.shadow_offset.0, effects.shadow_offset.1);
+        shader.upload_from_name_1f("u_shadow_softness", effects.shadow_softness);
+        shader.upload_from_name_4f("u_shadow_color", shadow.0, shadow.1, shadow.2, shadow.3);
+        self.uploaded = true;
+    }
+}
+
+// The sprite batches of the graphics front-ends, distance field text is batched on its own since the sprite shader
+// draws it differently. set_text_effects on a front-end only affects fonts with a distance field render mode.
+pub(crate) trait TextBatch {
+    fn text_rendering(&mut self) -> &mut TextRendering;
+    fn flush(&mut self);
+
+    fn begin_text(&mut self, mode: FontRenderMode) {
+        if mode != self.text_rendering().mode {
+            self.flush();
+            self.text_rendering().mode = mode;
+        }
+    }
+
+    fn end_text(&mut self) {
+        self.begin_text(FontRenderMode::Bitmap);
+    }
+}
+
+// A part of the text drawn with one of the fonts the layout is made with, color None uses the graphics color
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StyledRange {
+    pub start: usize,
+    pub end: usize,
+    pub font: usize,
+    pub color: Option<Color>,
+    pub underline: bool,
+    pub strikethrough: bool,
+}
+
+impl StyledRange {
+    pub fn new(start: usize, end: usize, font: usize) -> StyledRange {
+        StyledRange {
+            start,
+            end,
+            font,
+            color: None,
+            underline: false,
+            strikethrough: false,
+        }
+    }
+}
+
+// All coordinates are in pixels with the origin in the top left corner of the layout and y pointing down.
+// glyph.x and glyph.y is the pen position on the baseline and rect the part of the line the glyph covers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LayoutGlyph {
+    pub glyph: PositionedGlyph,
+    pub font: usize,
+    pub color: Option<Color>,
+    pub line: usize,
+    pub rect: (f32, f32, f32, f32),
+}
+
+// Underlines and strikethroughs, y is the top of the line
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextDecoration {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub thickness: f32,
+    pub color: Option<Color>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextLine {
+    pub start: usize, // byte range of the text on the line
+    pub end: usize,
+    pub glyphs: (usize, usize), // range in TextLayout::glyphs
+    pub x: f32,
+    pub y: f32,
+    pub baseline: f32,
+    pub width: f32,
+    pub height: f32,
+    pub truncated: bool,
+}
+
+pub struct TextLayout {
+    glyphs: Vec<LayoutGlyph>,
+    lines: Vec<TextLine>,
+    decorations: Vec<TextDecoration>,
+    bounds: (f32, f32, f32, f32),
+}
+
+#[derive(Clone, Copy)]
+struct Item {
+    glyph: PositionedGlyph,
+    style: usize,
+}
+
+struct PendingLine {
+    items: Vec<Item>, // x relative to the start of the line
+    style: usize, // used for the size of empty lines
+    start: usize,
+    end: usize,
+    width: f32,
+    paragraph_end: bool,
+    truncated: bool,
+}
+
+fn is_space(text: &str, cluster: usize) -> bool {
+    text.get(cluster..).and_then(|rest| rest.chars().next()).is_some_and(char::is_whitespace)
+}
+
+fn line_width(items: &[Item]) -> f32 {
+    items.iter().map(|item| item.glyph.x + item.glyph.advance).fold(0.0, f32::max)
+}
+
+// Fills the gaps between the ranges with the first font so every byte of the text has a style
+fn cover(text: &str, ranges: &[StyledRange]) -> Vec<StyledRange> {
+    let mut ranges: Vec<StyledRange> = ranges.iter().filter(|range| range.start < range.end).copied().collect();
+    ranges.sort_by_key(|range| range.start);
+
+    let mut styles = Vec::new();
+    let mut position = 0;
+    for range in ranges {
+        if range.start > position {
+            styles.push(StyledRange::new(position, range.start, 0));
+        }
+        let start = range.start.max(position);
+        if start < range.end {
+            styles.push(StyledRange { start, ..range });
+            position = range.end;
+        }
+    }
+    if position < text.len() || styles.is_empty() {
+        styles.push(StyledRange::new(position, text.len(), 0));
+    }
+    styles
+}
+
+fn shape_paragraph(fonts: &[&Font], text: &str, start: usize, end: usize, styles: &[StyledRange]) -> Vec<Item> {
+    let mut items = Vec::new();
+    let mut x = 0.0;
+    for (i, style) in styles.iter().enumerate() {
+        let (piece_start, piece_end) = (style.start.max(start), style.end.min(end));
+        if piece_start >= piece_end {
+            continue;
+        }
+        let run = fonts[style.font].shape(&text[piece_start..piece_end]);
+        items.extend(run.glyphs.iter().map(|glyph| Item {
+            glyph: PositionedGlyph { x: glyph.x + x, cluster: glyph.cluster + piece_start, ..*glyph },
+            style: i,
+        }));
+        x += run.width;
+    }
+    items
+}
+
+// Greedily fills lines with the glyphs of one paragraph, whitespace is allowed to hang past the max width
+fn break_lines(text: &str, start: usize, end: usize, style: usize, items: Vec<Item>, settings: &TextLayoutSettings, lines: &mut Vec<PendingLine>) {
+    let max_width = match (settings.wrap, settings.max_width) {
+        (TextWrap::None, _) | (_, None) => f32::INFINITY,
+        (_, Some(max_width)) => max_width,
+    };
+
+    if items.is_empty() {
+        lines.push(PendingLine { items: Vec::new(), style, start, end, width: 0.0, paragraph_end: true, truncated: false });
+        return;
+    }
+
+    let mut first = 0;
+    while first < items.len() {
+        let origin = items[first].glyph.x;
+        let mut last = first;
+        let mut last_space = None;
+        while last < items.len() {
+            let glyph = &items[last].glyph;
+            let space = is_space(text, glyph.cluster);
+            if !space && last > first && glyph.x + glyph.advance - origin > max_width {
+                break;
+            }
+            if space {
+                last_space = Some(last);
+            }
+            last += 1;
+        }
+
+        let (mut line_end, mut next) = (last, last);
+        if last < items.len() {
+            match (settings.wrap, last_space) {
+                (TextWrap::Word, Some(space)) if !is_space(text, items[last - 1].glyph.cluster) => {
+                    line_end = space;
+                    next = space + 1;
+                },
+                (TextWrap::Word, Some(_)) => {},
+                _ => {
+                    // Glyphs made from the same characters stay together
+                    while line_end > first + 1 && items[line_end].glyph.cluster == items[line_end - 1].glyph.cluster {
+                        line_end -= 1;
+                    }
+                    next = line_end;
+                },
+            }
+        }
+        while line_end > first && is_space(text, items[line_end - 1].glyph.cluster) {
+            line_end -= 1;
+        }
+        while next < items.len() && is_space(text, items[next].glyph.cluster) {
+            next += 1;
+        }
+
+        let line_items: Vec<Item> = items[first..line_end].iter().map(|item| Item { glyph: PositionedGlyph { x: item.glyph.x - origin, ..item.glyph }, ..*item }).collect();
+        lines.push(PendingLine {
+            width: line_width(&line_items),
+            items: line_items,
+            style: items[first].style,
+            start: if first == 0 { start } else { items[first].glyph.cluster },
+            end: if next < items.len() { items[next].glyph.cluster } else { end },
+            paragraph_end: next >= items.len(),
+            truncated: false,
+        });
+        first = next;
+    }
+}
+
+fn add_ellipsis(fonts: &[&Font], text: &str, line: &mut PendingLine, styles: &[StyledRange], ellipsis: &str, max_width: f32) {
+    let style = line.items.last().map_or(line.style, |item| item.style);
+    let ellipsis = fonts[styles[style].font].shape(ellipsis);
+
+    let mut cut = line.end;
+    while let Some(item) = line.items.last() {
+        if item.glyph.x + item.glyph.advance + ellipsis.width <= max_width && !is_space(text, item.glyph.cluster) {
+            break;
+        }
+        cut = item.glyph.cluster;
+        line.items.pop();
+    }
+
+    let x = line_width(&line.items);
+    line.items.extend(ellipsis.glyphs.iter().map(|glyph| Item { glyph: PositionedGlyph { x: glyph.x + x, cluster: cut, ..*glyph }, style }));
+    line.width = line_width(&line.items);
+    line.truncated = true;
+}
+
+impl TextLayout {
+    pub fn new(font: &Font, text: &str, settings: &TextLayoutSettings) -> TextLayout {
+        TextLayout::styled(&[font], text, &[], settings)
+    }
+
+    // Lays out text where parts of it use different fonts, colors and decorations. Text not covered by any
+    // range uses the first font.
+    pub fn styled(fonts: &[&Font], text: &str, ranges: &[StyledRange], settings: &TextLayoutSettings) -> TextLayout {
+        let styles = cover(text, ranges);
+        if fonts.is_empty() || styles.iter().any(|style| style.font >= fonts.len()) {
+            error_log!("Text layout uses a font that wasn't passed to it");
+            return TextLayout { glyphs: Vec::new(), lines: Vec::new(), decorations: Vec::new(), bounds: (0.0, 0.0, 0.0, 0.0) };
+        }
+
+        let mut pending = Vec::new();
+        let mut start = 0;
+        for paragraph in text.split('\n') {
+            let end = start + paragraph.strip_suffix('\r').unwrap_or(paragraph).len();
+            let style = styles.iter().position(|style| style.end > start).unwrap_or(styles.len() - 1);
+            let items = shape_paragraph(fonts, text, start, end, &styles);
+            break_lines(text, start, end, style, items, settings, &mut pending);
+            start += paragraph.len() + 1;
+        }
+
+        // Lines are as high as the largest font on them
+        let line_metrics = |line: &PendingLine| {
+            let mut used: Vec<usize> = line.items.iter().map(|item| styles[item.style].font).collect();
+            used.push(styles[line.style].font);
+            let font_height = used.iter().map(|&font| fonts[font].metrics().line_height).fold(0.0, f32::max);
+            let ascender = used.iter().map(|&font| fonts[font].metrics().ascender).fold(0.0, f32::max);
+            let height = font_height * settings.line_height;
+            (height, (height - font_height) / 2.0 + ascender)
+        };
+
+        let mut max_lines = settings.max_lines.unwrap_or(usize::MAX).max(1);
+        if let Some(max_height) = settings.max_height {
+            let mut height = 0.0;
+            let fitting = pending.iter().take_while(|line| {
+                height += line_metrics(line).0;
+                height <= max_height
+            }).count();
+            max_lines = max_lines.min(fitting.max(1));
+        }
+        let cut_off = pending.len() > max_lines;
+        pending.truncate(max_lines);
+
+        if let Some(ellipsis) = &settings.ellipsis {
+            let max_width = settings.max_width.unwrap_or(f32::INFINITY);
+            let count = pending.len();
+            for (i, line) in pending.iter_mut().enumerate() {
+                if line.width > max_width || (cut_off && i == count - 1) {
+                    add_ellipsis(fonts, text, line, &styles, ellipsis, max_width);
+                }
+            }
+        }
+
+        let block_width = settings.max_width.unwrap_or_else(|| pending.iter().map(|line| line.width).fold(0.0, f32::max));
+        let content_height: f32 = pending.iter().map(|line| line_metrics(line).0).sum();
+        let top = match (settings.max_height, settings.vertical_align) {
+            (Some(max_height), VerticalAlign::Middle) => (max_height - content_height) / 2.0,
+            (Some(max_height), VerticalAlign::Bottom) => max_height - content_height,
+            _ => 0.0,
+        };
+
+        let mut glyphs = Vec::new();
+        let mut lines = Vec::new();
+        let mut decorations = Vec::new();
+        let mut y = top;
+        for (i, line) in pending.into_iter().enumerate() {
+            let (line_height, baseline) = line_metrics(&line);
+            let spaces = line.items.iter().filter(|item| is_space(text, item.glyph.cluster)).count();
+            let justify = settings.align == HorizontalAlign::Justify && settings.max_width.is_some() && !line.paragraph_end && !line.truncated && spaces > 0;
+
+            let (x, spacing, width) = match settings.align {
+                _ if justify => (0.0, (block_width - line.width) / spaces as f32, block_width),
+                HorizontalAlign::Center => ((block_width - line.width) / 2.0, 0.0, line.width),
+                HorizontalAlign::Right => (block_width - line.width, 0.0, line.width),
+                _ => (0.0, 0.0, line.width),
+            };
+
+            let first = glyphs.len();
+            let mut extra = 0.0;
+            for item in &line.items {
+                let glyph = item.glyph;
+                let style = &styles[item.style];
+                let space = is_space(text, glyph.cluster);
+                let advance = if space { glyph.advance + spacing } else { glyph.advance };
+                let glyph_x = x + glyph.x + extra;
+                glyphs.push(LayoutGlyph {
+                    glyph: PositionedGlyph { x: glyph_x, y: y + baseline - glyph.y, ..glyph },
+                    font: style.font,
+                    color: style.color,
+                    line: i,
+                    rect: (glyph_x, y, advance, line_height),
+                });
+                if space {
+                    extra += spacing;
+                }
+            }
+
+            // Neighbouring glyphs with the same style share one line
+            let mut j = 0;
+            while j < line.items.len() {
+                let style_index = line.items[j].style;
+                let mut k = j;
+                while k + 1 < line.items.len() && line.items[k + 1].style == style_index {
+                    k += 1;
+                }
+
+                let style = &styles[style_index];
+                let (left, right) = (glyphs[first + j].rect, glyphs[first + k].rect);
+                let metrics = fonts[style.font].metrics();
+                let mut decorate = |offset: f32, thickness: f32| decorations.push(TextDecoration {
+                    x: left.0,
+                    y: y + baseline + offset - thickness / 2.0,
+                    width: right.0 + right.2 - left.0,
+                    thickness,
+                    color: style.color,
+                });
+                if style.underline {
+                    decorate(metrics.underline_position, metrics.underline_thickness);
+                }
+                if style.strikethrough {
+                    decorate(-metrics.strikeout_position, metrics.strikeout_thickness);
+                }
+                j = k + 1;
+            }
+
+            lines.push(TextLine {
+                start: line.start,
+                end: line.end,
+                glyphs: (first, glyphs.len()),
+                x,
+                y,
+                baseline: y + baseline,
+                width,
+                height: line_height,
+                truncated: line.truncated,
+            });
+            y += line_height;
+        }
+
+        let left = lines.iter().map(|line| line.x).fold(f32::INFINITY, f32::min);
+        let right = lines.iter().map(|line| line.x + line.width).fold(f32::NEG_INFINITY, f32::max);
+        let bounds = (left, top, right - left, content_height);
+
+        TextLayout { glyphs, lines, decorations, bounds }
+    }
+
+    // Index of the glyph under a point in layout coordinates
+    pub fn glyph_at(&self, x: f32, y: f32) -> Option<usize> {
+        let line = self.lines.iter().find(|line| y >= line.y && y < line.y + line.height)?;
+        (line.glyphs.0..line.glyphs.1).find(|&i| {
+            let (glyph_x, _, width, _) = self.glyphs[i].rect;
+            x >= glyph_x && x < glyph_x + width
+        })
+    }
+
+    pub fn glyphs(&self)        -> &Vec<LayoutGlyph>        { &self.glyphs      }
+    pub fn lines(&self)         -> &Vec<TextLine>           { &self.lines       }
+    pub fn decorations(&self)   -> &Vec<TextDecoration>     { &self.decorations }
+    pub fn bounds(&self)        -> (f32, f32, f32, f32)     { self.bounds       }
+    pub fn width(&self)         -> f32                      { self.bounds.2     }
+    pub fn height(&self)        -> f32                      { self.bounds.3     }
+}
+
+// A glyph of a layout as the graphics front-ends draw it, rect is x, y, width and height in pixels where y is the
+// distance from the top of the layout down to the bottom edge of the glyph. color None uses the graphics color.
+pub(crate) struct GlyphQuad {
+    pub texture: TextureRegion,
+    pub mode: FontRenderMode,
+    pub rect: (f32, f32, f32, f32),
+    pub color: Option<Color>,
+}
+
+// Caches the glyphs of a layout in the fonts it was made with and gives the quads to draw them with. The bool is
+// true when an atlas changed, pending sprites have to be flushed before the quads are drawn.
+pub(crate) fn glyph_quads(layout: &TextLayout, fonts: &[&Font]) -> (bool, Vec<GlyphQuad>) {
+    let mut changed = false;
+    for (i, font) in fonts.iter().enumerate() {
+        changed |= font.cache_glyphs(layout.glyphs.iter().filter(|laid_out| laid_out.font == i).map(|laid_out| &laid_out.glyph));
+    }
+
+    let mut quads = Vec::new();
+    for laid_out in &layout.glyphs {
+        let font = match fonts.get(laid_out.font) {
+            Some(font) => font,
+            None => continue,
+        };
+        let glyph = match font.shaped_glyph(&laid_out.glyph) {
+            Some(glyph) => glyph,
+            None => continue,
+        };
+
+        let bearing = glyph.bearing();
+        let size = glyph.size();
+        if size.0 == 0.0 || size.1 == 0.0 {
+            continue;
+        }
+
+        quads.push(GlyphQuad {
+            texture: glyph.texture(),
+            mode: font.render_mode(),
+            rect: (laid_out.glyph.x + bearing.0, laid_out.glyph.y - bearing.1 + size.1, size.0, size.1),
+            color: laid_out.color,
+        });
+    }
+    (changed, quads)
+}
@@ -20,11 +20,16 @@
  *   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
  *   SOFTWARE.
  */
+use std::io::{Read, Write};
 use std::rc::Rc;
 
-use engine_core::error_log;
-use image::{GenericImageView, save_buffer};
+use engine_core::{error_log, warn_log};
+use image::GenericImageView;
 
+use crate::atlas::{self, AtlasDescriptor, AtlasError, AtlasPage, AtlasRegion};
+use crate::color::ColorMatrix;
+use crate::compressed_texture::CompressedImage;
+use crate::qoi;
 use crate::framebuffer::FrameBuffer;
 
 pub struct Texture {
@@ -77,12 +82,86 @@ impl Texture {
 	}
 
     pub fn from_file(file_path: &str) -> Rc<Texture> {
+		let lower_case_path = file_path.to_lowercase();
+		if lower_case_path.ends_with(".ktx2") || lower_case_path.ends_with(".dds") {
+			match CompressedImage::from_file(file_path) {
+				Ok(compressed) => return Texture::from_compressed(&compressed),
+				Err(e) => {
+					error_log!("Failed to load compressed texture: {}\n{:?}", file_path, e);
+					return Texture::from_color(1, 1, 0xFF_FF_FF_FF);
+				}
+			}
+		}
+
 		let img = Image::from_file(file_path);
 		Texture::new(img.width(), img.height(), &img.get_buffer())
 	}
 
+	// Uninitialized storage for render targets, format and data_type only describe the (missing) upload
+	pub(crate) fn with_storage(width: u32, height: u32, internal_format: u32, format: u32, data_type: u32) -> Rc<Texture> {
+		unsafe {
+			let mut texture_id : u32 = 0;
+
+			gl_call!(gl::GenTextures(1, &mut texture_id));
+			gl_call!(gl::BindTexture(gl::TEXTURE_2D, texture_id));
+
+			gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST 		as i32));
+			gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST 		as i32));
+			gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE 	as i32));
+			gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE 	as i32));
+
+			gl_call!(gl::TexImage2D(gl::TEXTURE_2D, 0, internal_format as i32, width as i32, height as i32, 0, format, data_type, std::ptr::null()));
+
+			gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+
+			Rc::new(Texture { gl_texture_id: texture_id, width, height })
+		}
+	}
+
 	pub fn from_image(image: &Image) -> Rc<Texture> { Texture::new(image.width(), image.height(), &image.get_buffer()) }
 
+	pub fn from_compressed(image: &CompressedImage) -> Rc<Texture> {
+		let format = image.format();
+		let supported = format.is_supported();
+		let native = supported && (image.is_bottom_up() || format.can_flip());
+		if !supported {
+			warn_log!("Compressed format {:?} is not supported by the driver, decoding it on the CPU!", format);
+		}
+		else if !native {
+			warn_log!("Top-down {:?} blocks can't be flipped to the texture orientation, decoding them on the CPU!", format);
+		}
+
+		let levels = image.levels();
+		let min_filter = if levels.len() > 1 { gl::NEAREST_MIPMAP_NEAREST } else { gl::NEAREST };
+
+		unsafe {
+			let mut texture_id : u32 = 0;
+
+			gl_call!(gl::GenTextures(1, &mut texture_id));
+			gl_call!(gl::BindTexture(gl::TEXTURE_2D, texture_id));
+
+			gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min_filter 			as i32));
+			gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST 		as i32));
+			gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE 	as i32));
+			gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE 	as i32));
+			gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, levels.len() as i32 - 1));
+
+			for (i, level) in levels.iter().enumerate() {
+				let data = if native { image.level_data_bottom_up(i) } else { None };
+				if let Some(data) = data {
+					gl_call!(gl::CompressedTexImage2D(gl::TEXTURE_2D, i as i32, format.gl_internal_format(image.is_srgb()), level.width as i32, level.height as i32, 0, data.len() as i32, data.as_ptr() as *const std::ffi::c_void));
+				} else if let Some(decoded) = image.decode(i) {
+					let internal_format = if image.is_srgb() { gl::SRGB8_ALPHA8 } else { gl::RGBA8 };
+					gl_call!(gl::TexImage2D(gl::TEXTURE_2D, i as i32, internal_format as i32, level.width as i32, level.height as i32, 0, gl::RGBA, gl::UNSIGNED_BYTE, decoded.get_buffer().as_ptr() as *const std::ffi::c_void));
+				}
+			}
+
+			gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+
+			Rc::new(Texture { gl_texture_id: texture_id, width: image.width(), height: image.height() })
+		}
+	}
+
 	pub fn set_pixels_u32(&self, x: u32, y: u32, width: u32, height: u32, pixels: &Vec::<u8>) {
 		unsafe {
 			gl_call!(gl::TexSubImage2D(gl::TEXTURE_2D, 0, x as i32, y as i32, width as i32, height as i32, gl::RGBA, gl::UNSIGNED_BYTE, pixels.as_ptr() as *const std::ffi::c_void));
@@ -99,11 +178,24 @@ impl Texture {
 		}
 	}
 
+	// Reads the whole level 0 image back and copies out the requested rect, pixels outside the texture are transparent
 	pub fn get_pixels_u32(&self, x: u32, y: u32, width: u32, height: u32) -> Vec<u8> {
-		let mut data = vec![0; width as usize * height as usize * 4];
+		let texture_width = self.width as usize;
+		let texture_height = self.height as usize;
+		let mut pixels = vec![0u8; texture_width * texture_height * 4];
 		unsafe {
-			self.bind(0);
-			gl_call!(gl::GetBufferSubData(gl::TEXTURE_BUFFER, x as isize + y as isize * width as isize, width as isize * height as isize, data.as_mut_ptr() as *mut std::ffi::c_void));
+			gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.gl_texture_id));
+			gl_call!(gl::PixelStorei(gl::PACK_ALIGNMENT, 1));
+			gl_call!(gl::GetTexImage(gl::TEXTURE_2D, 0, gl::RGBA, gl::UNSIGNED_BYTE, pixels.as_mut_ptr() as *mut std::ffi::c_void));
+			gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+		}
+
+		let mut data = vec![0u8; width as usize * height as usize * 4];
+		let copy_width = texture_width.saturating_sub(x as usize).min(width as usize);
+		for row in 0..(height as usize).min(texture_height.saturating_sub(y as usize)) {
+			let src = ((y as usize + row) * texture_width + x as usize) * 4;
+			let dst = row * width as usize * 4;
+			data[dst..dst + copy_width * 4].copy_from_slice(&pixels[src..src + copy_width * 4]);
 		}
 		data
 	}
@@ -116,6 +208,17 @@ impl Texture {
 		self.get_pixels_u32(x, y, width, height)
 	}
     
+	// Nearest filtering is used by default
+	pub fn set_linear_filter(&self, linear: bool) {
+		let filter = if linear { gl::LINEAR } else { gl::NEAREST };
+		unsafe {
+			gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.gl_texture_id));
+			gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter as i32));
+			gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter as i32));
+			gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+		}
+	}
+
     pub fn bind(&self, slot: u32) {
         unsafe {
 		    gl_call!(gl::ActiveTexture(gl::TEXTURE0 + slot));
@@ -133,6 +236,8 @@ impl Texture {
     
     pub fn width(&self) 	-> u32 { self.width 	}
     pub fn height(&self) 	-> u32 { self.height 	}
+
+	pub(crate) fn gl_id(&self) -> u32 { self.gl_texture_id }
 }
 
 impl Drop for Texture {
@@ -215,7 +320,7 @@ impl TextureRegion {
 		let strong = self.texture.upgrade();
 		match strong {
 			Some(texture) => {
-				return Some(texture.get_pixels_u32(self.x+x, self.y+y, self.x+width, self.y+height))
+				return Some(texture.get_pixels_u32(self.x+x, self.y+y, width, height))
 			},
 			None => {
 				error_log!("Tried to get pixels of None texture!");
@@ -342,6 +447,7 @@ impl TextureRegion {
 	}
 }
 
+#[derive(Clone)]
 pub struct Image {
 	width: u32,
 	height: u32,
@@ -358,23 +464,39 @@ impl Image {
 	}
 
 	pub fn from_file(file_path: &str) -> Image {
-		let img = image::open(file_path);
-		match img {
-			Ok(img) => {
-				let width 	= img.width();
-				let height 	= img.height();
-		
-				let img = img.flipv();
-				let img = img.into_rgba();
-				return Image::new(width, height, img.to_vec())
-			},
+		match std::fs::read(file_path).map_err(ImageError::from).and_then(|bytes| Image::decode(&bytes)) {
+			Ok(image) => image,
 			Err(e) => {
-				error_log!("Failed to load image: {}\n{}", file_path, e);
-				return Image::from_color(1, 1, 0xFF_FF_FF_FF);
+				error_log!("Failed to load image: {}\n{:?}", file_path, e);
+				Image::from_color(1, 1, 0xFF_FF_FF_FF)
 			}
 		}
 	}
 
+	pub fn from_reader<R: Read>(reader: &mut R) -> Result<Image, ImageError> {
+		let mut bytes = Vec::new();
+		reader.read_to_end(&mut bytes)?;
+		Image::decode(&bytes)
+	}
+
+	// The format is sniffed from the data, TGA has no signature so it is tried last.
+	pub fn decode(bytes: &[u8]) -> Result<Image, ImageError> {
+		if bytes.starts_with(&qoi::QOI_MAGIC) {
+			let (width, height, pixels) = qoi::decode(bytes)?;
+			return Ok(Image::new(width, height, pixels).flip_vertically());
+		}
+
+		let img = match image::guess_format(bytes) {
+			Ok(format) => image::load_from_memory_with_format(bytes, format)?,
+			Err(_) => image::load_from_memory_with_format(bytes, image::ImageFormat::Tga).map_err(|_| ImageError::UnknownFormat)?,
+		};
+
+		let width 	= img.width();
+		let height 	= img.height();
+		let img = img.flipv().into_rgba8();
+		Ok(Image::new(width, height, img.into_raw()))
+	}
+
 	pub fn from_color(width: u32, height: u32, color: u32) -> Image {
 		let mut pixels = vec!(0u8; (width * height * 4) as usize);
 		for i in 0..(pixels.len() / 4) {
@@ -387,7 +509,7 @@ impl Image {
 	}
 
 	pub fn from_framebuffer(fb: &FrameBuffer) -> Image {
-		Image::new(fb.texture().width, fb.texture().height, fb.get_pixels(0, 0, fb.texture().width, fb.texture().height))
+		Image::new(fb.width(), fb.height(), fb.get_pixels(0, 0, fb.width(), fb.height()))
 	}
 
 	pub fn from_texture(texture: &Rc<Texture>) -> Image {
@@ -398,10 +520,42 @@ impl Image {
 		Image::new(texture.width, texture.height, texture.get_pixels_u32(0, 0, texture.width, texture.height).unwrap())
 	}
 
-	pub fn to_file(&self, path: &str) {
-		let img = self.flip_horizontally();
-		let buff = img.buffer;
-		save_buffer(path, &buff, self.width, self.height, image::ColorType::Rgba8).unwrap();
+	pub fn to_file(&self, path: &str) -> Result<(), ImageError> {
+		let format = ImageFormat::from_path(path).ok_or(ImageError::UnknownFormat)?;
+		let mut file = std::fs::File::create(path)?;
+		self.to_writer(&mut file, format)
+	}
+
+	pub fn to_writer<W: Write>(&self, writer: &mut W, format: ImageFormat) -> Result<(), ImageError> {
+		writer.write_all(&self.encode(format)?)?;
+		Ok(())
+	}
+
+	pub fn encode(&self, format: ImageFormat) -> Result<Vec<u8>, ImageError> {
+		let pixels = self.flip_vertically().buffer;
+		let mut bytes = Vec::new();
+
+		match format {
+			ImageFormat::Png => {
+				image::codecs::png::PngEncoder::new(&mut bytes).encode(&pixels, self.width, self.height, image::ColorType::Rgba8)?;
+			},
+			ImageFormat::Jpeg(quality) => {
+				// JPEG has no alpha channel
+				let rgb: Vec<u8> = pixels.chunks(4).flat_map(|px| px[..3].to_vec()).collect();
+				image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, quality.clamp(1, 100)).encode(&rgb, self.width, self.height, image::ColorType::Rgb8)?;
+			},
+			ImageFormat::Bmp => {
+				image::codecs::bmp::BmpEncoder::new(&mut bytes).encode(&pixels, self.width, self.height, image::ColorType::Rgba8)?;
+			},
+			ImageFormat::Tga => {
+				image::codecs::tga::TgaEncoder::new(&mut bytes).encode(&pixels, self.width, self.height, image::ColorType::Rgba8)?;
+			},
+			ImageFormat::Qoi => {
+				bytes = qoi::encode(self.width, self.height, &pixels);
+			},
+		}
+
+		Ok(bytes)
 	}
 
 	pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Image {
@@ -429,7 +583,9 @@ impl Image {
 		}
 
 		let mut resized_image = Image::from_color(width, height, 0x00_00_00_00);
-		resized_image.draw(0, 0, self.crop(0, 0, crop_width, crop_height));
+		let mut options = CompositeOptions::new();
+		options.blend_mode = ImageBlendMode::Replace;
+		resized_image.composite(self, (0, 0, crop_width, crop_height), 0, 0, &options);
 
 		self.width  = resized_image.width;
 		self.height = resized_image.height;
@@ -437,13 +593,111 @@ impl Image {
 	}
 
 	pub fn draw(&mut self, x: u32, y: u32, image: Image) {
-		for i in 0..image.width {
-			for j in 0..image.height {
-				//self.set_rgba8(x+i, y+j, image.get_rgba8(i, j))
-				self.buffer[(((x+i) + (y+j) * self.width) * 4 + 0) as usize] = image.buffer[((i + j * image.width) * 4 + 0) as usize];
-				self.buffer[(((x+i) + (y+j) * self.width) * 4 + 1) as usize] = image.buffer[((i + j * image.width) * 4 + 1) as usize];
-				self.buffer[(((x+i) + (y+j) * self.width) * 4 + 2) as usize] = image.buffer[((i + j * image.width) * 4 + 2) as usize];
-				self.buffer[(((x+i) + (y+j) * self.width) * 4 + 3) as usize] = image.buffer[((i + j * image.width) * 4 + 3) as usize];
+		self.composite(&image, (0, 0, image.width, image.height), x as i32, y as i32, &CompositeOptions::new());
+	}
+
+	// Draws the src_rect part of image with its lower left corner at (x, y), anything outside of
+	// this image or the clip rectangle is skipped.
+	pub fn composite(&mut self, image: &Image, src_rect: (u32, u32, u32, u32), x: i32, y: i32, options: &CompositeOptions) {
+		let (src_x, src_y, src_width, src_height) = src_rect;
+		let src_width = src_width.min(image.width.saturating_sub(src_x)) as i32;
+		let src_height = src_height.min(image.height.saturating_sub(src_y)) as i32;
+
+		let (clip_x, clip_y, clip_width, clip_height) = match options.clip {
+			Some(clip) => clip,
+			None => (0, 0, self.width, self.height),
+		};
+		let min_x = x.max(clip_x).max(0);
+		let min_y = y.max(clip_y).max(0);
+		let max_x = (x + src_width).min(clip_x + clip_width as i32).min(self.width as i32);
+		let max_y = (y + src_height).min(clip_y + clip_height as i32).min(self.height as i32);
+
+		let opacity = options.opacity.clamp(0.0, 1.0);
+
+		for dy in min_y..max_y {
+			for dx in min_x..max_x {
+				let sx = (dx - x) as u32 + src_x;
+				let sy = (dy - y) as u32 + src_y;
+				let src = ((sx + sy * image.width) * 4) as usize;
+				let dst = ((dx as u32 + dy as u32 * self.width) * 4) as usize;
+
+				let mut s = [0.0f32; 4];
+				let mut d = [0.0f32; 4];
+				for c in 0..4 {
+					s[c] = image.buffer[src + c] as f32 / 255.0;
+					d[c] = self.buffer[dst + c] as f32 / 255.0;
+				}
+
+				if !options.premultiplied {
+					for c in 0..3 {
+						s[c] *= s[3];
+						d[c] *= d[3];
+					}
+				}
+				for channel in s.iter_mut() {
+					*channel *= opacity;
+				}
+
+				let out = options.blend_mode.blend(s, d);
+
+				for c in 0..3 {
+					let value = if options.premultiplied {
+						out[c]
+					} else if out[3] > 0.0 {
+						out[c] / out[3]
+					} else {
+						0.0
+					};
+					self.buffer[dst + c] = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+				}
+				self.buffer[dst + 3] = (out[3].clamp(0.0, 1.0) * 255.0).round() as u8;
+			}
+		}
+	}
+
+	// Crops away fully transparent borders, returns the cropped image and the amount removed from the left and bottom
+	pub fn trim_transparent(&self) -> (Image, u32, u32) {
+		let mut min_x = self.width;
+		let mut min_y = self.height;
+		let mut max_x = 0;
+		let mut max_y = 0;
+
+		for y in 0..self.height {
+			for x in 0..self.width {
+				if self.buffer[((x + y * self.width) * 4 + 3) as usize] != 0 {
+					min_x = min_x.min(x);
+					min_y = min_y.min(y);
+					max_x = max_x.max(x + 1);
+					max_y = max_y.max(y + 1);
+				}
+			}
+		}
+
+		if min_x >= max_x {
+			return (Image::new(0, 0, std::vec::Vec::new()), 0, 0);
+		}
+		(self.crop(min_x, min_y, max_x - min_x, max_y - min_y), min_x, min_y)
+	}
+
+	// Repeats the outermost pixels of the rect amount pixels outwards
+	pub fn extrude_edges(&mut self, x: u32, y: u32, width: u32, height: u32, amount: u32) {
+		if amount == 0 || width == 0 || height == 0 {
+			return;
+		}
+
+		let min_x = x.saturating_sub(amount);
+		let min_y = y.saturating_sub(amount);
+		let max_x = (x + width + amount).min(self.width);
+		let max_y = (y + height + amount).min(self.height);
+
+		for py in min_y..max_y {
+			for px in min_x..max_x {
+				let sx = px.max(x).min(x + width - 1);
+				let sy = py.max(y).min(y + height - 1);
+				if sx != px || sy != py {
+					let color = self.get_rgba8(sx, sy);
+					self.set_rgba8(px, py, color);
+				}
 			}
 		}
 	}
@@ -451,6 +705,18 @@ impl Image {
 	pub fn flip_horizontally(&self) -> Image {
 		let mut image = Image::from_color(self.width, self.height, 0x00_00_00_FF);
 
+		for i in 0..self.height {
+			for j in 0..self.width {
+				image.set_rgba8(j, i, self.get_rgba8(self.width-j-1, i));
+			}
+		}
+
+		image
+	}
+
+	pub fn flip_vertically(&self) -> Image {
+		let mut image = Image::from_color(self.width, self.height, 0x00_00_00_FF);
+
 		for i in 0..self.height {
 			for j in 0..self.width {
 				image.set_rgba8(j, i, self.get_rgba8(j, self.height-i-1));
@@ -460,6 +726,155 @@ impl Image {
 		image
 	}
 
+	// Rotations are clockwise as the image is displayed, with the first row being the bottom of the image.
+	pub fn rotate_90(&self) -> Image {
+		let mut image = Image::from_color(self.height, self.width, 0x00_00_00_00);
+
+		for i in 0..self.height {
+			for j in 0..self.width {
+				image.set_rgba8(i, self.width-j-1, self.get_rgba8(j, i));
+			}
+		}
+
+		image
+	}
+
+	pub fn rotate_180(&self) -> Image {
+		let mut image = Image::from_color(self.width, self.height, 0x00_00_00_00);
+
+		for i in 0..self.height {
+			for j in 0..self.width {
+				image.set_rgba8(self.width-j-1, self.height-i-1, self.get_rgba8(j, i));
+			}
+		}
+
+		image
+	}
+
+	pub fn rotate_270(&self) -> Image {
+		let mut image = Image::from_color(self.height, self.width, 0x00_00_00_00);
+
+		for i in 0..self.height {
+			for j in 0..self.width {
+				image.set_rgba8(self.height-i-1, j, self.get_rgba8(j, i));
+			}
+		}
+
+		image
+	}
+
+	pub fn premultiply_alpha(&mut self) {
+		for pixel in self.buffer.chunks_mut(4) {
+			let a = pixel[3] as u32;
+			for channel in pixel.iter_mut().take(3) {
+				*channel = ((*channel as u32 * a + 127) / 255) as u8;
+			}
+		}
+	}
+
+	pub fn unpremultiply_alpha(&mut self) {
+		for pixel in self.buffer.chunks_mut(4) {
+			let a = pixel[3] as u32;
+			if a == 0 {
+				continue;
+			}
+			for channel in pixel.iter_mut().take(3) {
+				*channel = ((*channel as u32 * 255 + a / 2) / a).min(255) as u8;
+			}
+		}
+	}
+
+	pub fn apply_color_matrix(&mut self, matrix: &ColorMatrix) {
+		for pixel in self.buffer.chunks_mut(4) {
+			let rgba = (pixel[0] as f32 / 255.0, pixel[1] as f32 / 255.0, pixel[2] as f32 / 255.0, pixel[3] as f32 / 255.0);
+			let (r, g, b, a) = matrix.transform(rgba);
+			pixel[0] = (r.clamp(0.0, 1.0) * 255.0).round() as u8;
+			pixel[1] = (g.clamp(0.0, 1.0) * 255.0).round() as u8;
+			pixel[2] = (b.clamp(0.0, 1.0) * 255.0).round() as u8;
+			pixel[3] = (a.clamp(0.0, 1.0) * 255.0).round() as u8;
+		}
+	}
+
+	pub fn resample(&self, width: u32, height: u32, filter: ResampleFilter) -> Image {
+		if width == 0 || height == 0 || self.width == 0 || self.height == 0 {
+			return Image::new(width, height, vec![0; (width * height * 4) as usize]);
+		}
+
+		if let ResampleFilter::Nearest = filter {
+			let mut image = Image::from_color(width, height, 0x00_00_00_00);
+			for j in 0..height {
+				for i in 0..width {
+					let x = (((i as f32 + 0.5) * self.width as f32 / width as f32) as u32).min(self.width - 1);
+					let y = (((j as f32 + 0.5) * self.height as f32 / height as f32) as u32).min(self.height - 1);
+					image.set_rgba8(i, j, self.get_rgba8(x, y));
+				}
+			}
+			return image;
+		}
+
+		let pixels = self.to_premultiplied_f32();
+		let horizontal = resample_weights(self.width, width, filter);
+		let vertical = resample_weights(self.height, height, filter);
+
+		let pixels = convolve_rows(&pixels, self.width, self.height, width, &horizontal);
+		let pixels = convolve_columns(&pixels, width, height, &vertical);
+
+		Image::from_premultiplied_f32(width, height, &pixels)
+	}
+
+	pub fn blur(&self, sigma: f32) -> Image {
+		if sigma <= 0.0 {
+			return self.crop(0, 0, self.width, self.height);
+		}
+
+		let radius = (sigma * 3.0).ceil() as i32;
+		let mut kernel = Vec::with_capacity((radius * 2 + 1) as usize);
+		for i in -radius..=radius {
+			kernel.push((-(i * i) as f32 / (2.0 * sigma * sigma)).exp());
+		}
+		let sum: f32 = kernel.iter().sum();
+
+		let weights = |size: u32| -> Vec<Vec<(u32, f32)>> {
+			(0..size as i32).map(|i| {
+				(-radius..=radius).map(|k| {
+					let j = (i + k).max(0).min(size as i32 - 1) as u32;
+					(j, kernel[(k + radius) as usize] / sum)
+				}).collect()
+			}).collect()
+		};
+
+		let pixels = self.to_premultiplied_f32();
+		let pixels = convolve_rows(&pixels, self.width, self.height, self.width, &weights(self.width));
+		let pixels = convolve_columns(&pixels, self.width, self.height, &weights(self.height));
+
+		Image::from_premultiplied_f32(self.width, self.height, &pixels)
+	}
+
+	fn to_premultiplied_f32(&self) -> Vec<f32> {
+		let mut pixels = Vec::with_capacity(self.buffer.len());
+		for pixel in self.buffer.chunks(4) {
+			let a = pixel[3] as f32 / 255.0;
+			pixels.push(pixel[0] as f32 / 255.0 * a);
+			pixels.push(pixel[1] as f32 / 255.0 * a);
+			pixels.push(pixel[2] as f32 / 255.0 * a);
+			pixels.push(a);
+		}
+		pixels
+	}
+
+	fn from_premultiplied_f32(width: u32, height: u32, pixels: &[f32]) -> Image {
+		let mut buffer = Vec::with_capacity(pixels.len());
+		for pixel in pixels.chunks(4) {
+			let a = pixel[3].clamp(0.0, 1.0);
+			for channel in pixel.iter().take(3) {
+				let value = if a > 0.0 { channel / a } else { 0.0 };
+				buffer.push((value.clamp(0.0, 1.0) * 255.0).round() as u8);
+			}
+			buffer.push((a * 255.0).round() as u8);
+		}
+		Image::new(width, height, buffer)
+	}
+
 	pub fn get_rgba8(&self, x: u32, y: u32) -> u32 {
 		let mut pixel = 0;
 		pixel += (self.buffer[((x+y*self.width) * 4 + 0) as usize] as u32) << 24;
@@ -492,262 +907,786 @@ impl Image {
 	pub fn height(&self) -> u32 { self.height }
 }
 
-type ImagePackNodeID = u32;
+#[derive(Debug)]
+pub enum ImageError {
+	Io(std::io::Error),
+	Codec(image::ImageError),
+	UnknownFormat,
+	InvalidData(&'static str),
+}
 
-pub struct ImagePackNode {
-	x: u32,
-	y: u32,
-	width: u32,
-	height: u32,
-	image_width: u32,
-	image_height: u32,
-	available: bool,
-	child_1: std::option::Option::<ImagePackNodeID>,
-	child_2: std::option::Option::<ImagePackNodeID>,
+impl From<std::io::Error> for ImageError {
+	fn from(error: std::io::Error) -> Self { Self::Io(error) }
 }
 
-impl ImagePackNode {
-	fn new(x: u32, y: u32, width: u32, height: u32) -> ImagePackNode {
-		ImagePackNode {
-			x,
-			y,
-			width,
-			height,
-			image_width: width,
-			image_height: height,
-			available: true,
-			child_1: None,
-			child_2: None,
+impl From<image::ImageError> for ImageError {
+	fn from(error: image::ImageError) -> Self { Self::Codec(error) }
+}
+
+impl std::fmt::Display for ImageError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Io(error) => write!(f, "io error: {}", error),
+			Self::Codec(error) => write!(f, "codec error: {}", error),
+			Self::UnknownFormat => write!(f, "unknown image format"),
+			Self::InvalidData(reason) => write!(f, "invalid image data: {}", reason),
 		}
 	}
+}
 
-	fn has_space(&self, width: u32, height: u32) -> bool {
-		self.width >= width && self.height >= height && self.available
+impl std::error::Error for ImageError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Io(error) => Some(error),
+			Self::Codec(error) => Some(error),
+			_ => None,
+		}
 	}
 }
 
-pub struct ImagePack {
-	bitmap: Image,
-	pub nodes: std::vec::Vec::<ImagePackNode>,
-	head: ImagePackNodeID,
-	locations: std::collections::HashMap::<String, u32>,
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ImageFormat {
+	Png,
+	Jpeg(u8), // quality 1-100
+	Bmp,
+	Tga,
+	Qoi,
 }
 
-impl ImagePack {
-	pub fn new() -> ImagePack {
-		ImagePack {
-			bitmap: Image::from_color(1, 1, 0x00_00_00_00),
-			nodes: std::vec::Vec::new(),
-			head: 0,
-			locations: std::collections::HashMap::new(),
+impl ImageFormat {
+	pub fn from_path(path: &str) -> Option<ImageFormat> {
+		let extension = std::path::Path::new(path).extension()?.to_str()?.to_lowercase();
+		match extension.as_str() {
+			"png" => Some(ImageFormat::Png),
+			"jpg" | "jpeg" => Some(ImageFormat::Jpeg(90)),
+			"bmp" => Some(ImageFormat::Bmp),
+			"tga" => Some(ImageFormat::Tga),
+			"qoi" => Some(ImageFormat::Qoi),
+			_ => None,
 		}
 	}
+}
 
-	fn split_horz(&mut self, node_id: ImagePackNodeID) {
-		let node = &mut self.nodes[node_id as usize];
+#[derive(Copy, Clone, PartialEq)]
+pub enum ImageBlendMode {
+	Normal,
+	Additive,
+	Multiply,
+	Screen,
+	Replace,
+}
 
-		let up = ImagePackNode::new(
-			node.x,
-			node.y+node.height/2,
-			node.width,
-			node.height/2,
-		);
+impl ImageBlendMode {
+	// Both colours are premultiplied by their alpha.
+	fn blend(&self, s: [f32; 4], d: [f32; 4]) -> [f32; 4] {
+		let mut out = [0.0; 4];
+		match self {
+			ImageBlendMode::Normal => {
+				for c in 0..4 {
+					out[c] = s[c] + d[c] * (1.0 - s[3]);
+				}
+			},
+			ImageBlendMode::Additive => {
+				for c in 0..4 {
+					out[c] = (s[c] + d[c]).min(1.0);
+				}
+			},
+			ImageBlendMode::Multiply => {
+				for c in 0..3 {
+					out[c] = s[c] * (1.0 - d[3]) + d[c] * (1.0 - s[3]) + s[c] * d[c];
+				}
+				out[3] = s[3] + d[3] - s[3] * d[3];
+			},
+			ImageBlendMode::Screen => {
+				for c in 0..4 {
+					out[c] = s[c] + d[c] - s[c] * d[c];
+				}
+			},
+			ImageBlendMode::Replace => {
+				out = s;
+			},
+		}
+		out
+	}
+}
 
-		let down = ImagePackNode::new(
-			node.x,
-			node.y,
-			node.width,
-			node.height/2,
-		);
+#[derive(Copy, Clone)]
+pub struct CompositeOptions {
+	pub blend_mode: ImageBlendMode,
+	pub opacity: f32,
+	pub clip: Option<(i32, i32, u32, u32)>,
+	pub premultiplied: bool,
+}
 
-		node.available = false;
-		self.nodes.push(up);
-		self.nodes.push(down);
+impl CompositeOptions {
+	pub fn new() -> CompositeOptions {
+		CompositeOptions {
+			blend_mode: ImageBlendMode::Normal,
+			opacity: 1.0,
+			clip: None,
+			premultiplied: false,
+		}
 	}
+}
 
-	fn split_vert(&mut self, node_id: ImagePackNodeID) {
-		let node = &mut self.nodes[node_id as usize];
+impl Default for CompositeOptions {
+	fn default() -> Self { CompositeOptions::new() }
+}
 
-		let right = ImagePackNode::new(
-			node.x+node.width/2,
-			node.y,
-			node.width/2,
-			node.height,
-		);
+#[derive(Copy, Clone, PartialEq)]
+pub enum ResampleFilter {
+	Nearest,
+	Bilinear,
+	Bicubic,
+	Lanczos3,
+}
 
-		let left = ImagePackNode::new(
-			node.x,
-			node.y,
-			node.width/2,
-			node.height,
-		);
+impl ResampleFilter {
+	fn support(&self) -> f32 {
+		match self {
+			ResampleFilter::Nearest 	=> 0.5,
+			ResampleFilter::Bilinear 	=> 1.0,
+			ResampleFilter::Bicubic 	=> 2.0,
+			ResampleFilter::Lanczos3 	=> 3.0,
+		}
+	}
 
-		node.available = false;
-		self.nodes.push(right);
-		self.nodes.push(left);
+	fn kernel(&self, x: f32) -> f32 {
+		let x = x.abs();
+		match self {
+			ResampleFilter::Nearest => if x <= 0.5 { 1.0 } else { 0.0 },
+			ResampleFilter::Bilinear => (1.0 - x).max(0.0),
+			ResampleFilter::Bicubic => {
+				// Catmull-Rom
+				if x < 1.0 {
+					1.5 * x * x * x - 2.5 * x * x + 1.0
+				} else if x < 2.0 {
+					-0.5 * x * x * x + 2.5 * x * x - 4.0 * x + 2.0
+				} else {
+					0.0
+				}
+			},
+			ResampleFilter::Lanczos3 => {
+				if x < 3.0 { sinc(x) * sinc(x / 3.0) } else { 0.0 }
+			},
+		}
 	}
+}
 
-	fn double_horz(&mut self) {
-		let down = &self.nodes[self.head as usize];
-		let up = ImagePackNode::new(0, down.height, down.width, down.height);
+fn sinc(x: f32) -> f32 {
+	if x == 0.0 {
+		return 1.0;
+	}
+	let x = x * std::f32::consts::PI;
+	x.sin() / x
+}
 
-		let mut new_head = ImagePackNode::new(0, 0, down.width, down.height * 2);
-		new_head.available = false;
+fn resample_weights(src_size: u32, dst_size: u32, filter: ResampleFilter) -> Vec<Vec<(u32, f32)>> {
+	let scale = src_size as f32 / dst_size as f32;
+	let filter_scale = scale.max(1.0);
+	let support = filter.support() * filter_scale;
+
+	let mut weights = Vec::with_capacity(dst_size as usize);
+	for i in 0..dst_size {
+		let center = (i as f32 + 0.5) * scale - 0.5;
+		let mut contributions = Vec::new();
+		let mut sum = 0.0;
+
+		for j in (center - support).ceil() as i32..=(center + support).floor() as i32 {
+			let weight = filter.kernel((j as f32 - center) / filter_scale);
+			if weight == 0.0 {
+				continue;
+			}
+			let j = j.max(0).min(src_size as i32 - 1) as u32;
+			contributions.push((j, weight));
+			sum += weight;
+		}
 
-		new_head.child_1 = Some(self.head);
-		new_head.child_2 = Some(self.nodes.len() as u32 + 1);
+		if sum != 0.0 {
+			for contribution in &mut contributions {
+				contribution.1 /= sum;
+			}
+		} else {
+			contributions.push(((center.round().max(0.0) as u32).min(src_size - 1), 1.0));
+		}
 
-		self.head = self.nodes.len() as u32;
+		weights.push(contributions);
+	}
+	weights
+}
 
-		self.bitmap.resize(new_head.width, new_head.height);
+fn convolve_rows(pixels: &[f32], width: u32, height: u32, new_width: u32, weights: &[Vec<(u32, f32)>]) -> Vec<f32> {
+	let mut result = vec![0.0; (new_width * height * 4) as usize];
+	for y in 0..height {
+		for x in 0..new_width {
+			let dst = ((x + y * new_width) * 4) as usize;
+			for &(j, weight) in &weights[x as usize] {
+				let src = ((j + y * width) * 4) as usize;
+				for c in 0..4 {
+					result[dst + c] += pixels[src + c] * weight;
+				}
+			}
+		}
+	}
+	result
+}
 
-		self.nodes.push(new_head);
-		self.nodes.push(up);
+fn convolve_columns(pixels: &[f32], width: u32, new_height: u32, weights: &[Vec<(u32, f32)>]) -> Vec<f32> {
+	let mut result = vec![0.0; (width * new_height * 4) as usize];
+	for y in 0..new_height {
+		for &(j, weight) in &weights[y as usize] {
+			for x in 0..width {
+				let dst = ((x + y * width) * 4) as usize;
+				let src = ((x + j * width) * 4) as usize;
+				for c in 0..4 {
+					result[dst + c] += pixels[src + c] * weight;
+				}
+			}
+		}
+	}
+	result
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PackRect {
+	pub x: u32,
+	pub y: u32,
+	pub width: u32,
+	pub height: u32,
+}
 
+impl PackRect {
+	pub fn new(x: u32, y: u32, width: u32, height: u32) -> PackRect {
+		PackRect { x, y, width, height }
 	}
 
-	fn double_vert(&mut self) {
-		let left = &self.nodes[self.head as usize];
-		let right = ImagePackNode::new(left.width, 0, left.width, left.height);
+	pub fn contains(&self, other: &PackRect) -> bool {
+		other.x >= self.x && other.y >= self.y && other.x + other.width <= self.x + self.width && other.y + other.height <= self.y + self.height
+	}
+
+	pub fn intersects(&self, other: &PackRect) -> bool {
+		self.x < other.x + other.width && other.x < self.x + self.width && self.y < other.y + other.height && other.y < self.y + self.height
+	}
+}
+
+#[derive(Copy, Clone)]
+pub struct ImagePackSettings {
+	pub max_width: u32,
+	pub max_height: u32,
+	pub padding: u32,	// Empty pixels between two images
+	pub extrude: u32,	// Edge pixels repeated around every image to stop bleeding when filtering
+	pub allow_rotation: bool,
+	pub trim: bool,		// Remove fully transparent borders, the removed amount is stored as an offset
+}
+
+impl ImagePackSettings {
+	pub fn new() -> ImagePackSettings {
+		ImagePackSettings {
+			max_width: 4096,
+			max_height: 4096,
+			padding: 0,
+			extrude: 0,
+			allow_rotation: false,
+			trim: false,
+		}
+	}
+}
+
+impl Default for ImagePackSettings {
+	fn default() -> Self { ImagePackSettings::new() }
+}
+
+// Where an image ended up inside an ImagePack. Rotated images are stored rotated 90° clockwise
+// so width and height are the stored size, offset is the trimmed amount from the lower left corner.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PackedImage {
+	pub page: usize,
+	pub x: u32,
+	pub y: u32,
+	pub width: u32,
+	pub height: u32,
+	pub rotated: bool,
+	pub offset_x: u32,
+	pub offset_y: u32,
+	pub source_width: u32,
+	pub source_height: u32,
+}
+
+// A single MaxRects bin, free_rects holds every maximal free rectangle.
+struct ImagePackPage {
+	bitmap: Image,
+	free_rects: std::vec::Vec::<PackRect>,
+	used_area: u64,
+}
+
+impl ImagePackPage {
+	fn new(width: u32, height: u32) -> ImagePackPage {
+		ImagePackPage {
+			bitmap: Image::from_color(width, height, 0x00_00_00_00),
+			free_rects: vec![PackRect::new(0, 0, width, height)],
+			used_area: 0,
+		}
+	}
+
+	// Best short side fit, returns the rect, if it is rotated and its score (lower is better)
+	fn find_position(&self, width: u32, height: u32, allow_rotation: bool) -> Option<(PackRect, bool, (u32, u32))> {
+		let mut best: Option<(PackRect, bool, (u32, u32))> = None;
 
-		let mut new_head = ImagePackNode::new(0, 0, left.width * 2, left.height);
-		new_head.available = false;
+		for free in &self.free_rects {
+			let mut candidates = vec![(width, height, false)];
+			if allow_rotation && width != height {
+				candidates.push((height, width, true));
+			}
 
-		new_head.child_1 = Some(self.head);
-		new_head.child_2 = Some(self.nodes.len() as u32 + 1);
+			for (w, h, rotated) in candidates {
+				if free.width < w || free.height < h {
+					continue;
+				}
 
-		self.head = self.nodes.len() as u32;
+				let leftover_x = free.width - w;
+				let leftover_y = free.height - h;
+				let score = (leftover_x.min(leftover_y), leftover_x.max(leftover_y));
 
-		self.bitmap.resize(new_head.width, new_head.height);
+				if best.is_none_or(|(_, _, best_score)| score < best_score) {
+					best = Some((PackRect::new(free.x, free.y, w, h), rotated, score));
+				}
+			}
+		}
 
-		self.nodes.push(new_head);
-		self.nodes.push(right);
+		best
 	}
 
-	fn has_space(&mut self, width: u32, height: u32) -> bool {
-		for node in &mut self.nodes {
-			if node.available && node.has_space(width, height) {
+	fn place(&mut self, used: PackRect) {
+		let mut split = std::vec::Vec::new();
+
+		self.free_rects.retain(|free| {
+			if !free.intersects(&used) {
 				return true;
 			}
+
+			if used.x > free.x {
+				split.push(PackRect::new(free.x, free.y, used.x - free.x, free.height));
+			}
+			if used.x + used.width < free.x + free.width {
+				split.push(PackRect::new(used.x + used.width, free.y, free.x + free.width - used.x - used.width, free.height));
+			}
+			if used.y > free.y {
+				split.push(PackRect::new(free.x, free.y, free.width, used.y - free.y));
+			}
+			if used.y + used.height < free.y + free.height {
+				split.push(PackRect::new(free.x, used.y + used.height, free.width, free.y + free.height - used.y - used.height));
+			}
+			false
+		});
+
+		self.free_rects.append(&mut split);
+		self.prune();
+	}
+
+	fn prune(&mut self) {
+		let mut i = 0;
+		while i < self.free_rects.len() {
+			let mut j = i + 1;
+			let mut removed = false;
+			while j < self.free_rects.len() {
+				if self.free_rects[j].contains(&self.free_rects[i]) {
+					self.free_rects.swap_remove(i);
+					removed = true;
+					break;
+				}
+				if self.free_rects[i].contains(&self.free_rects[j]) {
+					self.free_rects.swap_remove(j);
+				} else {
+					j += 1;
+				}
+			}
+			if !removed {
+				i += 1;
+			}
 		}
-		false
 	}
 
-	fn get_empty_node(&mut self, width: u32, height: u32) -> ImagePackNodeID {
-		while !self.has_space(width, height) {
-			if self.bitmap.width() <= self.bitmap.height() {
-				self.double_vert();
-			} else {
-				self.double_horz();
+	// Doubles the smallest side, returns false when the page already is at its maximum size
+	fn grow(&mut self, max_width: u32, max_height: u32) -> bool {
+		let width = self.bitmap.width();
+		let height = self.bitmap.height();
+
+		let (new_width, new_height) = if (width <= height || height >= max_height) && width < max_width {
+			((width * 2).min(max_width), height)
+		} else if height < max_height {
+			(width, (height * 2).min(max_height))
+		} else {
+			return false;
+		};
+
+		for free in &mut self.free_rects {
+			if free.x + free.width == width {
+				free.width = new_width - free.x;
+			}
+			if free.y + free.height == height {
+				free.height = new_height - free.y;
 			}
 		}
-		
-		let mut smallest_possible_node_id = 0;
-		for node in &self.nodes {
-			if node.has_space(width, height) {
-				break;
+		if new_width > width {
+			self.free_rects.push(PackRect::new(width, 0, new_width - width, new_height));
+		}
+		if new_height > height {
+			self.free_rects.push(PackRect::new(0, height, new_width, new_height - height));
+		}
+		self.prune();
+
+		self.bitmap.resize(new_width, new_height);
+		true
+	}
+
+	fn area(&self) -> u64 { self.bitmap.width() as u64 * self.bitmap.height() as u64 }
+}
+
+static EMPTY_IMAGE: Image = Image { width: 0, height: 0, buffer: std::vec::Vec::new() };
+
+pub struct ImagePack {
+	settings: ImagePackSettings,
+	pages: std::vec::Vec::<ImagePackPage>,
+	locations: std::collections::HashMap::<String, PackedImage>,
+}
+
+impl Default for ImagePack {
+	fn default() -> Self { ImagePack::new() }
+}
+
+impl ImagePack {
+	pub fn new() -> ImagePack {
+		ImagePack::with_settings(ImagePackSettings::new())
+	}
+
+	pub fn with_settings(settings: ImagePackSettings) -> ImagePack {
+		ImagePack {
+			settings,
+			pages: std::vec::Vec::new(),
+			locations: std::collections::HashMap::new(),
+		}
+	}
+
+	fn find_space(&mut self, width: u32, height: u32) -> (usize, PackRect, bool) {
+		let max_width = self.settings.max_width;
+		let max_height = self.settings.max_height;
+		let allow_rotation = self.settings.allow_rotation;
+
+		let mut best: Option<(usize, PackRect, bool, (u32, u32))> = None;
+		for (i, page) in self.pages.iter().enumerate() {
+			if let Some((rect, rotated, score)) = page.find_position(width, height, allow_rotation) {
+				if best.is_none_or(|(_, _, _, best_score)| score < best_score) {
+					best = Some((i, rect, rotated, score));
+				}
 			}
-			smallest_possible_node_id += 1;
+		}
+		if let Some((i, rect, rotated, _)) = best {
+			return (i, rect, rotated);
 		}
 
-		let mut id = 0;
-		for i in 0..self.nodes.len() {
-			let node = &self.nodes[i];
-			
-			if node.has_space(width, height) {
-				let other_node = &self.nodes[smallest_possible_node_id];
-				if node.width * node.height < other_node.width * other_node.height {
-					smallest_possible_node_id = id;
+		for (i, page) in self.pages.iter_mut().enumerate() {
+			while page.grow(max_width, max_height) {
+				if let Some((rect, rotated, _)) = page.find_position(width, height, allow_rotation) {
+					return (i, rect, rotated);
 				}
 			}
-			
-			id += 1;
 		}
 
-		let mut smallest_node = &mut self.nodes[smallest_possible_node_id];
+		let fits = width <= max_width && height <= max_height;
+		let fits_rotated = allow_rotation && height <= max_width && width <= max_height;
+		let (page_width, page_height) = if fits || !fits_rotated { (width, height) } else { (height, width) };
 
-		while smallest_node.has_space(width * 2, height) || smallest_node.has_space(width, height * 2) {
-			if smallest_node.has_space(width * 2, height) {
-				self.split_vert(smallest_possible_node_id as ImagePackNodeID);
-				smallest_possible_node_id = self.nodes.len()-1;
-				smallest_node = &mut self.nodes[smallest_possible_node_id];  
-			}
-			if smallest_node.has_space(width, height * 2) {
-				self.split_horz(smallest_possible_node_id as ImagePackNodeID);
-				smallest_possible_node_id = self.nodes.len()-1;
-				smallest_node = &mut self.nodes[smallest_possible_node_id];
-			}
+		if !fits && !fits_rotated {
+			warn_log!("Image of size {}x{} is larger than the maximum page size {}x{}, it gets a page of its own", width, height, max_width, max_height);
 		}
 
-		self.nodes[smallest_possible_node_id].available = false;
-		self.nodes[smallest_possible_node_id].image_width = width;
-		self.nodes[smallest_possible_node_id].image_height = height;
-		return smallest_possible_node_id as ImagePackNodeID;
+		let page_width = page_width.next_power_of_two().min(max_width).max(page_width);
+		let page_height = page_height.next_power_of_two().min(max_height).max(page_height);
+		self.pages.push(ImagePackPage::new(page_width, page_height));
+
+		let page = self.pages.len() - 1;
+		let (rect, rotated, _) = self.pages[page].find_position(width, height, allow_rotation).unwrap();
+		(page, rect, rotated)
 	}
 
 	pub fn add_image(&mut self, image_name: &str, image: Image) {
-		if self.nodes.len() == 0 {
-			self.nodes.push(ImagePackNode::new(0, 0, image.width(), image.height()));
-			self.bitmap.resize(image.width(), image.height());
+		let source_width = image.width();
+		let source_height = image.height();
+
+		let (image, offset_x, offset_y) = if self.settings.trim { image.trim_transparent() } else { (image, 0, 0) };
+
+		if image.width() == 0 || image.height() == 0 {
+			self.locations.insert(image_name.to_string(), PackedImage {
+				page: 0, x: 0, y: 0, width: 0, height: 0, rotated: false,
+				offset_x, offset_y, source_width, source_height,
+			});
+			return;
+		}
+
+		let extrude = self.settings.extrude;
+		let border = extrude * 2 + self.settings.padding;
+		let (page_index, rect, rotated) = self.find_space(image.width() + border, image.height() + border);
+
+		let image = if rotated { image.rotate_90() } else { image };
+		let x = rect.x + extrude;
+		let y = rect.y + extrude;
+
+		let page = &mut self.pages[page_index];
+		page.place(rect);
+		page.used_area += image.width() as u64 * image.height() as u64;
+
+		let mut options = CompositeOptions::new();
+		options.blend_mode = ImageBlendMode::Replace;
+		page.bitmap.composite(&image, (0, 0, image.width(), image.height()), x as i32, y as i32, &options);
+		page.bitmap.extrude_edges(x, y, image.width(), image.height(), extrude);
+
+		self.locations.insert(image_name.to_string(), PackedImage {
+			page: page_index,
+			x,
+			y,
+			width: image.width(),
+			height: image.height(),
+			rotated,
+			offset_x,
+			offset_y,
+			source_width,
+			source_height,
+		});
+	}
+
+	// Packs all images at once, sorted from largest to smallest which packs a lot tighter than adding them one by one
+	pub fn add_images(&mut self, images: std::vec::Vec::<(String, Image)>) {
+		let mut images = images;
+		images.sort_by_key(|(_, image)| std::cmp::Reverse((image.width().max(image.height()), image.width() * image.height())));
+		for (name, image) in images {
+			self.add_image(&name, image);
+		}
+	}
+
+	// Returns the image as it was added, undoing rotation and trimming
+	pub fn get_image(&self, image_name: &str) -> Image {
+		let packed = self.locations.get(image_name).unwrap();
+		let mut image = Image::from_color(packed.source_width, packed.source_height, 0x00_00_00_00);
+
+		if packed.width > 0 && packed.height > 0 {
+			let stored = self.pages[packed.page].bitmap.crop(packed.x, packed.y, packed.width, packed.height);
+			let stored = if packed.rotated { stored.rotate_270() } else { stored };
+
+			let mut options = CompositeOptions::new();
+			options.blend_mode = ImageBlendMode::Replace;
+			image.composite(&stored, (0, 0, stored.width(), stored.height()), packed.offset_x as i32, packed.offset_y as i32, &options);
 		}
 
-		let id = self.get_empty_node(image.width(), image.height());
-		let node = &self.nodes[id as usize];
-		
-		self.locations.insert(image_name.to_string(), id);
-		self.bitmap.draw(node.x, node.y, image);
+		image
+	}
+
+	pub fn get(&self, image_name: &str) -> Option<&PackedImage> { self.locations.get(image_name) }
+	pub fn images(&self) -> &std::collections::HashMap::<String, PackedImage> { &self.locations }
+
+	pub fn page_count(&self) -> usize { self.pages.len() }
+	pub fn get_page(&self, page: usize) -> &Image { &self.pages[page].bitmap }
+
+	pub fn get_bitmap(&self) -> &Image {
+		match self.pages.first() {
+			Some(page) => &page.bitmap,
+			None => &EMPTY_IMAGE,
+		}
 	}
 
-	pub fn get_image(&mut self, image_name: &str) -> Image {
-		let id = self.locations.get(image_name).unwrap();
-		let node = &self.nodes[(*id) as usize];
-		self.bitmap.crop(node.x, node.y, node.width, node.height)
+	// Writes the descriptor to path and every page as a png next to it
+	pub fn save(&self, path: &str) -> Result<(), AtlasError> {
+		let stem = std::path::Path::new(path).file_stem().and_then(|stem| stem.to_str()).unwrap_or("atlas");
+		let descriptor = AtlasDescriptor::from_image_pack(self, stem);
+
+		for (i, page) in descriptor.pages.iter().enumerate() {
+			let file = atlas::page_path(path, &page.file);
+			self.get_page(i).to_file(file.to_str().unwrap_or(&page.file))?;
+		}
+		descriptor.save(path)
 	}
 
-	pub fn get_bitmap(&self) -> &Image { &self.bitmap }
+	// Loads a pack written by save, settings are used for images added afterwards
+	pub fn from_file(path: &str, settings: ImagePackSettings) -> Result<ImagePack, AtlasError> {
+		let descriptor = AtlasDescriptor::from_file(path)?;
+		let mut pack = ImagePack::with_settings(settings);
+
+		for page in &descriptor.pages {
+			let mut file = std::fs::File::open(atlas::page_path(path, &page.file))?;
+			let bitmap = Image::from_reader(&mut file)?;
+			let mut page = ImagePackPage::new(bitmap.width(), bitmap.height());
+			page.bitmap = bitmap;
+			pack.pages.push(page);
+		}
+
+		let extrude = pack.settings.extrude;
+		let border = extrude * 2 + pack.settings.padding;
+		for region in &descriptor.regions {
+			let rotated = match region.rotation {
+				0 => false,
+				90 => true,
+				rotation => return Err(AtlasError::InvalidDescriptor(format!("region {} is rotated {} degrees, an image pack only stores 0 or 90", region.name, rotation))),
+			};
+
+			if region.width > 0 && region.height > 0 {
+				let page = pack.pages.get_mut(region.page).ok_or_else(|| AtlasError::InvalidDescriptor(format!("region {} is on missing page {}", region.name, region.page)))?;
+				page.place(PackRect::new(region.x.saturating_sub(extrude), region.y.saturating_sub(extrude), region.width + border, region.height + border));
+				page.used_area += region.width as u64 * region.height as u64;
+			}
+
+			pack.locations.insert(region.name.clone(), PackedImage {
+				page: region.page,
+				x: region.x,
+				y: region.y,
+				width: region.width,
+				height: region.height,
+				rotated,
+				offset_x: region.offset_x,
+				offset_y: region.offset_y,
+				source_width: region.source_width,
+				source_height: region.source_height,
+			});
+		}
+
+		Ok(pack)
+	}
+
+	// Fraction of the page area covered by images, between 0 and 1
+	pub fn efficiency(&self) -> f32 {
+		let used: u64 = self.pages.iter().map(|page| page.used_area).sum();
+		let total: u64 = self.pages.iter().map(|page| page.area()).sum();
+		if total == 0 { 0.0 } else { used as f32 / total as f32 }
+	}
+
+	pub fn page_efficiency(&self, page: usize) -> f32 {
+		let page = &self.pages[page];
+		if page.area() == 0 { 0.0 } else { page.used_area as f32 / page.area() as f32 }
+	}
 }
 
 pub struct TextureAtlas {
-	texture: Rc::<Texture>,
+	pages: std::vec::Vec::<Rc::<Texture>>,
 	textures: std::collections::HashMap::<String, TextureRegion>,
+	regions: std::collections::HashMap::<String, AtlasRegion>,
 }
 
 impl TextureAtlas {
 	pub fn new(texture: Texture) -> TextureAtlas {
 		TextureAtlas {
-			texture: std::rc::Rc::new(texture),
+			pages: vec![std::rc::Rc::new(texture)],
 			textures: std::collections::HashMap::new(),
+			regions: std::collections::HashMap::new(),
 		}
 	}
 
 	pub fn from_image_pack(image_pack: &ImagePack) -> TextureAtlas {
-		let texture = Texture::from_image(&image_pack.bitmap);
-		let mut textures = std::collections::HashMap::new();
+		let mut pages: std::vec::Vec::<Rc::<Texture>> = (0..image_pack.page_count()).map(|page| Texture::from_image(image_pack.get_page(page))).collect();
+		if pages.is_empty() {
+			pages.push(Texture::from_color(1, 1, 0x00_00_00_00));
+		}
+		TextureAtlas::from_descriptor(&AtlasDescriptor::from_image_pack(image_pack, "atlas"), pages)
+	}
 
-		for (texture_name, node_id) in image_pack.locations.iter() {
-			let node = &image_pack.nodes[*node_id as usize];
-			let texture_region = TextureRegion::new(node.x, node.y, node.image_width, node.image_height, &texture);
-			textures.insert(texture_name.to_string(), texture_region);
+	pub fn from_descriptor(descriptor: &AtlasDescriptor, pages: std::vec::Vec::<Rc::<Texture>>) -> TextureAtlas {
+		let mut textures = std::collections::HashMap::new();
+		let mut regions = std::collections::HashMap::new();
+
+		for region in &descriptor.regions {
+			match pages.get(region.page) {
+				Some(texture) => {
+					textures.insert(region.name.clone(), TextureRegion::new(region.x, region.y, region.width, region.height, texture));
+					regions.insert(region.name.clone(), region.clone());
+				},
+				None => {
+					error_log!("Atlas region {} is on page {} but there are only {} pages", region.name, region.page, pages.len());
+				}
+			}
 		}
 
 		TextureAtlas {
-			texture,
+			pages,
 			textures,
+			regions,
+		}
+	}
+
+	// Loads any descriptor AtlasDescriptor parses, page images are relative to the descriptor
+	pub fn from_file(path: &str) -> Result<TextureAtlas, AtlasError> {
+		let descriptor = AtlasDescriptor::from_file(path)?;
+
+		let mut pages = std::vec::Vec::new();
+		for page in &descriptor.pages {
+			let mut file = std::fs::File::open(atlas::page_path(path, &page.file))?;
+			pages.push(Texture::from_image(&Image::from_reader(&mut file)?));
 		}
+
+		Ok(TextureAtlas::from_descriptor(&descriptor, pages))
+	}
+
+	// Writes the descriptor to path and every page as a png next to it
+	pub fn save(&self, path: &str) -> Result<(), AtlasError> {
+		let stem = std::path::Path::new(path).file_stem().and_then(|stem| stem.to_str()).unwrap_or("atlas");
+		let descriptor = self.descriptor(stem);
+
+		for (page, texture) in descriptor.pages.iter().zip(&self.pages) {
+			let file = atlas::page_path(path, &page.file);
+			Image::from_texture(texture).to_file(file.to_str().unwrap_or(&page.file))?;
+		}
+		descriptor.save(path)
 	}
 
-	pub fn texture(self) -> std::rc::Rc::<Texture> { self.texture }
+	pub fn descriptor(&self, page_file_stem: &str) -> AtlasDescriptor {
+		let mut descriptor = AtlasDescriptor::new();
+		for (i, texture) in self.pages.iter().enumerate() {
+			descriptor.pages.push(AtlasPage {
+				file: format!("{}_{}.png", page_file_stem, i),
+				width: texture.width(),
+				height: texture.height(),
+			});
+		}
+
+		let mut names: std::vec::Vec::<&String> = self.regions.keys().collect();
+		names.sort();
+		descriptor.regions = names.iter().map(|name| self.regions[*name].clone()).collect();
+		descriptor
+	}
+
+	pub fn texture(self) -> std::rc::Rc::<Texture> { self.pages[0].clone() }
+
+	pub fn page(&self, page: usize) -> &std::rc::Rc::<Texture> { &self.pages[page] }
+	pub fn page_count(&self) -> usize { self.pages.len() }
 
 	pub fn get(&self, texture_name: &str) -> TextureRegion { self.textures.get(texture_name).unwrap().clone() }
 
+	// Pivot, trim offset and rotation of a region
+	pub fn region(&self, texture_name: &str) -> Option<&AtlasRegion> { self.regions.get(texture_name) }
+
 	pub fn set(&mut self, texture_name: &str, region: TextureRegion) {
-		if !std::rc::Rc::ptr_eq(&region.texture.upgrade().unwrap(), &self.texture) {
-			error_log!("You can't set a texture region inside a texture atlas which points to a different texture!");
-			return;
+		let page = match region.texture.upgrade() {
+			Some(texture) => self.pages.iter().position(|page| std::rc::Rc::ptr_eq(page, &texture)),
+			None => None,
+		};
+
+		match page {
+			Some(page) => {
+				// Keep the rotation, trim offset and pivot of a region that is replaced
+				let mut atlas_region = AtlasRegion::new(texture_name, page, region.x, region.y, region.width, region.height);
+				if let Some(old) = self.regions.get(texture_name) {
+					atlas_region.rotation = old.rotation;
+					atlas_region.offset_x = old.offset_x;
+					atlas_region.offset_y = old.offset_y;
+					atlas_region.source_width = old.source_width;
+					atlas_region.source_height = old.source_height;
+					atlas_region.pivot = old.pivot;
+				}
+				self.regions.insert(texture_name.to_string(), atlas_region);
+				self.textures.insert(texture_name.to_string(), region);
+			},
+			None => {
+				error_log!("You can't set a texture region inside a texture atlas which points to a different texture!");
+			}
 		}
-		self.textures.insert(texture_name.to_string(), region);
 	}
 
-	pub fn bind(&mut self) 	{ self.texture.bind(0); }
+	pub fn bind(&mut self) 	{ self.pages[0].bind(0); }
 	pub fn un_bind() 		{ Texture::un_bind();   }
-}
\ No newline at end of file
+}